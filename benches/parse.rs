@@ -0,0 +1,58 @@
+//! Lexing and parsing time for generated plans of growing size.
+//!
+//! ```bash
+//! cargo bench --bench parse
+//! ```
+//!
+//! Each plan is a valid `@ALN_UPDATE_SYSTEM` followed by `@STAGE` blocks of
+//! eight entries mixing strings, numbers, lists, inline objects and comments,
+//! so the numbers track what generated plans look like. `lex` collects the
+//! tokens into a `Vec`; `stream` only pulls them, as the parser does.
+
+use aln_system_update_orchestrator::aln::{lexer, parser};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::fmt::Write;
+
+/// A plan with about `entries` key-value entries.
+fn plan(entries: usize) -> String {
+    let mut src = std::fs::read_to_string("aln/system_update_integration_v1.7.aln").unwrap();
+    for i in 0..entries / 8 {
+        write!(
+            src,
+            "@STAGE s{i} after=s{prev} {{\n  \
+             # generated stage {i}\n  \
+             image: 'registry.local/app:{i}',\n  \
+             replicas: {replicas},\n  \
+             ratio: 0.{i},\n  \
+             enabled: true,\n  \
+             region: eu_west_1,\n  \
+             tags: ['canary', \"stage-{i}\"],\n  \
+             limits: {{ cpu: 2, mem: '4Gi' }},\n  \
+             notes: '''multi\nline'''\n\
+             }}\n",
+            prev = i.saturating_sub(1),
+            replicas = i % 7,
+        )
+        .unwrap();
+    }
+    src
+}
+
+fn bench(c: &mut Criterion) {
+    for (name, run) in [
+        ("lex", (|src: &str| lexer::lex(src).unwrap().len()) as fn(&str) -> usize),
+        ("stream", |src: &str| lexer::Lexer::new(src).count()),
+        ("parse", |src: &str| parser::parse_str(src, None).unwrap().items.len()),
+    ] {
+        let mut group = c.benchmark_group(name);
+        for entries in [1_000, 10_000, 50_000] {
+            let src = plan(entries);
+            group.throughput(Throughput::Bytes(src.len() as u64));
+            group.bench_with_input(BenchmarkId::from_parameter(entries), &src, |b, src| b.iter(|| run(src)));
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+//! Every token and error the lexer reports lies inside the input, and token
+//! text is the source slice it claims to be.
+
+#![no_main]
+
+use aln_system_update_orchestrator::aln::lexer::lex_recovering;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|src: &str| {
+    let (tokens, errors) = lex_recovering(src);
+    for t in &tokens {
+        assert_eq!(t.text, &src[t.span.start..t.span.end]);
+    }
+    for e in &errors {
+        let span = e.span();
+        assert!(span.start <= span.end && src.get(span.start..span.end).is_some(), "{span:?}");
+    }
+});
@@ -0,0 +1,28 @@
+//! The parser and plan loader never panic, and whatever parses prints to
+//! canonical text that parses back to the same output.
+//!
+//! This drives the same front end as `parse_file` from memory, so inputs
+//! cannot `@INCLUDE` arbitrary files from the fuzzing machine.
+
+#![no_main]
+
+use aln_system_update_orchestrator::aln::parser::parse_str;
+use aln_system_update_orchestrator::aln::printer::print;
+use aln_system_update_orchestrator::aln::AlnUpdatePlan;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|src: &str| {
+    match parse_str(src, None) {
+        Ok(ast) => {
+            let printed = print(&ast);
+            let again = parse_str(&printed, None).expect("printed form parses");
+            assert_eq!(print(&again), printed);
+        }
+        Err(e) => {
+            let _ = e.to_string();
+        }
+    }
+    if let Err(e) = AlnUpdatePlan::from_str(src, None) {
+        let _ = e.to_string();
+    }
+});
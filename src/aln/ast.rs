@@ -1,40 +1,359 @@
-use serde::{Deserialize, Serialize};
+//! ALN syntax tree.
+//!
+//! The serde implementations define the documented JSON/YAML/TOML mapping used
+//! by [`crate::aln::convert`]; spans are never serialized.
+
+use crate::aln::include::Origin;
+use crate::aln::span::Span;
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AlnFile {
     pub items: Vec<AlnItem>,
+    /// Comments after the last item.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trailing_comments: Vec<Comment>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A top-level item. Both kinds are written as blocks; see
+/// [`AlnItem::from_block`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
 pub enum AlnItem {
     Block(Block),
+    /// `@META { owner: ..., description: ... }`, describing the file rather
+    /// than configuring anything. Only recognised at the top level.
+    Meta(Block),
+}
+
+/// Name of the top-level metadata block.
+pub const META: &str = "META";
+
+impl AlnItem {
+    /// The item a top-level block stands for: [`AlnItem::Meta`] for `@META`,
+    /// a plain block otherwise.
+    pub fn from_block(block: Block) -> Self {
+        if block.name == META {
+            AlnItem::Meta(block)
+        } else {
+            AlnItem::Block(block)
+        }
+    }
+
+    pub fn block(&self) -> &Block {
+        match self {
+            AlnItem::Block(b) | AlnItem::Meta(b) => b,
+        }
+    }
+
+    pub fn block_mut(&mut self) -> &mut Block {
+        match self {
+            AlnItem::Block(b) | AlnItem::Meta(b) => b,
+        }
+    }
+
+    pub fn into_block(self) -> Block {
+        match self {
+            AlnItem::Block(b) | AlnItem::Meta(b) => b,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for AlnItem {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Block::deserialize(deserializer).map(AlnItem::from_block)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
+    #[serde(rename = "block")]
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub args: Option<BlockArgs>,
+    #[serde(default)]
     pub body: Vec<BlockEntry>,
+    /// Comments directly above the `@NAME` line.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub comments: Vec<Comment>,
+    /// Comments after the last entry, before the closing `}`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub closing_comments: Vec<Comment>,
+    /// From `@` through the closing `}`.
+    #[serde(skip)]
+    pub span: Span,
+    #[serde(skip)]
+    pub name_span: Span,
+    /// Set on blocks spliced in by `@INCLUDE`; blocks nested inside them share
+    /// their origin.
+    #[serde(skip)]
+    pub origin: Option<Origin>,
 }
 
+/// Arguments between the block name and `{`, e.g.
+/// `@STEP deploy after=build timeout=30 {`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct BlockArgs {
-    pub raw: String,
+    pub items: Vec<BlockArg>,
+    #[serde(skip)]
+    pub span: Span,
 }
 
+/// A positional (`deploy`) or named (`timeout=30`) block argument.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum BlockEntry {
-    KeyValue { key: String, value: Value },
-    NestedBlock(Block),
-    List(Vec<Value>),
+pub struct BlockArg {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub value: Value,
+    #[serde(default, skip_serializing_if = "ValueTrivia::is_empty")]
+    pub trivia: ValueTrivia,
+    #[serde(skip)]
+    pub span: Span,
+}
+
+impl BlockArgs {
+    pub fn positional(&self) -> impl Iterator<Item = &Value> {
+        self.items.iter().filter(|a| a.name.is_none()).map(|a| &a.value)
+    }
+
+    pub fn named(&self, name: &str) -> Option<&Value> {
+        self.items
+            .iter()
+            .find(|a| a.name.as_deref() == Some(name))
+            .map(|a| &a.value)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
+pub enum BlockEntry {
+    KeyValue {
+        key: String,
+        value: Value,
+        /// Comments directly above the key.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        comments: Vec<Comment>,
+        /// Comments inside the value.
+        #[serde(default, skip_serializing_if = "ValueTrivia::is_empty")]
+        trivia: ValueTrivia,
+        #[serde(skip)]
+        key_span: Span,
+        #[serde(skip)]
+        value_span: Span,
+    },
+    NestedBlock(Block),
+    List(BareList),
+}
+
+impl BlockEntry {
+    /// Source span of the whole entry; `None` for entries built in code.
+    pub fn span(&self) -> Option<Span> {
+        let span = match self {
+            BlockEntry::KeyValue { key_span, value_span, .. } => key_span.to(*value_span),
+            BlockEntry::NestedBlock(b) => b.span,
+            BlockEntry::List(_) => return None,
+        };
+        (!span.is_empty()).then_some(span)
+    }
+}
+
+/// An array standing alone in a block body, as in `@PORTS { [80, 443] }`.
+/// Serializes as the plain array, or as `{ "list": [...], "trivia": {...} }`
+/// when it holds comments.
+#[derive(Debug, Clone, Default)]
+pub struct BareList {
+    pub values: Vec<Value>,
+    /// Comments above the list (at `[]`) and inside it.
+    pub trivia: ValueTrivia,
+}
+
+impl Serialize for BareList {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.trivia.is_empty() {
+            return self.values.serialize(serializer);
+        }
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("list", &self.values)?;
+        map.serialize_entry("trivia", &self.trivia)?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for BareList {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Bare(Vec<Value>),
+            Full {
+                list: Vec<Value>,
+                #[serde(default)]
+                trivia: ValueTrivia,
+            },
+        }
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Bare(values) => BareList { values, trivia: ValueTrivia::default() },
+            Repr::Full { list, trivia } => BareList { values: list, trivia },
+        })
+    }
+}
+
+/// Source details of a value that are not part of the value itself, so that
+/// rewriting tools can keep them and interpolation can tell raw strings apart. Each is located by a path of element or
+/// field indices leading from the value, `[]` being the value itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValueTrivia {
+    /// Comments above an array element or object field. An index one past the
+    /// last element stands for the closing bracket.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub comments: Vec<InnerComment>,
+    /// Strings written raw (`r'...'`) that contain `${`. Interpolation leaves
+    /// them as they are, so `r'echo ${HOME}'` stays shell text.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub raw: Vec<Vec<usize>>,
+}
+
+impl ValueTrivia {
+    pub fn is_empty(&self) -> bool {
+        self.comments.is_empty() && self.raw.is_empty()
+    }
+
+    /// Whether the string at `at` was written raw.
+    pub fn is_raw(&self, at: &[usize]) -> bool {
+        self.raw.iter().any(|r| r == at)
+    }
+}
+
+/// A comment inside a value, see [`ValueTrivia`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InnerComment {
+    pub at: Vec<usize>,
+    #[serde(rename = "text")]
+    pub comment: Comment,
+}
+
+/// A value. Serializes as the plain JSON/YAML/TOML equivalent; objects keep
+/// their key order in both directions.
+#[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Str(String),
     Bool(bool),
-    Number(f64),
+    Integer(i64),
+    Float(f64),
     Array(Vec<Value>),
     Object(Vec<(String, Value)>),
 }
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Str(s) => serializer.serialize_str(s),
+            Value::Bool(b) => serializer.serialize_bool(*b),
+            Value::Integer(n) => serializer.serialize_i64(*n),
+            Value::Float(n) => serializer.serialize_f64(*n),
+            Value::Array(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            Value::Object(fields) => {
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (k, v) in fields {
+                    map.serialize_entry(k, v)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a string, boolean, number, array or map")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Integer(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+        i64::try_from(v)
+            .map(Value::Integer)
+            .map_err(|_| E::custom(format!("integer {} is out of range", v)))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::Str(v.to_string()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Value, E> {
+        Ok(Value::Str(v))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut fields = Vec::new();
+        while let Some((k, v)) = map.next_entry()? {
+            fields.push((k, v));
+        }
+        Ok(Value::Object(fields))
+    }
+}
+
+/// A source comment kept verbatim, delimiters included (`# ...`, `// ...` or
+/// `/* ... */`), so rewriting tools can emit it back unchanged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+}
+
+impl From<String> for Comment {
+    fn from(text: String) -> Self {
+        Self { text, span: Span::default() }
+    }
+}
+
+impl From<Comment> for String {
+    fn from(c: Comment) -> Self {
+        c.text
+    }
+}
+
+impl Comment {
+    pub fn is_block(&self) -> bool {
+        self.text.starts_with("/*")
+    }
+}
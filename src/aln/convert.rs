@@ -0,0 +1,154 @@
+//! Lossless conversion between ALN and JSON, YAML or TOML.
+//!
+//! All three formats share one document shape, defined by the serde
+//! implementations in [`crate::aln::ast`]. Every ALN construct has exactly one
+//! representation, so converting to another format and back reproduces the
+//! same plan (only source positions and formatting are lost):
+//!
+//! ```json
+//! {
+//!   "items": [
+//!     {
+//!       "block": "STEP",
+//!       "args": [{ "value": "deploy" }, { "name": "timeout", "value": 30 }],
+//!       "comments": ["# runs last"],
+//!       "body": [
+//!         { "key": "image", "value": "web:1.2" },
+//!         { "key": "limits", "value": { "cpu": 2, "ratio": 0.5 } },
+//!         { "block": "HOOK", "body": [] }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! - A block is an object with `block` (its name) and `body`, plus optional
+//!   `args`, `comments` (above the block) and `closing_comments` (before `}`).
+//! - A body entry is `{ "key", "value", "comments"? }`, a nested block or a
+//!   bare array.
+//! - Block and argument names must be identifiers; keys may be any string.
+//!   Positional block arguments cannot be objects; named ones can.
+//! - Block arguments are `{ "value" }` when positional, `{ "name", "value" }`
+//!   when named.
+//! - Values map to native strings, booleans, arrays and maps. Integers and
+//!   floats stay distinct (`1` vs `1.0`) and map keys keep their order.
+//! - Comments are kept verbatim with their `#`, `//` or `/* */` delimiters;
+//!   comments after the last block go in a top-level `trailing_comments`.
+//!   Comments inside an entry's or argument's value go in its `trivia`, each
+//!   located by element indices, e.g. `{ "at": [1], "text": "# keep" }` above
+//!   the second element. A bare array with comments becomes
+//!   `{ "list": [...], "trivia": {...} }`. `trivia.raw` lists the raw strings
+//!   that contain `${`, which interpolation skips.
+
+use crate::aln::ast::{AlnFile, Block, BlockArg, Value};
+use crate::aln::parser::{parse_str, LoadAlnError};
+use crate::aln::printer::{self, is_identifier};
+use crate::aln::visit::{self, BlockPath, Visit};
+use std::path::Path;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Aln,
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Format {
+    /// Guess the format from a file extension.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        path.as_ref().extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl FromStr for Format {
+    type Err = ConvertError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "aln" => Ok(Format::Aln),
+            "json" => Ok(Format::Json),
+            "yaml" | "yml" => Ok(Format::Yaml),
+            "toml" => Ok(Format::Toml),
+            _ => Err(ConvertError::UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConvertError {
+    #[error("unknown format `{0}` (expected aln, json, yaml or toml)")]
+    UnknownFormat(String),
+    #[error(transparent)]
+    Aln(#[from] LoadAlnError),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("YAML error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("TOML error: {0}")]
+    TomlDe(#[from] toml::de::Error),
+    #[error("TOML error: {0}")]
+    TomlSer(#[from] toml::ser::Error),
+    #[error("cannot write {0} as ALN: it is not an identifier")]
+    NotAnIdentifier(String),
+    #[error("cannot write argument `{0}` of @{1} as ALN: a positional argument cannot be an object")]
+    ObjectArgument(String, String),
+}
+
+/// Write `file` in the given format.
+pub fn to_string(file: &AlnFile, format: Format) -> Result<String, ConvertError> {
+    Ok(match format {
+        Format::Aln => {
+            let mut unwritable = Unwritable(None);
+            unwritable.visit_file(file);
+            if let Some(e) = unwritable.0 {
+                return Err(e);
+            }
+            printer::print(file)
+        }
+        Format::Json => serde_json::to_string_pretty(file)? + "\n",
+        Format::Yaml => serde_yaml::to_string(file)?,
+        Format::Toml => toml::to_string_pretty(file)?,
+    })
+}
+
+/// Read a document in the given format. `name` is used in ALN diagnostics.
+pub fn from_str(src: &str, format: Format, name: &str) -> Result<AlnFile, ConvertError> {
+    Ok(match format {
+        Format::Aln => parse_str(src, Some(name))?,
+        Format::Json => serde_json::from_str(src)?,
+        Format::Yaml => serde_yaml::from_str(src)?,
+        Format::Toml => toml::from_str(src)?,
+    })
+}
+
+/// First thing in the AST that ALN has no syntax for: a block or argument
+/// name that is not an identifier, e.g. a block named `my block` read from
+/// JSON, or a positional object argument, which would read back as the body.
+struct Unwritable(Option<ConvertError>);
+
+impl<'ast> Visit<'ast> for Unwritable {
+    fn visit_block(&mut self, block: &'ast Block, path: &mut BlockPath) {
+        if self.0.is_none() && !is_identifier(&block.name) {
+            self.0 = Some(ConvertError::NotAnIdentifier(format!("block name `{}`", block.name)));
+        }
+        if self.0.is_none() {
+            let mut objects = block.args.iter().flat_map(|a| &a.items).filter(|a| a.name.is_none());
+            if let Some(arg) = objects.find(|a| matches!(a.value, Value::Object(_))) {
+                self.0 = Some(ConvertError::ObjectArgument(printer::print_value(&arg.value), block.name.clone()));
+            }
+        }
+        visit::walk_block(self, block, path);
+    }
+
+    fn visit_arg(&mut self, arg: &'ast BlockArg, _path: &mut BlockPath) {
+        match &arg.name {
+            Some(name) if self.0.is_none() && !is_identifier(name) => {
+                self.0 = Some(ConvertError::NotAnIdentifier(format!("argument name `{name}`")));
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! serde `Deserializer` for ALN.
+//!
+//! A file deserializes as a map from top-level block names to blocks, and a
+//! block as a map of its keys plus its nested blocks (keyed by block name):
+//!
+//! ```
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Plan {
+//!     #[serde(rename = "DEPLOY")]
+//!     deploy: Deploy,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! struct Deploy {
+//!     replicas: u32,
+//!     #[serde(rename = "STEP")]
+//!     steps: Vec<Step>,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! struct Step {
+//!     #[serde(rename = "@args")]
+//!     args: Vec<String>,
+//!     timeout: Option<u64>,
+//! }
+//!
+//! let plan: Plan = aln_system_update_orchestrator::aln::from_str(
+//!     "@DEPLOY { replicas: 3, @STEP build {}, @STEP ship { timeout: 30 } }",
+//! )
+//! .unwrap();
+//! assert_eq!(plan.deploy.steps[1].args, ["ship"]);
+//! ```
+//!
+//! - A block name used more than once becomes a sequence; a block used once
+//!   can fill either a struct or a one-element `Vec`.
+//! - Positional block arguments are exposed under the key `@args`, named ones
+//!   as `@name`.
+//! - Inline objects deserialize like nested blocks, so both spellings of a
+//!   section work.
+
+use crate::aln::ast::{AlnFile, AlnItem, Block, BlockEntry, Value};
+use crate::aln::parser::{parse_str, LoadAlnError};
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Load(#[from] LoadAlnError),
+    /// The document does not fit the target type. `path` locates the value,
+    /// e.g. `ALN_UPDATE_SYSTEM.INTEROP.maintain_func`.
+    #[error("{}{message}", if path.is_empty() { String::new() } else { format!("{}: ", path) })]
+    Data { path: String, message: String },
+}
+
+impl Error {
+    /// Prefix the error's path with `segment` (a key, or `[i]` for an index).
+    pub(crate) fn within(self, segment: &str) -> Self {
+        match self {
+            Error::Data { path, message } => {
+                let path = match (path.is_empty(), path.starts_with('[')) {
+                    (true, _) => segment.to_string(),
+                    (false, true) => format!("{}{}", segment, path),
+                    (false, false) => format!("{}.{}", segment, path),
+                };
+                Error::Data { path, message }
+            }
+            other => other,
+        }
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Data { path: String::new(), message: msg.to_string() }
+    }
+}
+
+impl serde::ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        <Error as de::Error>::custom(msg)
+    }
+}
+
+/// Parse ALN source and deserialize it into `T`.
+pub fn from_str<T: DeserializeOwned>(src: &str) -> Result<T, Error> {
+    let file = parse_str(src, None)?;
+    from_file(&file)
+}
+
+/// Deserialize a parsed file: a map of its top-level blocks.
+pub fn from_file<T: DeserializeOwned>(file: &AlnFile) -> Result<T, Error> {
+    from_value(&file_to_value(file))
+}
+
+/// Deserialize one block: a map of its keys and nested blocks.
+pub fn from_block<T: DeserializeOwned>(block: &Block) -> Result<T, Error> {
+    from_value(&block_to_value(block))
+}
+
+pub fn from_value<T: DeserializeOwned>(value: &Value) -> Result<T, Error> {
+    T::deserialize(ValueDeserializer(value))
+}
+
+/// The map view of a file described in the module docs.
+pub fn file_to_value(file: &AlnFile) -> Value {
+    Value::Object(group_blocks(file.items.iter().map(AlnItem::block)))
+}
+
+/// The map view of a block described in the module docs.
+pub fn block_to_value(block: &Block) -> Value {
+    let mut fields = Vec::new();
+    if let Some(args) = &block.args {
+        let positional: Vec<Value> = args.positional().cloned().collect();
+        if !positional.is_empty() {
+            fields.push(("@args".to_string(), Value::Array(positional)));
+        }
+        for arg in &args.items {
+            if let Some(name) = &arg.name {
+                fields.push((format!("@{}", name), arg.value.clone()));
+            }
+        }
+    }
+    let mut nested = Vec::new();
+    for entry in &block.body {
+        match entry {
+            BlockEntry::KeyValue { key, value, .. } => fields.push((key.clone(), value.clone())),
+            BlockEntry::NestedBlock(b) => nested.push(b),
+            // Bare lists have no key to be addressed by.
+            BlockEntry::List(_) => {}
+        }
+    }
+    fields.extend(group_blocks(nested.into_iter()));
+    Value::Object(fields)
+}
+
+/// One entry per block name, in first-appearance order; names used more than
+/// once map to an array of blocks.
+fn group_blocks<'a>(blocks: impl Iterator<Item = &'a Block>) -> Vec<(String, Value)> {
+    let mut grouped: Vec<(String, Vec<Value>)> = Vec::new();
+    for b in blocks {
+        let value = block_to_value(b);
+        match grouped.iter_mut().find(|(name, _)| *name == b.name) {
+            Some((_, values)) => values.push(value),
+            None => grouped.push((b.name.clone(), vec![value])),
+        }
+    }
+    grouped
+        .into_iter()
+        .map(|(name, mut values)| {
+            let value = if values.len() == 1 { values.remove(0) } else { Value::Array(values) };
+            (name, value)
+        })
+        .collect()
+}
+
+struct ValueDeserializer<'a>(&'a Value);
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Str(s) => visitor.visit_borrowed_str(s),
+            Value::Bool(b) => visitor.visit_bool(*b),
+            Value::Integer(n) => visitor.visit_i64(*n),
+            Value::Float(n) => visitor.visit_f64(*n),
+            Value::Array(items) => visitor.visit_seq(Seq { iter: items.iter().enumerate() }),
+            Value::Object(fields) => visitor.visit_map(Map { iter: fields.iter(), key: None }),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            // A block that appears once still fills a `Vec` of blocks.
+            Value::Object(_) => visitor.visit_seq(Seq { iter: std::slice::from_ref(self.0).iter().enumerate() }),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Str(s) => visitor.visit_enum(s.as_str().into_deserializer()),
+            Value::Object(fields) if fields.len() == 1 => {
+                let (variant, value) = &fields[0];
+                visitor
+                    .visit_enum(Enum { variant, value })
+                    .map_err(|e| e.within(variant))
+            }
+            other => Err(de::Error::invalid_type(unexpected(other), &"a string or single-key map")),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct tuple tuple_struct map struct identifier
+    }
+}
+
+fn unexpected(value: &Value) -> de::Unexpected<'_> {
+    match value {
+        Value::Str(s) => de::Unexpected::Str(s),
+        Value::Bool(b) => de::Unexpected::Bool(*b),
+        Value::Integer(n) => de::Unexpected::Signed(*n),
+        Value::Float(n) => de::Unexpected::Float(*n),
+        Value::Array(_) => de::Unexpected::Seq,
+        Value::Object(_) => de::Unexpected::Map,
+    }
+}
+
+struct Seq<'a> {
+    iter: std::iter::Enumerate<std::slice::Iter<'a, Value>>,
+}
+
+impl<'de> SeqAccess<'de> for Seq<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+        match self.iter.next() {
+            Some((i, v)) => seed
+                .deserialize(ValueDeserializer(v))
+                .map(Some)
+                .map_err(|e| e.within(&format!("[{}]", i))),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct Map<'a> {
+    iter: std::slice::Iter<'a, (String, Value)>,
+    key: Option<&'a (String, Value)>,
+}
+
+impl<'de> MapAccess<'de> for Map<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        match self.iter.next() {
+            Some(entry) => {
+                self.key = Some(entry);
+                seed.deserialize(de::value::BorrowedStrDeserializer::<Error>::new(&entry.0)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let (key, value) = self.key.take().expect("next_value called before next_key");
+        seed.deserialize(ValueDeserializer(value)).map_err(|e| e.within(key))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct Enum<'a> {
+    variant: &'a str,
+    value: &'a Value,
+}
+
+impl<'de> EnumAccess<'de> for Enum<'de> {
+    type Error = Error;
+    type Variant = ValueDeserializer<'de>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self::Variant), Error> {
+        let variant = seed.deserialize(de::value::BorrowedStrDeserializer::<Error>::new(self.variant))?;
+        Ok((variant, ValueDeserializer(self.value)))
+    }
+}
+
+impl<'de> VariantAccess<'de> for ValueDeserializer<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Err(de::Error::invalid_type(unexpected(self.0), &"a unit variant"))
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
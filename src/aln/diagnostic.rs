@@ -0,0 +1,134 @@
+use crate::aln::span::{SourceFile, Span};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => f.write_str("error"),
+            Severity::Warning => f.write_str("warning"),
+        }
+    }
+}
+
+/// A message about a region of ALN source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(message, span)
+        }
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Render as a caret-underlined snippet:
+    ///
+    /// ```text
+    /// error: expected `:` after key `mode`, found string
+    ///  --> plan.aln:3:10
+    ///   |
+    /// 3 |     mode 'x'
+    ///   |          ^^^
+    /// ```
+    pub fn render(&self, file: &SourceFile) -> String {
+        let pos = file.line_col(self.span.start);
+        let line = file.line_text(pos.line);
+        let gutter = pos.line.to_string().len();
+        let pad = " ".repeat(gutter);
+
+        // Underline to the end of the span, clamped to the first line.
+        let end = file.line_col(self.span.end);
+        let width = if end.line == pos.line {
+            end.col.saturating_sub(pos.col).max(1)
+        } else {
+            line.chars().count().saturating_sub(pos.col - 1).max(1)
+        };
+
+        let mut out = format!(
+            "{}: {}\n{pad}--> {}:{}\n{pad} |\n{} | {}\n{pad} | {}{}",
+            self.severity,
+            self.message,
+            file.name(),
+            pos,
+            pos.line,
+            line,
+            " ".repeat(pos.col - 1),
+            "^".repeat(width),
+        );
+        for note in &self.notes {
+            out.push_str(&format!("\n{pad} = note: {note}"));
+        }
+        out
+    }
+}
+
+/// Diagnostics bundled with the source they refer to, so they can be shown
+/// without the caller holding on to the file.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub file: SourceFile,
+    /// Files pulled in with `@INCLUDE`, each at its own base offset.
+    pub sources: Vec<SourceFile>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    pub fn new(file: SourceFile, diagnostics: Vec<Diagnostic>) -> Self {
+        Self { file, sources: Vec::new(), diagnostics }
+    }
+
+    /// Offset just past the last file loaded, root or included.
+    pub fn end(&self) -> usize {
+        self.sources.iter().map(SourceFile::end).fold(self.file.end(), usize::max)
+    }
+
+    /// The file a span starting at `offset` points into.
+    pub fn source(&self, offset: usize) -> &SourceFile {
+        self.sources
+            .iter()
+            .find(|f| f.contains(offset))
+            .unwrap_or(&self.file)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.severity == Severity::Error)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, d) in self.diagnostics.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n\n")?;
+            }
+            f.write_str(&d.render(self.source(d.span.start)))?;
+        }
+        Ok(())
+    }
+}
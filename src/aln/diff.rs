@@ -0,0 +1,375 @@
+//! Structural diff of two plans, for reviewing what a change means rather
+//! than how its text moved.
+//!
+//! Formatting, comments and the order of keys, blocks and inline object
+//! fields are ignored. Blocks are matched as in [`crate::aln::merge`]: by name
+//! and positional arguments, then by name alone, in which case the change of
+//! arguments is reported as `@args`. Named block arguments are compared like
+//! keys, as `@name`.
+//!
+//! Lists of scalars report the items added and removed, and a `reordered`
+//! change when the items in both lists are in a different order. Lists
+//! holding arrays or objects are compared element by element.
+//!
+//! Paths follow merge provenance, e.g. `ALN_UPDATE_SYSTEM.SEPARATE(components).renderers`;
+//! a block repeated under the same name and arguments gets an index, as in
+//! `PIPE.STAGE[1]`.
+//!
+//! ```
+//! use aln_system_update_orchestrator::aln::diff::diff_files;
+//! use aln_system_update_orchestrator::aln::parser::parse_str;
+//!
+//! let old = parse_str("@A { mode: 'x', features: ['a', 'b'] }", None).unwrap();
+//! let new = parse_str("@A {\n  features: ['b', 'a', 'c'],\n  mode: 'y'\n}", None).unwrap();
+//! assert_eq!(
+//!     diff_files(&old, &new).to_string(),
+//!     "A.features: added 'c'\n\
+//!      A.features: reordered from ['a', 'b'] to ['b', 'a']\n\
+//!      A.mode: changed from 'x' to 'y'\n"
+//! );
+//! ```
+
+use crate::aln::ast::{AlnFile, AlnItem, Block, BlockArg, BlockArgs, BlockEntry, Value, ValueTrivia, META};
+use crate::aln::merge::{same_block, segment, NAME};
+use crate::aln::model::{AlnPlanSet, AlnUpdatePlan};
+use crate::aln::span::Span;
+use crate::aln::printer::print_value;
+use crate::aln::ser;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Every change between two plans. Within a block, key changes come before
+/// nested blocks, each in the order of the new plan with removals last.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Diff {
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Change {
+    pub path: String,
+    #[serde(flatten)]
+    pub kind: ChangeKind,
+}
+
+/// Serializes with a `change` tag, e.g.
+/// `{"path": "A.features", "change": "items_added", "items": ["c"]}`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum ChangeKind {
+    BlockAdded,
+    BlockRemoved,
+    Added { value: Value },
+    Removed { value: Value },
+    Changed { old: Value, new: Value },
+    /// Items added to a list.
+    ItemsAdded { items: Vec<Value> },
+    /// Items removed from a list.
+    ItemsRemoved { items: Vec<Value> },
+    /// The items a list kept, old order and new order.
+    Reordered { old: Vec<Value>, new: Vec<Value> },
+}
+
+impl ChangeKind {
+    /// The change in words, with every printed value or list passed through
+    /// `value`, e.g. to make it a markdown code span.
+    fn describe(&self, value: impl Fn(String) -> String) -> String {
+        let one = |v: &Value| value(print_value(v));
+        let items = |items: &[Value]| items.iter().map(one).collect::<Vec<_>>().join(", ");
+        let list = |items: &[Value]| value(format!("[{}]", items.iter().map(print_value).collect::<Vec<_>>().join(", ")));
+        match self {
+            ChangeKind::BlockAdded => "block added".to_string(),
+            ChangeKind::BlockRemoved => "block removed".to_string(),
+            ChangeKind::Added { value } => format!("added as {}", one(value)),
+            ChangeKind::Removed { value } => format!("removed, was {}", one(value)),
+            ChangeKind::Changed { old, new } => format!("changed from {} to {}", one(old), one(new)),
+            ChangeKind::ItemsAdded { items: added } => format!("added {}", items(added)),
+            ChangeKind::ItemsRemoved { items: removed } => format!("removed {}", items(removed)),
+            ChangeKind::Reordered { old, new } => format!("reordered from {} to {}", list(old), list(new)),
+        }
+    }
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.describe(|v| v))
+    }
+}
+
+/// `s` as a code span in a markdown table cell: fenced with one backtick more
+/// than the longest run inside it, and with `|` escaped so it cannot end the
+/// cell.
+fn code(s: &str) -> String {
+    let longest = s.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest + 1);
+    let pad = if s.starts_with('`') || s.ends_with('`') { " " } else { "" };
+    format!("{fence}{pad}{}{pad}{fence}", s.replace('|', "\\|"))
+}
+
+/// One line per change: `ALN_UPDATE_SYSTEM.RENDER_IN_FRAME.mode: changed from 'a' to 'b'`.
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}: {}", change.path, change.kind)?;
+        }
+        Ok(())
+    }
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// A table for review comments, or a line saying there are no changes.
+    pub fn to_markdown(&self) -> String {
+        if self.is_empty() {
+            return "No changes.\n".to_string();
+        }
+        let mut out = String::from("| Path | Change |\n| --- | --- |\n");
+        for change in &self.changes {
+            let description = change.kind.describe(|v| code(&v));
+            out.push_str(&format!("| {} | {} |\n", code(&change.path), description));
+        }
+        out
+    }
+
+    fn push(&mut self, path: String, kind: ChangeKind) {
+        self.changes.push(Change { path, kind });
+    }
+}
+
+/// Diff two syntax trees, as parsed; includes and interpolation are whatever
+/// the caller resolved.
+pub fn diff_files(old: &AlnFile, new: &AlnFile) -> Diff {
+    fn blocks(file: &AlnFile) -> Vec<&Block> {
+        file.items.iter().map(AlnItem::block).collect()
+    }
+
+    let mut diff = Diff::default();
+    block_list(&mut diff, "", &blocks(old), &blocks(new));
+    diff
+}
+
+/// Diff two loaded plans. Defaults are filled in and the version is always a
+/// key, so only differences in meaning remain. Paths are those of the plan
+/// written out by [`AlnUpdatePlan::to_file`], without block arguments.
+pub fn diff_plans(old: &AlnUpdatePlan, new: &AlnUpdatePlan) -> Diff {
+    diff_files(&old.to_file(), &new.to_file())
+}
+
+/// Diff two loaded plan sets like [`diff_plans`]. Systems are matched by
+/// name, as in `ALN_UPDATE_SYSTEM(name=web).version`, and the metadata is
+/// compared as `META`.
+pub fn diff_plan_sets(old: &AlnPlanSet, new: &AlnPlanSet) -> Diff {
+    fn file(set: &AlnPlanSet) -> AlnFile {
+        let mut items = Vec::new();
+        if let Some(meta) = &set.meta {
+            let file = ser::to_file(&BTreeMap::from([(META, meta)])).expect("metadata always serializes to ALN");
+            items.extend(file.items);
+        }
+        for system in &set.systems {
+            let mut file = system.plan.to_file();
+            if let (Some(name), Some(root)) = (&system.name, file.items.first_mut()) {
+                let arg = BlockArg {
+                    name: Some(NAME.to_string()),
+                    value: Value::Str(name.clone()),
+                    trivia: ValueTrivia::default(),
+                    span: Span::default(),
+                };
+                root.block_mut().args = Some(BlockArgs { items: vec![arg], span: Span::default() });
+            }
+            items.extend(file.items);
+        }
+        AlnFile { items, trailing_comments: Vec::new() }
+    }
+    diff_files(&file(old), &file(new))
+}
+
+
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+fn body(diff: &mut Diff, path: &str, old: &[BlockEntry], new: &[BlockEntry]) {
+    fn blocks(entries: &[BlockEntry]) -> Vec<&Block> {
+        entries
+            .iter()
+            .filter_map(|e| match e {
+                BlockEntry::NestedBlock(b) => Some(b),
+                _ => None,
+            })
+            .collect()
+    }
+
+    let keys = |entries: &[BlockEntry]| -> Vec<(String, Value)> {
+        entries
+            .iter()
+            .filter_map(|e| match e {
+                BlockEntry::KeyValue { key, value, .. } => Some((key.clone(), value.clone())),
+                _ => None,
+            })
+            .collect()
+    };
+    let bare = |entries: &[BlockEntry]| -> Vec<Value> {
+        entries
+            .iter()
+            .filter_map(|e| match e {
+                BlockEntry::List(list) => Some(list.values.clone()),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    };
+
+    fields(diff, path, &keys(old), &keys(new));
+    block_list(diff, path, &blocks(old), &blocks(new));
+    items(diff, path, &bare(old), &bare(new));
+}
+
+/// Pair up the blocks of one body: same name and positional arguments first,
+/// then the leftovers by name, in order.
+fn block_list(diff: &mut Diff, path: &str, old: &[&Block], new: &[&Block]) {
+    let mut pairs: Vec<Option<usize>> = vec![None; new.len()];
+    let mut used = vec![false; old.len()];
+    for matches in [same_block as fn(&Block, &Block) -> bool, |a, b| a.name == b.name] {
+        for (n, block) in new.iter().enumerate() {
+            if pairs[n].is_some() {
+                continue;
+            }
+            if let Some(o) = (0..old.len()).find(|&o| !used[o] && matches(old[o], block)) {
+                used[o] = true;
+                pairs[n] = Some(o);
+            }
+        }
+    }
+
+    let new_paths = block_paths(path, new);
+    let old_paths = block_paths(path, old);
+    for (n, block) in new.iter().enumerate() {
+        match pairs[n] {
+            Some(o) => diff_block(diff, &new_paths[n], old[o], block),
+            None => diff.push(new_paths[n].clone(), ChangeKind::BlockAdded),
+        }
+    }
+    for (o, _) in old.iter().enumerate().filter(|&(o, _)| !used[o]) {
+        diff.push(old_paths[o].clone(), ChangeKind::BlockRemoved);
+    }
+}
+
+/// Paths of sibling blocks, indexed where a segment repeats.
+fn block_paths(path: &str, blocks: &[&Block]) -> Vec<String> {
+    let segments: Vec<String> = blocks.iter().map(|b| segment(b)).collect();
+    segments
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            if segments.iter().filter(|other| *other == s).count() > 1 {
+                let nth = segments[..i].iter().filter(|other| *other == s).count();
+                join(path, &format!("{}[{}]", s, nth))
+            } else {
+                join(path, s)
+            }
+        })
+        .collect()
+}
+
+fn diff_block(diff: &mut Diff, path: &str, old: &Block, new: &Block) {
+    let positional = |b: &Block| -> Vec<Value> { b.args.iter().flat_map(|a| a.positional()).cloned().collect() };
+    let (old_args, new_args) = (positional(old), positional(new));
+    if old_args != new_args {
+        diff.push(
+            join(path, "@args"),
+            ChangeKind::Changed { old: Value::Array(old_args), new: Value::Array(new_args) },
+        );
+    }
+
+    let named = |b: &Block| -> Vec<(String, Value)> {
+        b.args
+            .iter()
+            .flat_map(|a| &a.items)
+            .filter_map(|a| Some((format!("@{}", a.name.as_ref()?), a.value.clone())))
+            .collect()
+    };
+    fields(diff, path, &named(old), &named(new));
+    body(diff, path, &old.body, &new.body);
+}
+
+/// Keys of a block or fields of an object, matched by name.
+fn fields(diff: &mut Diff, path: &str, old: &[(String, Value)], new: &[(String, Value)]) {
+    for (key, value) in new {
+        let key_path = join(path, key);
+        match old.iter().find(|(k, _)| k == key) {
+            Some((_, old_value)) => diff_value(diff, &key_path, old_value, value),
+            None => diff.push(key_path, ChangeKind::Added { value: value.clone() }),
+        }
+    }
+    for (key, value) in old {
+        if !new.iter().any(|(k, _)| k == key) {
+            diff.push(join(path, key), ChangeKind::Removed { value: value.clone() });
+        }
+    }
+}
+
+fn diff_value(diff: &mut Diff, path: &str, old: &Value, new: &Value) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => fields(diff, path, old, new),
+        (Value::Array(old), Value::Array(new)) => {
+            let scalars = |items: &[Value]| !items.iter().any(|v| matches!(v, Value::Array(_) | Value::Object(_)));
+            if scalars(old) && scalars(new) {
+                items(diff, path, old, new);
+                return;
+            }
+            for (i, (old, new)) in old.iter().zip(new).enumerate() {
+                diff_value(diff, &format!("{}[{}]", path, i), old, new);
+            }
+            if new.len() > old.len() {
+                diff.push(path.to_string(), ChangeKind::ItemsAdded { items: new[old.len()..].to_vec() });
+            }
+            if old.len() > new.len() {
+                diff.push(path.to_string(), ChangeKind::ItemsRemoved { items: old[new.len()..].to_vec() });
+            }
+        }
+        (old, new) if old != new => {
+            diff.push(path.to_string(), ChangeKind::Changed { old: old.clone(), new: new.clone() })
+        }
+        _ => {}
+    }
+}
+
+/// Compare two lists of scalars. Each item counts once per occurrence; the
+/// items found in both lists are then checked for a change of order.
+fn items(diff: &mut Diff, path: &str, old: &[Value], new: &[Value]) {
+    let mut unmatched: Vec<Option<&Value>> = old.iter().map(Some).collect();
+    let mut added = Vec::new();
+    // Indices into `old` of the items kept, in their new order.
+    let mut kept = Vec::new();
+    for item in new {
+        match unmatched.iter().position(|v| *v == Some(item)) {
+            Some(i) => {
+                unmatched[i] = None;
+                kept.push(i);
+            }
+            None => added.push(item.clone()),
+        }
+    }
+    let removed: Vec<Value> = unmatched.into_iter().flatten().cloned().collect();
+    if !added.is_empty() {
+        diff.push(path.to_string(), ChangeKind::ItemsAdded { items: added });
+    }
+    if !removed.is_empty() {
+        diff.push(path.to_string(), ChangeKind::ItemsRemoved { items: removed });
+    }
+    if kept.windows(2).any(|w| w[0] > w[1]) {
+        let new = kept.iter().map(|&i| old[i].clone()).collect();
+        kept.sort_unstable();
+        let old = kept.iter().map(|&i| old[i].clone()).collect();
+        diff.push(path.to_string(), ChangeKind::Reordered { old, new });
+    }
+}
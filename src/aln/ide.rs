@@ -0,0 +1,294 @@
+//! Editor support for `.aln` files: diagnostics, hover, completion,
+//! go-to-definition and formatting over one open document.
+//!
+//! Everything here works on byte offsets into the document text; the
+//! `aln-lsp` binary translates to and from LSP positions. Block and key docs
+//! come from [`AlnUpdatePlan::schema`], so hover and completion know the plan
+//! sections (`@SEPARATE`, `@INTEROP`, ...) wherever they appear, including in
+//! files meant to be included into a plan. A top-level `@META` block uses
+//! [`AlnMeta::schema`].
+
+use crate::aln::ast::{AlnFile, AlnItem, Block, BlockEntry, Value};
+use crate::aln::diagnostic::Diagnostic;
+use crate::aln::include::{self, INCLUDE};
+use crate::aln::model::{AlnMeta, AlnPlanSet, AlnUpdatePlan};
+use crate::aln::parser::{parse_source, LoadAlnError, Parsed};
+use crate::aln::printer;
+use crate::aln::schema::{BlockSchema, Field, Strictness};
+use crate::aln::span::{SourceFile, Span};
+use std::path::{Path, PathBuf};
+
+/// One parsed document and what is known about it.
+pub struct Analysis {
+    file: SourceFile,
+    /// The document as written, with `@INCLUDE` directives in place.
+    ast: AlnFile,
+    syntax_ok: bool,
+    diagnostics: Vec<Diagnostic>,
+    schema: BlockSchema,
+    meta: BlockSchema,
+}
+
+/// Hover text for the name under the cursor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hover {
+    pub span: Span,
+    /// Markdown.
+    pub contents: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Key,
+    Block,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    /// Short type or summary shown next to the label.
+    pub detail: String,
+    pub documentation: String,
+    pub insert_text: String,
+}
+
+impl Analysis {
+    /// Analyse `text`, the contents of the document at `path`. Includes are
+    /// resolved relative to `path` and plans are checked leniently, so unknown
+    /// keys show up as warnings.
+    pub fn new(path: &str, text: &str) -> Self {
+        let Parsed { ast, report } = parse_source(SourceFile::new(path, text));
+        let file = report.file.clone();
+        let schema = AlnUpdatePlan::schema();
+        let syntax_ok = !report.has_errors();
+
+        let diagnostics = if !syntax_ok {
+            report.diagnostics
+        } else {
+            let resolved = include::resolve(Parsed { ast: ast.clone(), report });
+            let is_plan = resolved.ast.items.iter().any(|item| match item {
+                AlnItem::Block(b) => b.name == schema.name,
+                AlnItem::Meta(_) => false,
+            });
+            let report = if is_plan {
+                match AlnPlanSet::from_parsed(resolved, Strictness::Lenient) {
+                    Ok((_, report)) => report,
+                    Err(LoadAlnError::Syntax(r) | LoadAlnError::Resolve(r) | LoadAlnError::Schema(r)) => r,
+                    Err(LoadAlnError::Io(_) | LoadAlnError::NoInput) => unreachable!("nothing is read after parsing"),
+                }
+            } else {
+                resolved.report
+            };
+            // Problems inside included files are shown when those are opened.
+            report.diagnostics.into_iter().filter(|d| d.span.start <= file.end()).collect()
+        };
+        Self { file, ast, syntax_ok, diagnostics, schema, meta: AlnMeta::schema() }
+    }
+
+    pub fn file(&self) -> &SourceFile {
+        &self.file
+    }
+
+    pub fn ast(&self) -> &AlnFile {
+        &self.ast
+    }
+
+    /// Syntax errors, or once the document parses, include, interpolation and
+    /// schema problems.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Docs for the block name or key at `offset`.
+    pub fn hover(&self, offset: usize) -> Option<Hover> {
+        let chain = self.enclosing(offset);
+        for (depth, block) in chain.iter().enumerate() {
+            // `@` is part of what the user points at.
+            let name = Span::new(block.name_span.start.saturating_sub(1), block.name_span.end);
+            if contains(name, offset) {
+                let schema = self.block_schema(&chain[..=depth])?;
+                return Some(Hover { span: name, contents: block_doc(schema) });
+            }
+        }
+        let block = chain.last()?;
+        let schema = self.block_schema(&chain)?;
+        block.body.iter().find_map(|entry| match entry {
+            BlockEntry::KeyValue { key, key_span, .. } if contains(*key_span, offset) => {
+                let field = schema.get_field(key)?;
+                Some(Hover { span: *key_span, contents: field_doc(field) })
+            }
+            _ => None,
+        })
+    }
+
+    /// Keys and blocks that may be added at `offset`: those the enclosing
+    /// block's schema accepts and does not have yet, or at the top level the
+    /// plan root and `@META` while they are missing.
+    pub fn completions(&self, offset: usize) -> Vec<Completion> {
+        let src = self.file.src();
+        let offset = offset.min(src.len());
+        let word_start = src[..offset]
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .map_or(0, |i| i + 1);
+        let before = src[..word_start].trim_end_matches([' ', '\t']);
+        if before.ends_with(':') || before.ends_with('=') {
+            return Vec::new();
+        }
+        let after_at = src[..word_start].ends_with('@');
+        let block = |schema: &BlockSchema| Completion {
+            label: format!("@{}", schema.name),
+            kind: CompletionKind::Block,
+            detail: "block".to_string(),
+            documentation: schema.doc.to_string(),
+            insert_text: format!("{}{} {{\n}}", if after_at { "" } else { "@" }, schema.name),
+        };
+
+        let chain = self.enclosing(offset);
+        let Some(parent) = chain.last() else {
+            let missing = |schema: &BlockSchema| !self.ast.items.iter().any(|item| item.block().name == schema.name);
+            return [&self.schema, &self.meta].into_iter().filter(|s| missing(s)).map(block).collect();
+        };
+        let Some(schema) = self.block_schema(&chain) else {
+            return Vec::new();
+        };
+
+        let mut out = Vec::new();
+        if !after_at {
+            for field in &schema.fields {
+                let present = parent
+                    .body
+                    .iter()
+                    .any(|e| matches!(e, BlockEntry::KeyValue { key, .. } if key == field.key));
+                if !present {
+                    out.push(Completion {
+                        label: field.key.to_string(),
+                        kind: CompletionKind::Key,
+                        detail: field.ty.to_string(),
+                        documentation: field.doc.to_string(),
+                        insert_text: format!("{}: ", field.key),
+                    });
+                }
+            }
+        }
+        for (nested, _) in &schema.blocks {
+            let present = parent
+                .body
+                .iter()
+                .any(|e| matches!(e, BlockEntry::NestedBlock(b) if b.name == nested.name));
+            if !present {
+                out.push(block(nested));
+            }
+        }
+        out
+    }
+
+    /// The file an `@INCLUDE` path or a policy file reference at `offset`
+    /// points to, if it exists. Paths are relative to this document.
+    pub fn definition(&self, offset: usize) -> Option<PathBuf> {
+        let dir = Path::new(self.file.name()).parent().unwrap_or(Path::new(""));
+        let target = self.enclosing(offset).into_iter().rev().find_map(|block| {
+            if block.name == INCLUDE {
+                let arg = block.args.as_ref()?.items.first()?;
+                return match &arg.value {
+                    Value::Str(s) if contains(arg.span, offset) => Some(s),
+                    _ => None,
+                };
+            }
+            block.body.iter().find_map(|entry| match entry {
+                BlockEntry::KeyValue { key, value: Value::Str(s), value_span, .. }
+                    if contains(*value_span, offset) && is_file_reference(key, s) =>
+                {
+                    Some(s)
+                }
+                _ => None,
+            })
+        })?;
+        let path = dir.join(target);
+        path.is_file().then_some(path)
+    }
+
+    /// The document in canonical style, or `None` while it has syntax errors.
+    pub fn format(&self) -> Option<String> {
+        self.syntax_ok.then(|| printer::print(&self.ast))
+    }
+
+    /// Blocks whose span contains `offset`, outermost first. `@INCLUDE`
+    /// directives count as blocks.
+    fn enclosing(&self, offset: usize) -> Vec<&Block> {
+        let mut chain = Vec::new();
+        let mut blocks: Vec<&Block> = self
+            .ast
+            .items
+            .iter()
+            .map(AlnItem::block)
+            .collect();
+        while let Some(block) = blocks.into_iter().find(|b| contains(b.span, offset)) {
+            chain.push(block);
+            blocks = block
+                .body
+                .iter()
+                .filter_map(|e| match e {
+                    BlockEntry::NestedBlock(b) => Some(b),
+                    _ => None,
+                })
+                .collect();
+        }
+        chain
+    }
+
+    /// Schema for the innermost of `chain`. A chain that does not start at the
+    /// plan root or `@META`, as in an included file, starts wherever its
+    /// outermost block appears in the plan schema.
+    fn block_schema(&self, chain: &[&Block]) -> Option<&BlockSchema> {
+        let (first, rest) = chain.split_first()?;
+        let mut schema = if first.name == self.schema.name {
+            &self.schema
+        } else if first.name == self.meta.name {
+            &self.meta
+        } else {
+            self.schema.find_block(&first.name)?
+        };
+        for block in rest {
+            schema = schema.get_block(&block.name)?;
+        }
+        Some(schema)
+    }
+}
+
+/// Spans are half-open, but a cursor just past a name is still on it.
+fn contains(span: Span, offset: usize) -> bool {
+    span.start <= offset && offset <= span.end
+}
+
+fn is_file_reference(key: &str, value: &str) -> bool {
+    key == "policy" || value.ends_with(".rego") || value.ends_with(".aln")
+}
+
+fn block_doc(schema: &BlockSchema) -> String {
+    let mut out = format!("```aln\n@{} {{ ... }}\n```\n{}", schema.name, schema.doc);
+    if !schema.fields.is_empty() {
+        out.push_str("\n\nKeys:");
+        for field in &schema.fields {
+            out.push_str(&format!("\n- `{}`: {}", field.key, field.ty));
+        }
+    }
+    if !schema.blocks.is_empty() {
+        out.push_str("\n\nBlocks:");
+        for (nested, required) in &schema.blocks {
+            let required = if *required { " (required)" } else { "" };
+            out.push_str(&format!("\n- `@{}`{}", nested.name, required));
+        }
+    }
+    out
+}
+
+fn field_doc(field: &Field) -> String {
+    let mut out = format!("```aln\n{}: {}\n```\n{}", field.key, field.ty, field.doc);
+    if let Some(default) = &field.default {
+        out.push_str(&format!("\n\nDefault: `{}`", default));
+    } else if field.required {
+        out.push_str("\n\nRequired.");
+    }
+    out
+}
@@ -0,0 +1,184 @@
+//! `@INCLUDE` resolution.
+//!
+//! `@INCLUDE 'shared/rego.aln'` is replaced by the blocks of the named file,
+//! resolved relative to the including file. It may appear at the top level or
+//! inside a block, and included files may include others. Spliced blocks
+//! record their [`Origin`]. Included sources are placed after the root file in
+//! offset space (see [`SourceFile::base`]), so a [`Report`] renders
+//! diagnostics from any of them against the right file.
+//!
+//! [`Report`]: crate::aln::diagnostic::Report
+
+use crate::aln::ast::{AlnFile, AlnItem, Block, BlockArg, BlockEntry, Value};
+use crate::aln::diagnostic::Diagnostic;
+use crate::aln::parser::{parse_source, Parsed};
+use crate::aln::span::{LineCol, SourceFile};
+use crate::aln::visit::{self, BlockPath, Visit};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the include directive, `@INCLUDE 'path'`.
+pub const INCLUDE: &str = "INCLUDE";
+
+/// Where a spliced block was read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    pub file: String,
+    /// `@INCLUDE` sites that led to the file, innermost first; the last one is
+    /// in the root file.
+    pub included_from: Vec<IncludeSite>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncludeSite {
+    pub file: String,
+    pub pos: LineCol,
+}
+
+impl fmt::Display for IncludeSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.pos)
+    }
+}
+
+/// Replace every `@INCLUDE` in `parsed` with the blocks it names. Problems in
+/// included files are added to the report with a note per include site.
+pub(crate) fn resolve(parsed: Parsed) -> Parsed {
+    let Parsed { ast, mut report } = parsed;
+    let root = Path::new(report.file.name());
+    let mut r = Resolver {
+        next_base: report.file.end() + 1,
+        stack: vec![(canonical(root), report.file.name().to_string())],
+        files: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    let mut ast = ast;
+    ast.items = r.items(ast.items, &report.file, &[]);
+
+    report.sources.extend(r.files);
+    report.diagnostics.extend(r.diagnostics);
+    report.diagnostics.sort_by_key(|d| d.span.start);
+    Parsed { ast, report }
+}
+
+/// An error for every `@INCLUDE` left in `file`, for sources that were not
+/// loaded from a file and so have nothing to resolve against.
+pub(crate) fn unresolved(file: &AlnFile) -> Vec<Diagnostic> {
+    struct Unresolved(Vec<Diagnostic>);
+
+    impl Visit<'_> for Unresolved {
+        fn visit_block(&mut self, block: &Block, path: &mut BlockPath) {
+            if block.name == INCLUDE {
+                self.0.push(Diagnostic::error("@INCLUDE is only supported in plans loaded from files", block.span));
+            }
+            visit::walk_block(self, block, path);
+        }
+    }
+
+    let mut v = Unresolved(Vec::new());
+    v.visit_file(file);
+    v.0
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+struct Resolver {
+    /// Base offset for the next file loaded.
+    next_base: usize,
+    /// Files currently being resolved, outermost first, for cycle detection.
+    stack: Vec<(PathBuf, String)>,
+    files: Vec<SourceFile>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Resolver {
+    fn items(&mut self, items: Vec<AlnItem>, file: &SourceFile, chain: &[IncludeSite]) -> Vec<AlnItem> {
+        let mut out = Vec::new();
+        for item in items {
+            out.extend(self.block(item.into_block(), file, chain).into_iter().map(AlnItem::from_block));
+        }
+        out
+    }
+
+    /// `block` with includes in its body resolved, or, for a directive, the
+    /// blocks it includes.
+    fn block(&mut self, mut block: Block, file: &SourceFile, chain: &[IncludeSite]) -> Vec<Block> {
+        if block.name == INCLUDE {
+            return self.include(&block, file, chain);
+        }
+        for entry in std::mem::take(&mut block.body) {
+            match entry {
+                BlockEntry::NestedBlock(b) => {
+                    let nested = self.block(b, file, chain);
+                    block.body.extend(nested.into_iter().map(BlockEntry::NestedBlock));
+                }
+                other => block.body.push(other),
+            }
+        }
+        vec![block]
+    }
+
+    fn include(&mut self, directive: &Block, file: &SourceFile, chain: &[IncludeSite]) -> Vec<Block> {
+        let target = match directive.args.as_ref().map(|a| a.items.as_slice()) {
+            Some([BlockArg { name: None, value: Value::Str(s), .. }]) => s,
+            _ => {
+                let d = Diagnostic::error("@INCLUDE expects a single file path", directive.span);
+                self.push(d, chain);
+                return Vec::new();
+            }
+        };
+        let path = Path::new(file.name()).parent().unwrap_or(Path::new("")).join(target);
+        let name = path.to_string_lossy().into_owned();
+        let key = canonical(&path);
+
+        if let Some(i) = self.stack.iter().position(|(p, _)| *p == key) {
+            let cycle: Vec<&str> = self.stack[i..].iter().map(|(_, n)| n.as_str()).collect();
+            let d = Diagnostic::error(
+                format!("include cycle: {} -> {}", cycle.join(" -> "), name),
+                directive.span,
+            );
+            self.push(d, chain);
+            return Vec::new();
+        }
+        let src = match fs::read_to_string(&path) {
+            Ok(src) => src,
+            Err(e) => {
+                let span = directive.args.as_ref().map_or(directive.span, |a| a.span);
+                self.push(Diagnostic::error(format!("cannot include `{}`: {}", name, e), span), chain);
+                return Vec::new();
+            }
+        };
+
+        let included = SourceFile::new(name.clone(), src).with_base(self.next_base);
+        self.next_base = included.end() + 1;
+        let Parsed { ast, report } = parse_source(included);
+
+        let site = IncludeSite { file: file.name().to_string(), pos: file.line_col(directive.span.start) };
+        let chain: Vec<IncludeSite> = std::iter::once(site).chain(chain.iter().cloned()).collect();
+        for d in report.diagnostics {
+            self.push(d, &chain);
+        }
+
+        self.stack.push((key, name.clone()));
+        let mut blocks = Vec::new();
+        for item in ast.items {
+            blocks.extend(self.block(item.into_block(), &report.file, &chain));
+        }
+        self.stack.pop();
+        self.files.push(report.file);
+
+        // Blocks from deeper includes keep their own origin.
+        for b in &mut blocks {
+            b.origin.get_or_insert_with(|| Origin { file: name.clone(), included_from: chain.clone() });
+        }
+        blocks
+    }
+
+    fn push(&mut self, mut d: Diagnostic, chain: &[IncludeSite]) {
+        d.notes.extend(chain.iter().map(|site| format!("included from {}", site)));
+        self.diagnostics.push(d);
+    }
+}
@@ -0,0 +1,337 @@
+//! `${...}` interpolation in string values.
+//!
+//! Runs on a parsed file before it is mapped into a model:
+//!
+//! - `${env.NAME}`: an environment variable.
+//! - `${vars.name}`: a key of the top-level `@VARS { ... }` block.
+//! - `${INTEROP.enable_lan}`, `${mode}`: another key, named by the blocks
+//!   leading to it. The first name is looked up in the enclosing block, then
+//!   its parent, and so on up to the top level, so siblings and ancestors can be
+//!   referred to by their short names.
+//!
+//! A string that is exactly one reference takes the referenced value with its
+//! type (`maintain_func: '${vars.maintain}'` can be a bool); references
+//! embedded in text must be scalars and are spliced in as text. `$${` is a
+//! literal `${`. Raw strings (`r'echo ${HOME}'`) are never interpolated.
+//! Referenced values are interpolated first; undefined names and reference
+//! cycles are errors.
+
+use crate::aln::ast::{AlnFile, Block, BlockEntry, Value, ValueTrivia};
+use crate::aln::diagnostic::Diagnostic;
+use crate::aln::span::Span;
+use std::collections::HashMap;
+
+/// Name of the top-level block holding `${vars.*}` values.
+pub const VARS: &str = "VARS";
+
+/// Interpolate `file` in place using the process environment.
+pub fn interpolate_env(file: &mut AlnFile) -> Vec<Diagnostic> {
+    interpolate(file, |name| std::env::var(name).ok())
+}
+
+/// Interpolate `file` in place, looking up `${env.*}` with `env`. Returns one
+/// diagnostic per value that could not be resolved; those values are left as
+/// written.
+pub fn interpolate(file: &mut AlnFile, env: impl Fn(&str) -> Option<String>) -> Vec<Diagnostic> {
+    let snapshot = file.clone();
+    let mut r = Resolver { file: &snapshot, env: &env, cache: HashMap::new(), stack: Vec::new() };
+    let mut diagnostics = Vec::new();
+    for (i, item) in file.items.iter_mut().enumerate() {
+        r.walk(item.block_mut(), vec![i], &mut diagnostics);
+    }
+    diagnostics
+}
+
+/// Address of a block or entry: item index, then body entry indices.
+type Path = Vec<usize>;
+
+#[derive(Debug, Clone)]
+struct Unresolved {
+    message: String,
+    /// The key whose own value holds the bad reference, if not the value
+    /// being resolved right now. Errors are reported there, once.
+    at: Option<Path>,
+}
+
+impl Unresolved {
+    fn new(message: impl Into<String>) -> Self {
+        Self { message: message.into(), at: None }
+    }
+}
+
+struct Resolver<'a> {
+    /// The file as written; lookups never see half-interpolated values.
+    file: &'a AlnFile,
+    env: &'a dyn Fn(&str) -> Option<String>,
+    cache: HashMap<Path, Result<Value, Unresolved>>,
+    /// Keys being resolved, outermost first.
+    stack: Vec<Path>,
+}
+
+impl<'a> Resolver<'a> {
+    fn walk(&mut self, block: &mut Block, path: Path, diagnostics: &mut Vec<Diagnostic>) {
+        let mut report = |result: Result<Value, Unresolved>, here: &Path, span: Span, target: &mut Value| {
+            match result {
+                Ok(v) => *target = v,
+                Err(e) if e.at.is_none() || e.at.as_ref() == Some(here) => {
+                    diagnostics.push(Diagnostic::error(e.message, span))
+                }
+                // Reported at the key it came from.
+                Err(_) => {}
+            }
+        };
+
+        if let Some(args) = &mut block.args {
+            for arg in &mut args.items {
+                let result = self.value(&arg.value, &arg.trivia, &mut Vec::new(), &path);
+                report(result, &path, arg.span, &mut arg.value);
+            }
+        }
+        let mut nested = Vec::new();
+        for (i, entry) in block.body.iter_mut().enumerate() {
+            let mut here = path.clone();
+            here.push(i);
+            match entry {
+                BlockEntry::KeyValue { value, value_span, .. } => {
+                    let result = self.resolve(&here);
+                    report(result, &here, *value_span, value);
+                }
+                BlockEntry::List(list) => {
+                    for (i, v) in list.values.iter_mut().enumerate() {
+                        let result = self.value(v, &list.trivia, &mut vec![i], &path);
+                        report(result, &path, block.span, v);
+                    }
+                }
+                BlockEntry::NestedBlock(b) => nested.push((b, here)),
+            }
+        }
+        for (b, here) in nested {
+            self.walk(b, here, diagnostics);
+        }
+    }
+
+    /// The interpolated value of the key entry at `key`.
+    fn resolve(&mut self, key: &Path) -> Result<Value, Unresolved> {
+        if let Some(cached) = self.cache.get(key) {
+            return cached.clone();
+        }
+        if let Some(i) = self.stack.iter().position(|k| k == key) {
+            let cycle: Vec<String> = self.stack[i..].iter().chain([key]).map(|k| self.describe(k)).collect();
+            return Err(Unresolved::new(format!("reference cycle: {}", cycle.join(" -> "))));
+        }
+        let (entry, block) = key.split_last().expect("key paths are never empty");
+        let (written, trivia) = match self.entry(block, *entry) {
+            Some(BlockEntry::KeyValue { value, trivia, .. }) => (value, trivia),
+            _ => unreachable!("key paths point at key entries"),
+        };
+
+        self.stack.push(key.clone());
+        let result = self.value(written, trivia, &mut Vec::new(), entry_block(key)).map_err(|mut e| {
+            e.at.get_or_insert_with(|| key.clone());
+            e
+        });
+        self.stack.pop();
+        self.cache.insert(key.clone(), result.clone());
+        result
+    }
+
+    /// `at` is the position of `value` in the entry or argument value that
+    /// `trivia` belongs to, for telling raw strings apart.
+    fn value(
+        &mut self,
+        value: &Value,
+        trivia: &ValueTrivia,
+        at: &mut Vec<usize>,
+        scope: &[usize],
+    ) -> Result<Value, Unresolved> {
+        if let Value::Str(s) = value {
+            if s.contains('$') && !trivia.is_raw(at) {
+                return self.string(s, scope);
+            }
+        }
+        let mut child = |r: &mut Self, i: usize, v: &Value| {
+            at.push(i);
+            let v = r.value(v, trivia, at, scope);
+            at.pop();
+            v
+        };
+        match value {
+            Value::Array(items) => {
+                let items = items.iter().enumerate().map(|(i, v)| child(self, i, v)).collect::<Result<_, _>>()?;
+                Ok(Value::Array(items))
+            }
+            Value::Object(fields) => {
+                let fields = fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (k, v))| Ok((k.clone(), child(self, i, v)?)))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::Object(fields))
+            }
+            other => Ok(other.clone()),
+        }
+    }
+
+    fn string(&mut self, s: &str, scope: &[usize]) -> Result<Value, Unresolved> {
+        let parts = template(s)?;
+        if let [Part::Ref(name)] = parts.as_slice() {
+            return self.reference(name, scope);
+        }
+        let mut out = String::new();
+        for part in parts {
+            match part {
+                Part::Text(text) => out.push_str(&text),
+                Part::Ref(name) => match self.reference(&name, scope)? {
+                    Value::Str(s) => out.push_str(&s),
+                    Value::Bool(b) => out.push_str(&b.to_string()),
+                    Value::Integer(n) => out.push_str(&n.to_string()),
+                    Value::Float(n) => out.push_str(&n.to_string()),
+                    _ => {
+                        return Err(Unresolved::new(format!(
+                            "`{}` is not a scalar and cannot be embedded in a string",
+                            name
+                        )))
+                    }
+                },
+            }
+        }
+        Ok(Value::Str(out))
+    }
+
+    fn reference(&mut self, name: &str, scope: &[usize]) -> Result<Value, Unresolved> {
+        let segments: Vec<&str> = name.split('.').collect();
+        match segments.as_slice() {
+            ["env", var] => (self.env)(var)
+                .map(Value::Str)
+                .ok_or_else(|| Unresolved::new(format!("undefined environment variable `{}`", var))),
+            ["vars", var] => {
+                let key = self
+                    .top_level(VARS)
+                    .and_then(|vars| self.find_key(&vars, var))
+                    .ok_or_else(|| Unresolved::new(format!("undefined variable `{}`", var)))?;
+                self.resolve(&key)
+            }
+            ["env" | "vars", ..] => Err(Unresolved::new(format!("`{}` expects a single name after the dot", name))),
+            [blocks @ .., key] => {
+                let key = self
+                    .lookup(scope, blocks, key)
+                    .ok_or_else(|| Unresolved::new(format!("`{}` does not name a key", name)))?;
+                self.resolve(&key)
+            }
+            [] => unreachable!("split yields at least one segment"),
+        }
+    }
+
+    /// Find `blocks...key` starting from `scope` and moving outwards.
+    fn lookup(&self, scope: &[usize], blocks: &[&str], key: &str) -> Option<Path> {
+        (0..=scope.len()).rev().find_map(|depth| {
+            let mut path = scope[..depth].to_vec();
+            for name in blocks {
+                path = self.child_block(&path, name)?;
+            }
+            if path.is_empty() {
+                return None;
+            }
+            self.find_key(&path, key)
+        })
+    }
+
+    fn top_level(&self, name: &str) -> Option<Path> {
+        self.child_block(&[], name)
+    }
+
+    fn child_block(&self, parent: &[usize], name: &str) -> Option<Path> {
+        let index = if parent.is_empty() {
+            self.file.items.iter().position(|item| item.block().name == name)?
+        } else {
+            self.block(parent)?
+                .body
+                .iter()
+                .position(|e| matches!(e, BlockEntry::NestedBlock(b) if b.name == name))?
+        };
+        let mut path = parent.to_vec();
+        path.push(index);
+        Some(path)
+    }
+
+    fn find_key(&self, block: &[usize], key: &str) -> Option<Path> {
+        let index = self
+            .block(block)?
+            .body
+            .iter()
+            .position(|e| matches!(e, BlockEntry::KeyValue { key: k, .. } if k == key))?;
+        let mut path = block.to_vec();
+        path.push(index);
+        Some(path)
+    }
+
+    fn block(&self, path: &[usize]) -> Option<&'a Block> {
+        let (first, rest) = path.split_first()?;
+        let mut block = self.file.items.get(*first)?.block();
+        for &i in rest {
+            match block.body.get(i)? {
+                BlockEntry::NestedBlock(b) => block = b,
+                _ => return None,
+            }
+        }
+        Some(block)
+    }
+
+    fn entry(&self, block: &[usize], index: usize) -> Option<&'a BlockEntry> {
+        self.block(block)?.body.get(index)
+    }
+
+    /// `INTEROP.enable_lan`-style name of a key, for cycle messages.
+    fn describe(&self, key: &Path) -> String {
+        let mut names: Vec<&str> = (1..key.len()).filter_map(|n| self.block(&key[..n]).map(|b| b.name.as_str())).collect();
+        if let Some(BlockEntry::KeyValue { key: k, .. }) = self.entry(entry_block(key), key[key.len() - 1]) {
+            names.push(k);
+        }
+        names.join(".")
+    }
+}
+
+fn entry_block(key: &[usize]) -> &[usize] {
+    &key[..key.len() - 1]
+}
+
+enum Part {
+    Text(String),
+    Ref(String),
+}
+
+/// Split a string into literal text and `${...}` references.
+fn template(s: &str) -> Result<Vec<Part>, Unresolved> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut rest = s;
+    while let Some(i) = rest.find('$') {
+        text.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if let Some(after) = rest.strip_prefix("$${") {
+            text.push_str("${");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .ok_or_else(|| Unresolved::new("unterminated `${` in string"))?;
+            let name = after[..end].trim();
+            if name.is_empty() {
+                return Err(Unresolved::new("empty `${}` reference"));
+            }
+            if !text.is_empty() {
+                parts.push(Part::Text(std::mem::take(&mut text)));
+            }
+            parts.push(Part::Ref(name.to_string()));
+            rest = &after[end + 1..];
+        } else {
+            text.push('$');
+            rest = &rest[1..];
+        }
+    }
+    text.push_str(rest);
+    if !text.is_empty() || parts.is_empty() {
+        parts.push(Part::Text(text));
+    }
+    Ok(parts)
+}
@@ -1,7 +1,12 @@
+use crate::aln::ast::Comment;
+use crate::aln::diagnostic::Diagnostic;
+use crate::aln::span::Span;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use thiserror::Error;
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum TokenKind {
+pub enum TokenKind<'a> {
     At,
     LBrace,
     RBrace,
@@ -9,100 +14,512 @@ pub enum TokenKind {
     RBracket,
     Colon,
     Comma,
-    Identifier(String),
-    StringLiteral(String),
-    NumberLiteral(String),
+    Eq,
+    /// Interned; the name itself is the token's [`Token::text`].
+    Identifier(Symbol),
+    /// Decoded contents. Borrowed from the source unless escapes had to be
+    /// decoded.
+    StringLiteral(Cow<'a, str>),
+    IntegerLiteral(i64),
+    FloatLiteral(f64),
     BoolLiteral(bool),
+    /// End of input; carries any comments that trail the last real token.
+    Eof,
 }
 
 #[derive(Debug, Clone)]
-pub struct Token {
-    pub kind: TokenKind,
-    pub offset: usize,
+pub struct Token<'a> {
+    pub kind: TokenKind<'a>,
+    pub span: Span,
+    /// The source text the token was read from.
+    pub text: &'a str,
+    /// Comments that appear between the previous token and this one.
+    pub leading: Vec<Comment>,
+}
+
+impl Token<'_> {
+    /// Human-readable name used in diagnostics.
+    pub fn describe(&self) -> String {
+        match self.kind {
+            TokenKind::Identifier(_) => format!("identifier `{}`", self.text),
+            ref kind => kind.describe(),
+        }
+    }
+}
+
+impl TokenKind<'_> {
+    /// Human-readable name used in diagnostics; [`Token::describe`] also
+    /// names identifiers.
+    pub fn describe(&self) -> String {
+        match self {
+            TokenKind::At => "`@`".into(),
+            TokenKind::LBrace => "`{`".into(),
+            TokenKind::RBrace => "`}`".into(),
+            TokenKind::LBracket => "`[`".into(),
+            TokenKind::RBracket => "`]`".into(),
+            TokenKind::Colon => "`:`".into(),
+            TokenKind::Comma => "`,`".into(),
+            TokenKind::Eq => "`=`".into(),
+            TokenKind::Identifier(_) => "identifier".into(),
+            TokenKind::StringLiteral(_) => "string".into(),
+            TokenKind::IntegerLiteral(n) => format!("integer `{}`", n),
+            TokenKind::FloatLiteral(n) => format!("float `{:?}`", n),
+            TokenKind::BoolLiteral(b) => format!("`{}`", b),
+            TokenKind::Eof => "end of file".into(),
+        }
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum LexError {
-    #[error("Unexpected character '{ch}' at {offset}")]
-    UnexpectedChar { ch: char, offset: usize },
-    #[error("Unterminated string starting at {offset}")]
-    UnterminatedString { offset: usize },
-}
-
-pub fn lex(input: &str) -> Result<Vec<Token>, LexError> {
-    let mut tokens = Vec::new();
-    let mut chars = input.char_indices().peekable();
-
-    while let Some((idx, ch)) = chars.next() {
-        match ch {
-            '@' => tokens.push(Token { kind: TokenKind::At, offset: idx }),
-            '{' => tokens.push(Token { kind: TokenKind::LBrace, offset: idx }),
-            '}' => tokens.push(Token { kind: TokenKind::RBrace, offset: idx }),
-            '[' => tokens.push(Token { kind: TokenKind::LBracket, offset: idx }),
-            ']' => tokens.push(Token { kind: TokenKind::RBracket, offset: idx }),
-            ':' => tokens.push(Token { kind: TokenKind::Colon, offset: idx }),
-            ',' => tokens.push(Token { kind: TokenKind::Comma, offset: idx }),
-            '"' | '\'' => {
-                let start_quote = ch;
-                let start = idx;
-                let mut value = String::new();
-                loop {
-                    match chars.next() {
-                        Some((_, c)) if c == start_quote => break,
-                        Some((_, c)) => value.push(c),
-                        None => {
-                            return Err(LexError::UnterminatedString { offset: start });
+    #[error("Unexpected character '{ch}'")]
+    UnexpectedChar { ch: char, span: Span },
+    #[error("Unterminated string")]
+    UnterminatedString { span: Span },
+    #[error("Unterminated block comment")]
+    UnterminatedComment { span: Span },
+    #[error("Invalid escape sequence '{text}'")]
+    InvalidEscape { text: String, span: Span },
+    #[error("Invalid number '{text}': {reason}")]
+    InvalidNumber { text: String, reason: &'static str, span: Span },
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::UnexpectedChar { span, .. }
+            | LexError::UnterminatedString { span }
+            | LexError::UnterminatedComment { span }
+            | LexError::InvalidEscape { span, .. }
+            | LexError::InvalidNumber { span, .. } => *span,
+        }
+    }
+}
+
+impl From<LexError> for Diagnostic {
+    fn from(e: LexError) -> Self {
+        Diagnostic::error(e.to_string(), e.span())
+    }
+}
+
+/// Tokenize ALN source.
+///
+/// `#` and `//` start line comments, `/* ... */` is a block comment. Comments
+/// are not emitted as tokens; they are kept verbatim as trivia on the token that
+/// follows them, and the returned vector always ends with a `TokenKind::Eof`
+/// token holding any comments left at the end of the input.
+pub fn lex(input: &str) -> Result<Vec<Token<'_>>, LexError> {
+    let (tokens, mut errors) = lex_recovering(input);
+    if errors.is_empty() {
+        Ok(tokens)
+    } else {
+        Err(errors.swap_remove(0))
+    }
+}
+
+/// Like [`lex`], but skips over bad input and keeps going, returning every
+/// error alongside the tokens that could be read.
+pub fn lex_recovering(input: &str) -> (Vec<Token<'_>>, Vec<LexError>) {
+    let mut lexer = Lexer::new(input);
+    let tokens = lexer.by_ref().collect();
+    (tokens, lexer.into_errors())
+}
+
+/// Streaming tokenizer: yields one token at a time, ending with a single
+/// `TokenKind::Eof`, so the parser never holds the whole token list.
+///
+/// Bad input is skipped and recorded; [`Lexer::errors`] has everything found
+/// so far.
+pub struct Lexer<'a> {
+    input: &'a str,
+    chars: Chars<'a>,
+    interner: Interner<'a>,
+    errors: Vec<LexError>,
+    done: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            chars: input.char_indices().peekable(),
+            interner: Interner::default(),
+            errors: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Identifiers seen so far.
+    pub fn interner(&self) -> &Interner<'a> {
+        &self.interner
+    }
+
+    pub fn errors(&self) -> &[LexError] {
+        &self.errors
+    }
+
+    pub fn into_errors(self) -> Vec<LexError> {
+        self.errors
+    }
+
+    fn next_token(&mut self) -> Token<'a> {
+        let input = self.input;
+        let mut leading = Vec::new();
+        while let Some((idx, ch)) = self.chars.next() {
+            let chars = &mut self.chars;
+            let errors = &mut self.errors;
+            let kind = match ch {
+                '@' => TokenKind::At,
+                '{' => TokenKind::LBrace,
+                '}' => TokenKind::RBrace,
+                '[' => TokenKind::LBracket,
+                ']' => TokenKind::RBracket,
+                ':' => TokenKind::Colon,
+                ',' => TokenKind::Comma,
+                '=' => TokenKind::Eq,
+                '#' => {
+                    leading.push(line_comment(input, idx, chars));
+                    continue;
+                }
+                '/' if matches!(chars.peek(), Some((_, '/'))) => {
+                    leading.push(line_comment(input, idx, chars));
+                    continue;
+                }
+                '/' if matches!(chars.peek(), Some((_, '*'))) => {
+                    chars.next();
+                    let mut prev = '\0';
+                    let end = loop {
+                        match chars.next() {
+                            Some((i, '/')) if prev == '*' => break i + 1,
+                            Some((_, c)) => prev = c,
+                            None => {
+                                errors.push(LexError::UnterminatedComment {
+                                    span: Span::new(idx, input.len()),
+                                });
+                                break input.len();
+                            }
                         }
+                    };
+                    leading.push(Comment {
+                        text: input[idx..end].to_string(),
+                        span: Span::new(idx, end),
+                    });
+                    continue;
+                }
+                '"' | '\'' => {
+                    let triple = input[idx..].starts_with(&triple_quote(ch));
+                    if triple {
+                        chars.nth(1);
                     }
+                    TokenKind::StringLiteral(quoted_string(input, idx, ch, triple, chars, errors))
                 }
-                tokens.push(Token {
-                    kind: TokenKind::StringLiteral(value),
-                    offset: start,
-                });
-            }
-            ch if ch.is_whitespace() => {}
-            ch if ch.is_ascii_alphabetic() || ch == '_' => {
-                let mut ident = String::new();
-                ident.push(ch);
-                while let Some((_, c)) = chars.peek().copied() {
-                    if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
-                        ident.push(c);
-                        chars.next();
-                    } else {
-                        break;
+                'r' if raw_string_open(&input[idx + 1..]).is_some() => {
+                    TokenKind::StringLiteral(raw_string(input, idx, chars, errors))
+                }
+                ch if ch.is_whitespace() => continue,
+                ch if ch.is_ascii_alphabetic() || ch == '_' => {
+                    while chars.next_if(|&(_, c)| c.is_ascii_alphanumeric() || c == '_' || c == '.').is_some() {}
+                    let end = chars.peek().map(|&(i, _)| i).unwrap_or(input.len());
+                    match &input[idx..end] {
+                        "true" => TokenKind::BoolLiteral(true),
+                        "false" => TokenKind::BoolLiteral(false),
+                        ident => TokenKind::Identifier(self.interner.intern(ident)),
                     }
                 }
-                let kind = match ident.as_str() {
-                    "true" => TokenKind::BoolLiteral(true),
-                    "false" => TokenKind::BoolLiteral(false),
-                    _ => TokenKind::Identifier(ident),
-                };
-                tokens.push(Token { kind, offset: idx });
+                '-' | '+' if chars.peek().is_some_and(|(_, c)| c.is_ascii_digit()) => {
+                    number(input, idx, chars, errors)
+                }
+                ch if ch.is_ascii_digit() => number(input, idx, chars, errors),
+                other => {
+                    errors.push(LexError::UnexpectedChar {
+                        ch: other,
+                        span: Span::new(idx, idx + other.len_utf8()),
+                    });
+                    continue;
+                }
+            };
+            let end = self.chars.peek().map(|&(i, _)| i).unwrap_or(input.len());
+            return Token { kind, span: Span::new(idx, end), text: &input[idx..end], leading };
+        }
+        Token {
+            kind: TokenKind::Eof,
+            span: Span::new(input.len(), input.len()),
+            text: "",
+            leading,
+        }
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        if self.done {
+            return None;
+        }
+        let token = self.next_token();
+        self.done = token.kind == TokenKind::Eof;
+        Some(token)
+    }
+}
+
+/// An interned identifier. Symbols from the same [`Interner`] are equal
+/// exactly when their names are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+/// Identifier table filled by a [`Lexer`]. Names are slices of the source,
+/// so interning never copies them.
+#[derive(Debug, Default)]
+pub struct Interner<'a> {
+    symbols: HashMap<&'a str, Symbol>,
+    names: Vec<&'a str>,
+}
+
+impl<'a> Interner<'a> {
+    pub fn intern(&mut self, name: &'a str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(name) {
+            return symbol;
+        }
+        let symbol = Symbol(self.names.len() as u32);
+        self.names.push(name);
+        self.symbols.insert(name, symbol);
+        symbol
+    }
+
+    /// The symbol for `name`, if it has been interned.
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.symbols.get(name).copied()
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> &'a str {
+        self.names[symbol.0 as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+/// Consume a line comment up to (not including) the newline.
+fn line_comment(input: &str, start: usize, chars: &mut Chars<'_>) -> Comment {
+    let mut end = input.len();
+    while let Some((i, c)) = chars.peek().copied() {
+        if c == '\n' {
+            end = i;
+            break;
+        }
+        chars.next();
+    }
+    let text = input[start..end].trim_end();
+    Comment {
+        text: text.to_string(),
+        span: Span::new(start, start + text.len()),
+    }
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::CharIndices<'a>>;
+
+/// A number starting at `start`, whose first character (sign or digit) has
+/// been consumed. Grammar: `[+-]? digits ('.' digits)? ([eE] [+-]? digits)?`.
+/// Literals without a fraction or exponent are integers.
+///
+/// The whole run of number-like characters is consumed first so `1.2.3` or
+/// `10px` are reported as one malformed literal rather than split up.
+fn number<'a>(input: &str, start: usize, chars: &mut Chars<'_>, errors: &mut Vec<LexError>) -> TokenKind<'a> {
+    let mut prev = input[start..].chars().next().unwrap_or('0');
+    while let Some((_, c)) = chars.next_if(|&(_, c)| {
+        c.is_ascii_alphanumeric() || c == '.' || c == '_' || (matches!(c, '+' | '-') && matches!(prev, 'e' | 'E'))
+    }) {
+        prev = c;
+    }
+    let end = chars.peek().map(|&(i, _)| i).unwrap_or(input.len());
+    let text = &input[start..end];
+    let mut invalid = |reason| {
+        errors.push(LexError::InvalidNumber {
+            text: text.to_string(),
+            reason,
+            span: Span::new(start, end),
+        });
+        TokenKind::IntegerLiteral(0)
+    };
+
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let unsigned = text.strip_prefix(['+', '-']).unwrap_or(text);
+    let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+        Some((m, e)) => (m, Some(e.strip_prefix(['+', '-']).unwrap_or(e))),
+        None => (unsigned, None),
+    };
+    let (whole, fraction) = match mantissa.split_once('.') {
+        Some((w, f)) => (w, Some(f)),
+        None => (mantissa, None),
+    };
+    if !digits(whole) || !fraction.is_none_or(digits) {
+        return invalid("expected digits with at most one '.'");
+    }
+    if !exponent.is_none_or(digits) {
+        return invalid("expected digits after the exponent");
+    }
+
+    if fraction.is_none() && exponent.is_none() {
+        match text.parse::<i64>() {
+            Ok(n) => TokenKind::IntegerLiteral(n),
+            Err(_) => invalid("integer out of range"),
+        }
+    } else {
+        match text.parse::<f64>() {
+            Ok(n) if n.is_finite() => TokenKind::FloatLiteral(n),
+            _ => invalid("float out of range"),
+        }
+    }
+}
+
+fn triple_quote(quote: char) -> String {
+    quote.to_string().repeat(3)
+}
+
+/// Body of a `'...'`, `"..."` or triple-quoted string whose opening quote(s)
+/// at `start` have been consumed. Escapes are decoded; triple-quoted strings
+/// may span lines and drop a newline directly after the opening quotes.
+///
+/// The body is borrowed from `input` up to the first backslash; only strings
+/// with escapes are copied.
+fn quoted_string<'a>(
+    input: &'a str,
+    start: usize,
+    quote: char,
+    triple: bool,
+    chars: &mut Chars<'_>,
+    errors: &mut Vec<LexError>,
+) -> Cow<'a, str> {
+    let close = triple_quote(quote);
+    if triple {
+        skip_newline(chars);
+    }
+    let body = chars.peek().map(|&(i, _)| i).unwrap_or(input.len());
+    let mut decoded: Option<String> = None;
+    let end = loop {
+        match chars.next() {
+            Some((i, c)) if c == quote && (!triple || input[i..].starts_with(&close)) => {
+                if triple {
+                    chars.nth(1);
+                }
+                break i;
             }
-            ch if ch.is_ascii_digit() => {
-                let mut num = String::new();
-                num.push(ch);
-                while let Some((_, c)) = chars.peek().copied() {
-                    if c.is_ascii_digit() || c == '.' {
-                        num.push(c);
-                        chars.next();
-                    } else {
-                        break;
-                    }
+            Some((i, '\\')) => {
+                let value = decoded.get_or_insert_with(|| input[body..i].to_string());
+                match escape(input, i, chars) {
+                    Ok(Some(c)) => value.push(c),
+                    Ok(None) => {}
+                    Err(e) => errors.push(e),
                 }
-                tokens.push(Token {
-                    kind: TokenKind::NumberLiteral(num),
-                    offset: idx,
-                });
             }
-            other => {
-                return Err(LexError::UnexpectedChar {
-                    ch: other,
-                    offset: idx,
+            Some((_, c)) => {
+                if let Some(value) = &mut decoded {
+                    value.push(c);
+                }
+            }
+            None => {
+                errors.push(LexError::UnterminatedString {
+                    span: Span::new(start, input.len()),
                 });
+                break input.len();
             }
         }
+    };
+    match decoded {
+        Some(value) => Cow::Owned(value),
+        None => Cow::Borrowed(&input[body..end]),
     }
+}
+
+/// Decode the escape whose backslash is at `at`. Returns `None` for a line
+/// continuation (backslash at end of line), which swallows the newline and the
+/// next line's indentation.
+fn escape(input: &str, at: usize, chars: &mut Chars<'_>) -> Result<Option<char>, LexError> {
+    let invalid = |end: usize| LexError::InvalidEscape {
+        text: input[at..end].to_string(),
+        span: Span::new(at, end),
+    };
+    let Some((i, c)) = chars.next() else {
+        return Err(invalid(input.len()));
+    };
+    let decoded = match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        '\\' | '\'' | '"' => c,
+        '\n' | '\r' => {
+            while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+            return Ok(None);
+        }
+        'u' => {
+            // `\u{` then at most six hex digits; the `}` must follow them, so
+            // an unclosed escape never reaches past its string.
+            let Some(hex) = input[i + 1..].strip_prefix('{') else {
+                return Err(invalid(i + 1));
+            };
+            let close = i + 2 + hex.bytes().take(6).take_while(u8::is_ascii_hexdigit).count();
+            let end = if input[close..].starts_with('}') { close + 1 } else { close };
+            while chars.next_if(|&(j, _)| j < end).is_some() {}
+            if end == close {
+                return Err(invalid(close));
+            }
+            return u32::from_str_radix(&input[i + 2..close], 16)
+                .ok()
+                .and_then(char::from_u32)
+                .map(Some)
+                .ok_or_else(|| invalid(end));
+        }
+        _ => return Err(invalid(i + c.len_utf8())),
+    };
+    Ok(Some(decoded))
+}
+
+/// If `rest` (the input after an `r`) opens a raw string, the number of `#`
+/// marks and the quote character.
+fn raw_string_open(rest: &str) -> Option<(usize, char)> {
+    let hashes = rest.bytes().take_while(|&b| b == b'#').count();
+    match rest[hashes..].chars().next() {
+        Some(q @ ('"' | '\'')) => Some((hashes, q)),
+        _ => None,
+    }
+}
+
+/// A raw string starting with the `r` at `start`: `r'...'`, `r"..."`,
+/// `r#"..."#` (any number of `#`) or the triple-quoted forms of these. The body
+/// is taken verbatim, with no escape processing.
+fn raw_string<'a>(input: &'a str, start: usize, chars: &mut Chars<'_>, errors: &mut Vec<LexError>) -> Cow<'a, str> {
+    let (hashes, quote) = raw_string_open(&input[start + 1..]).expect("checked by caller");
+    let mut open = start + 1 + hashes;
+    let triple = input[open..].starts_with(&triple_quote(quote));
+    let mut close = if triple { triple_quote(quote) } else { quote.to_string() };
+    open += close.len();
+    close.push_str(&"#".repeat(hashes));
+
+    while chars.next_if(|&(i, _)| i < open).is_some() {}
+    if triple {
+        skip_newline(chars);
+    }
+    let body = chars.peek().map(|&(i, _)| i).unwrap_or(input.len());
+    let Some(len) = input[body..].find(&close) else {
+        while chars.next().is_some() {}
+        errors.push(LexError::UnterminatedString {
+            span: Span::new(start, input.len()),
+        });
+        return Cow::Borrowed(&input[body..]);
+    };
+    let end = body + len + close.len();
+    while chars.next_if(|&(i, _)| i < end).is_some() {}
+    Cow::Borrowed(&input[body..body + len])
+}
 
-    Ok(tokens)
+fn skip_newline(chars: &mut Chars<'_>) {
+    chars.next_if(|&(_, c)| c == '\r');
+    chars.next_if(|&(_, c)| c == '\n');
 }
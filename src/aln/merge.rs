@@ -0,0 +1,463 @@
+//! Deep merge of a base plan with overlays, e.g. `base.aln` + `prod.aln`.
+//!
+//! Each overlay is merged into the result of the ones before it:
+//!
+//! - Blocks with the same name, positional arguments and [`NAME`] argument
+//!   are merged recursively; other overlay blocks are appended. Other named
+//!   arguments of the overlay block replace the base block's. So
+//!   `@ALN_UPDATE_SYSTEM name='web'` only merges into the system of that name.
+//! - Scalars replace. Inline objects merge key by key with these same rules.
+//! - Lists are appended, unless the overlay block lists the key in
+//!   `replace=['features']`, in which case the overlay list replaces the base.
+//!   Lists inside inline objects are listed by dotted path, e.g.
+//!   `replace=['limits.tags']` for `limits: { tags: [...] }`.
+//! - An overlay block with `merge='replace'` replaces the base block whole.
+//!
+//! `replace` and `merge` are merge markers: they are read from the overlay and
+//! not copied into the result. Every key in the result keeps a history of the
+//! layers that set it, see [`Merged::explain`].
+
+use crate::aln::ast::{AlnFile, AlnItem, Block, BlockArgs, BlockEntry, InnerComment, Value, ValueTrivia};
+use crate::aln::diagnostic::{Diagnostic, Report};
+use crate::aln::parser::{parse_file_at, LoadAlnError, Parsed};
+use crate::aln::printer::print_value;
+use crate::aln::span::Span;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Named block argument marking lists that replace instead of append.
+pub const REPLACE: &str = "replace";
+/// Named block argument; `merge='replace'` replaces the whole block.
+pub const MERGE: &str = "merge";
+/// Named block argument that is part of a block's identity, like its
+/// positional arguments.
+pub const NAME: &str = "name";
+
+/// How a layer changed a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Set,
+    Replaced,
+    Appended,
+    Merged,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Action::Set => "set",
+            Action::Replaced => "replaced",
+            Action::Appended => "appended",
+            Action::Merged => "merged",
+        })
+    }
+}
+
+/// Where a final value came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Provenance {
+    /// Dotted path, e.g. `ALN_UPDATE_SYSTEM.RENDER_IN_FRAME.mode`. Blocks with
+    /// positional arguments are written `STEP(build)`.
+    pub path: String,
+    pub value: Value,
+    /// Span of the value in the layer that last wrote it.
+    pub span: Span,
+    /// Every layer that touched the key, first to last, as `file:line:col`.
+    pub history: Vec<(Action, String)>,
+}
+
+/// Result of [`merge_files`].
+#[derive(Debug, Clone)]
+pub struct Merged {
+    pub ast: AlnFile,
+    /// Diagnostics of every layer; spans point into the layer they came from.
+    pub report: Report,
+    pub provenance: Vec<Provenance>,
+}
+
+impl Merged {
+    pub fn into_parsed(self) -> Parsed {
+        Parsed { ast: self.ast, report: self.report }
+    }
+
+    /// One paragraph per key: its final value, then each layer that set it.
+    ///
+    /// ```text
+    /// ALN_UPDATE_SYSTEM.RENDER_IN_FRAME.mode = 'prod'
+    ///   set at base.aln:14:11
+    ///   replaced at prod.aln:3:11
+    /// ```
+    pub fn explain(&self) -> String {
+        let mut out = String::new();
+        for p in &self.provenance {
+            out.push_str(&format!("{} = {}\n", p.path, print_value(&p.value)));
+            for (action, location) in &p.history {
+                out.push_str(&format!("  {} at {}\n", action, location));
+            }
+        }
+        out
+    }
+}
+
+/// Parse `paths` and merge them in order, first path as the base. Layers are
+/// parsed into one offset space so their diagnostics render against the
+/// right file. Fails on I/O errors and an empty `paths` only; check `report`
+/// for syntax errors.
+pub fn merge_files(paths: &[&str]) -> Result<Merged, LoadAlnError> {
+    merge_files_with(paths, |_, _| None)
+}
+
+/// [`merge_files`], asking `refuse` before each overlay whether the layers
+/// merged so far, given as the entries of the top level, accept it. A
+/// diagnostic from `refuse` stops the merge with [`LoadAlnError::Schema`].
+pub(crate) fn merge_files_with(
+    paths: &[&str],
+    mut refuse: impl FnMut(&[BlockEntry], &str) -> Option<Diagnostic>,
+) -> Result<Merged, LoadAlnError> {
+    if paths.is_empty() {
+        return Err(LoadAlnError::NoInput);
+    }
+    let mut merger = Merger::default();
+    let mut body = Vec::new();
+    let mut report: Option<Report> = None;
+    for path in paths {
+        if let Some(r) = report.as_ref().filter(|r| !r.has_errors()) {
+            if let Some(d) = refuse(&body, path) {
+                return Err(LoadAlnError::Schema(Report { diagnostics: vec![d], ..r.clone() }));
+            }
+        }
+        let base = report.as_ref().map_or(0, |r| r.end() + 1);
+        let Parsed { ast, report: layer } = parse_file_at(path, base)?;
+        merger.body(&mut body, items_to_entries(ast.items), "", &[]);
+        report = Some(match report {
+            None => layer,
+            Some(mut r) => {
+                r.sources.push(layer.file);
+                r.sources.extend(layer.sources);
+                r.diagnostics.extend(layer.diagnostics);
+                r
+            }
+        });
+    }
+    let report = report.expect("paths is not empty");
+    let ast = AlnFile { items: entries_to_items(body), trailing_comments: Vec::new() };
+    let provenance = merger.provenance(&ast, &report);
+    Ok(Merged { ast, report, provenance })
+}
+
+/// Merge `overlay` into `base` without tracking provenance.
+pub fn merge(base: AlnFile, overlay: AlnFile) -> AlnFile {
+    let mut merger = Merger::default();
+    let mut body = items_to_entries(base.items);
+    merger.body(&mut body, items_to_entries(overlay.items), "", &[]);
+    AlnFile { items: entries_to_items(body), trailing_comments: base.trailing_comments }
+}
+
+fn items_to_entries(items: Vec<AlnItem>) -> Vec<BlockEntry> {
+    items
+        .into_iter()
+        .map(|item| BlockEntry::NestedBlock(item.into_block()))
+        .collect()
+}
+
+/// Inverse of [`items_to_entries`]; the top level only ever holds blocks.
+fn entries_to_items(entries: Vec<BlockEntry>) -> Vec<AlnItem> {
+    entries
+        .into_iter()
+        .filter_map(|e| match e {
+            BlockEntry::NestedBlock(b) => Some(AlnItem::from_block(b)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Path segment for a block: its name, plus positional arguments and
+/// [`NAME`] if any, e.g. `STEP(build)` or `ALN_UPDATE_SYSTEM(name=web)`.
+pub(crate) fn segment(block: &Block) -> String {
+    let text = |v: &Value| match v {
+        Value::Str(s) => s.clone(),
+        v => print_value(v),
+    };
+    let mut args: Vec<String> = block.args.iter().flat_map(|a| a.positional()).map(text).collect();
+    if let Some(name) = block.args.as_ref().and_then(|a| a.named(NAME)) {
+        args.push(format!("{}={}", NAME, text(name)));
+    }
+    if args.is_empty() {
+        block.name.clone()
+    } else {
+        format!("{}({})", block.name, args.join(", "))
+    }
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", prefix, name)
+    }
+}
+
+/// Whether two blocks are the same block in different layers.
+pub(crate) fn same_block(a: &Block, b: &Block) -> bool {
+    fn name(b: &Block) -> Option<&Value> {
+        b.args.as_ref().and_then(|a| a.named(NAME))
+    }
+    a.name == b.name
+        && a.args.iter().flat_map(|a| a.positional()).eq(b.args.iter().flat_map(|a| a.positional()))
+        && name(a) == name(b)
+}
+
+/// Remove the merge markers from `block`, returning whether it replaces the
+/// base block and which list keys replace instead of append.
+fn take_markers(block: &mut Block) -> (bool, Vec<String>) {
+    let Some(args) = &mut block.args else {
+        return (false, Vec::new());
+    };
+    let mut replace_block = false;
+    let mut replace_lists = Vec::new();
+    args.items.retain(|arg| match arg.name.as_deref() {
+        Some(MERGE) => {
+            replace_block = arg.value == Value::Str("replace".into());
+            false
+        }
+        Some(REPLACE) => {
+            match &arg.value {
+                Value::Array(keys) => replace_lists.extend(keys.iter().filter_map(|k| match k {
+                    Value::Str(s) => Some(s.clone()),
+                    _ => None,
+                })),
+                Value::Str(s) => replace_lists.push(s.clone()),
+                _ => {}
+            }
+            false
+        }
+        _ => true,
+    });
+    if args.items.is_empty() {
+        block.args = None;
+    }
+    (replace_block, replace_lists)
+}
+
+#[derive(Default)]
+struct Merger {
+    /// Key path -> layers that set it, with the span of the value they wrote.
+    history: HashMap<String, Vec<(Action, Span)>>,
+}
+
+impl Merger {
+    fn body(&mut self, base: &mut Vec<BlockEntry>, overlay: Vec<BlockEntry>, path: &str, replace_lists: &[String]) {
+        for entry in overlay {
+            match entry {
+                BlockEntry::KeyValue { key, value, comments, trivia, key_span, value_span } => {
+                    let key_path = join(path, &key);
+                    let existing = base.iter_mut().find_map(|e| match e {
+                        BlockEntry::KeyValue { key: k, value, comments, trivia, value_span, .. } if *k == key => {
+                            Some((value, comments, trivia, value_span))
+                        }
+                        _ => None,
+                    });
+                    let Some((old, old_comments, old_trivia, old_span)) = existing else {
+                        self.record(key_path, Action::Set, value_span);
+                        base.push(BlockEntry::KeyValue { key, value, comments, trivia, key_span, value_span });
+                        continue;
+                    };
+                    let action = match (old, value) {
+                        (Value::Array(items), Value::Array(more)) if !replace_lists.contains(&key) => {
+                            Inline::new(old_trivia, &trivia, replace_lists).append(items, more);
+                            Action::Appended
+                        }
+                        (Value::Object(fields), Value::Object(more)) => {
+                            Inline::new(old_trivia, &trivia, replace_lists).object(fields, more, &key);
+                            Action::Merged
+                        }
+                        (old, value) => {
+                            *old = value;
+                            *old_trivia = trivia;
+                            *old_span = value_span;
+                            Action::Replaced
+                        }
+                    };
+                    if !comments.is_empty() {
+                        *old_comments = comments;
+                    }
+                    self.record(key_path, action, value_span);
+                }
+                BlockEntry::NestedBlock(mut block) => {
+                    let (replace_block, lists) = take_markers(&mut block);
+                    let block_path = join(path, &segment(&block));
+                    let existing = base.iter_mut().find_map(|e| match e {
+                        BlockEntry::NestedBlock(b) if same_block(b, &block) => Some(b),
+                        _ => None,
+                    });
+                    match existing {
+                        Some(old) if replace_block => {
+                            let prefix = format!("{}.", block_path);
+                            self.history.retain(|k, _| !k.starts_with(&prefix));
+                            self.record_block(&block, &block_path);
+                            *old = block;
+                        }
+                        Some(old) => {
+                            for arg in block.args.iter().flat_map(|a| &a.items) {
+                                let Some(name) = &arg.name else { continue };
+                                let args = old
+                                    .args
+                                    .get_or_insert_with(|| BlockArgs { items: Vec::new(), span: Span::default() });
+                                match args.items.iter_mut().find(|a| a.name.as_ref() == Some(name)) {
+                                    Some(a) => {
+                                        a.value = arg.value.clone();
+                                        a.trivia = arg.trivia.clone();
+                                    }
+                                    None => args.items.push(arg.clone()),
+                                }
+                            }
+                            if !block.comments.is_empty() {
+                                old.comments = block.comments;
+                            }
+                            self.body(&mut old.body, block.body, &block_path, &lists);
+                        }
+                        None => {
+                            self.record_block(&block, &block_path);
+                            base.push(BlockEntry::NestedBlock(block));
+                        }
+                    }
+                }
+                BlockEntry::List(list) => base.push(BlockEntry::List(list)),
+            }
+        }
+    }
+
+    /// Record every key of a block taken over as a whole.
+    fn record_block(&mut self, block: &Block, path: &str) {
+        for entry in &block.body {
+            match entry {
+                BlockEntry::KeyValue { key, value_span, .. } => self.record(join(path, key), Action::Set, *value_span),
+                BlockEntry::NestedBlock(b) => self.record_block(b, &join(path, &segment(b))),
+                BlockEntry::List(_) => {}
+            }
+        }
+    }
+
+    fn record(&mut self, path: String, action: Action, span: Span) {
+        self.history.entry(path).or_default().push((action, span));
+    }
+
+    /// Provenance of every key in `file`, in file order.
+    fn provenance(&self, file: &AlnFile, report: &Report) -> Vec<Provenance> {
+        let mut out = Vec::new();
+        for item in &file.items {
+            let b = item.block();
+            self.block_provenance(b, &segment(b), report, &mut out);
+        }
+        out
+    }
+
+    fn block_provenance(&self, block: &Block, path: &str, report: &Report, out: &mut Vec<Provenance>) {
+        for entry in &block.body {
+            match entry {
+                BlockEntry::KeyValue { key, value, value_span, .. } => {
+                    let path = join(path, key);
+                    let history = self.history.get(&path).map_or(Vec::new(), |h| {
+                        h.iter()
+                            .map(|(action, span)| {
+                                let file = report.source(span.start);
+                                (*action, format!("{}:{}", file.name(), file.line_col(span.start)))
+                            })
+                            .collect()
+                    });
+                    out.push(Provenance { path, value: value.clone(), span: *value_span, history });
+                }
+                BlockEntry::NestedBlock(b) => self.block_provenance(b, &join(path, &segment(b)), report, out),
+                BlockEntry::List(_) => {}
+            }
+        }
+    }
+}
+
+/// Merges one overlay value into the base value of the same entry. `at` and
+/// `from` are the positions being merged in `trivia` and `overlay`, the trivia
+/// of the two entries, so comments and raw strings follow the values they
+/// belong to.
+struct Inline<'a> {
+    trivia: &'a mut ValueTrivia,
+    overlay: &'a ValueTrivia,
+    /// The overlay block's `replace` keys.
+    replace: &'a [String],
+    at: Vec<usize>,
+    from: Vec<usize>,
+}
+
+impl<'a> Inline<'a> {
+    fn new(trivia: &'a mut ValueTrivia, overlay: &'a ValueTrivia, replace: &'a [String]) -> Self {
+        Self { trivia, overlay, replace, at: Vec::new(), from: Vec::new() }
+    }
+
+    /// Merge inline object `more` into `fields`, the value at dotted `path`:
+    /// nested objects merge, lists append unless `replace` names them, e.g.
+    /// `limits.tags`, and anything else replaces.
+    fn object(&mut self, fields: &mut Vec<(String, Value)>, more: Vec<(String, Value)>, path: &str) {
+        let len = more.len();
+        for (j, (key, value)) in more.into_iter().enumerate() {
+            let existing = fields.iter().position(|(k, _)| *k == key);
+            let path = join(path, &key);
+            self.at.push(existing.unwrap_or(fields.len()));
+            self.from.push(j);
+            match (existing.map(|i| &mut fields[i].1), value) {
+                (Some(Value::Object(inner)), Value::Object(more)) => {
+                    self.graft(false);
+                    self.object(inner, more, &path);
+                }
+                (Some(Value::Array(items)), Value::Array(more)) if !self.replace.contains(&path) => {
+                    self.graft(false);
+                    self.append(items, more);
+                }
+                (Some(old), value) => {
+                    self.trivia.comments.retain(|c| !c.at.starts_with(&self.at));
+                    self.trivia.raw.retain(|r| !r.starts_with(&self.at));
+                    self.graft(true);
+                    *old = value;
+                }
+                (None, value) => {
+                    self.graft(true);
+                    fields.push((key, value));
+                }
+            }
+            self.at.pop();
+            self.from.pop();
+        }
+        self.at.push(fields.len());
+        self.from.push(len);
+        self.graft(false);
+        self.at.pop();
+        self.from.pop();
+    }
+
+    /// Append the overlay list `more` to `items`.
+    fn append(&mut self, items: &mut Vec<Value>, more: Vec<Value>) {
+        // Up to `more.len()` inclusive: the overlay's closing bracket.
+        for k in 0..=more.len() {
+            self.at.push(items.len() + k);
+            self.from.push(k);
+            self.graft(true);
+            self.at.pop();
+            self.from.pop();
+        }
+        items.extend(more);
+    }
+
+    /// Copy the overlay trivia at `from` to `at`, with everything below it if
+    /// `whole`.
+    fn graft(&mut self, whole: bool) {
+        let (at, from) = (&self.at, &self.from);
+        let moved = |path: &Vec<usize>| {
+            let inside = if whole { path.starts_with(from) } else { path == from };
+            inside.then(|| [at, &path[from.len()..]].concat())
+        };
+        for c in &self.overlay.comments {
+            if let Some(path) = moved(&c.at) {
+                self.trivia.comments.push(InnerComment { at: path, comment: c.comment.clone() });
+            }
+        }
+        self.trivia.raw.extend(self.overlay.raw.iter().filter_map(moved));
+    }
+}
@@ -0,0 +1,333 @@
+//! Upgrading plans written for older format versions.
+//!
+//! A [`Migration`] rewrites the root `@ALN_UPDATE_SYSTEM` block of plans
+//! older than its target version, e.g. renaming a key or splitting a block.
+//! A [`Registry`] keeps migrations in version order; [`Registry::migrate`]
+//! applies every step a plan needs, oldest first, then declares the new
+//! version where the plan declared the old one. Each update system of a file
+//! is migrated from its own version. A plan that declares no version is
+//! migrated from [`UNDECLARED`] and gets a `version` key.
+//!
+//! Plans from [`OLDEST_SUPPORTED`] to [`NEWEST_SUPPORTED`] share one shape so
+//! far, so [`Registry::builtin`] has no steps yet and migrating a plan only
+//! updates its version. [`set_version_text`] does that on the source text,
+//! so such a migration changes nothing else in the file. A format change
+//! registers its step there along with the new [`NEWEST_SUPPORTED`].
+//!
+//! ```
+//! use aln_system_update_orchestrator::aln::migrate::{rename_key, Migration, Registry};
+//! use aln_system_update_orchestrator::aln::parser::parse_str;
+//! use aln_system_update_orchestrator::aln::printer::print;
+//! use aln_system_update_orchestrator::aln::PlanVersion;
+//!
+//! let registry = Registry::new().register(Migration {
+//!     to: PlanVersion::new(1, 0, 1, 0),
+//!     description: "rename INTEROP.lan to enable_lan",
+//!     apply: |root| {
+//!         rename_key(root, &["INTEROP"], "lan", "enable_lan");
+//!         Ok(())
+//!     },
+//! });
+//! let mut file = parse_str("@ALN_UPDATE_SYSTEM '1.0.0.2' { @INTEROP { lan: 'auto' } }", None).unwrap();
+//! let migrated = registry.migrate(&mut file, PlanVersion::new(1, 0, 1, 7)).unwrap();
+//! assert_eq!(migrated[0].steps, ["rename INTEROP.lan to enable_lan"]);
+//! assert_eq!(
+//!     print(&file),
+//!     "@ALN_UPDATE_SYSTEM '1.0.1.7' {\n  @INTEROP {\n    enable_lan: 'auto'\n  }\n}\n"
+//! );
+//! ```
+//!
+//! [`OLDEST_SUPPORTED`]: crate::aln::version::OLDEST_SUPPORTED
+//! [`NEWEST_SUPPORTED`]: crate::aln::version::NEWEST_SUPPORTED
+//! [`UNDECLARED`]: crate::aln::version::UNDECLARED
+
+use crate::aln::ast::{AlnFile, AlnItem, Block, BlockEntry, Value, ValueTrivia};
+use crate::aln::diagnostic::Diagnostic;
+use crate::aln::lexer::{self, TokenKind};
+use crate::aln::merge;
+use crate::aln::model::AlnUpdatePlan;
+use crate::aln::printer;
+use crate::aln::span::Span;
+use crate::aln::version::{self, PlanVersion};
+use thiserror::Error;
+
+/// One step of the format's history.
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    /// Plans older than this version need the step; afterwards they have this
+    /// version's shape.
+    pub to: PlanVersion,
+    /// Shown in previews, e.g. `rename INTEROP.lan to enable_lan`.
+    pub description: &'static str,
+    /// Rewrites the root block. Errors say why the plan cannot be upgraded.
+    pub apply: fn(&mut Block) -> Result<(), String>,
+}
+
+/// Migrations ordered by target version.
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    migrations: Vec<Migration>,
+}
+
+/// What [`Registry::migrate`] did to one update system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migrated {
+    /// The system's `name` argument, if it has one.
+    pub system: Option<String>,
+    /// The declared version, or [`version::UNDECLARED`] if there was none.
+    pub from: PlanVersion,
+    /// Whether the plan declared `from`. An undeclared version is written
+    /// out even when `from` is already `to`.
+    pub declared: bool,
+    pub to: PlanVersion,
+    /// Descriptions of the steps applied, in order.
+    pub steps: Vec<&'static str>,
+}
+
+impl Migrated {
+    /// Whether the plan was rewritten at all.
+    pub fn changed(&self) -> bool {
+        self.from != self.to || !self.declared
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MigrateError {
+    #[error("missing @{0} block")]
+    MissingRoot(&'static str),
+    #[error("{}", .0.message)]
+    Version(Diagnostic),
+    #[error("plan version {found} is newer than the target version {target}")]
+    Newer { found: PlanVersion, target: PlanVersion },
+    #[error("migration to {to} ({description}) failed: {message}")]
+    Step { to: PlanVersion, description: &'static str, message: String },
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The migrations between the format versions this orchestrator knows.
+    pub fn builtin() -> Self {
+        Self::new()
+    }
+
+    /// Add a step. Steps to the same version run in the order registered.
+    pub fn register(mut self, migration: Migration) -> Self {
+        let at = self.migrations.partition_point(|m| m.to <= migration.to);
+        self.migrations.insert(at, migration);
+        self
+    }
+
+    pub fn migrations(&self) -> &[Migration] {
+        &self.migrations
+    }
+
+    /// Steps a plan at `from` needs to reach `to`, oldest first.
+    pub fn steps(&self, from: PlanVersion, to: PlanVersion) -> impl Iterator<Item = &Migration> {
+        self.migrations.iter().filter(move |m| from < m.to && m.to <= to)
+    }
+
+    /// Upgrade every update system of `file` to version `to` in place, in
+    /// file order. A plan already at `to` is left untouched; a newer one is an
+    /// error. On error, `file` may be partly migrated.
+    pub fn migrate(&self, file: &mut AlnFile, to: PlanVersion) -> Result<Vec<Migrated>, MigrateError> {
+        let name = AlnUpdatePlan::schema().name;
+        let roots: Vec<&mut Block> = file
+            .items
+            .iter_mut()
+            .filter_map(|item| match item {
+                AlnItem::Block(b) if b.name == name => Some(b),
+                _ => None,
+            })
+            .collect();
+        if roots.is_empty() {
+            return Err(MigrateError::MissingRoot(name));
+        }
+        roots.into_iter().map(|root| self.migrate_root(root, to)).collect()
+    }
+
+    fn migrate_root(&self, root: &mut Block, to: PlanVersion) -> Result<Migrated, MigrateError> {
+        let system = match root.args.as_ref().and_then(|a| a.named(merge::NAME)) {
+            Some(Value::Str(name)) => Some(name.clone()),
+            _ => None,
+        };
+        let (from, span) = version::declared_unchecked(root).map_err(MigrateError::Version)?;
+        let declared = span.is_some();
+        if from > to {
+            return Err(MigrateError::Newer { found: from, target: to });
+        }
+
+        let mut steps = Vec::new();
+        for m in self.steps(from, to) {
+            (m.apply)(root).map_err(|message| MigrateError::Step {
+                to: m.to,
+                description: m.description,
+                message,
+            })?;
+            steps.push(m.description);
+        }
+        if from != to || !declared {
+            set_version(root, to);
+        }
+        Ok(Migrated { system, from, declared, to, steps })
+    }
+}
+
+/// Rewrite every version declaration of `root`, see [`version::declarations`]
+/// for where they may be, or declare one as the first key if it has none.
+fn set_version(root: &mut Block, version: PlanVersion) {
+    let new = Value::Str(version.to_string());
+    let mut found = false;
+    if let Some(args) = &mut root.args {
+        let first = args.items.iter().position(|a| a.name.is_none());
+        for (i, arg) in args.items.iter_mut().enumerate() {
+            if Some(i) == first || arg.name.as_deref() == Some("version") {
+                arg.value = new.clone();
+                found = true;
+            }
+        }
+    }
+    for entry in &mut root.body {
+        if let BlockEntry::KeyValue { key, value, .. } = entry {
+            if key == "version" {
+                *value = new.clone();
+                found = true;
+            }
+        }
+    }
+    if !found {
+        let entry = BlockEntry::KeyValue {
+            key: "version".to_string(),
+            value: new,
+            comments: Vec::new(),
+            trivia: ValueTrivia::default(),
+            key_span: Span::default(),
+            value_span: Span::default(),
+        };
+        root.body.insert(0, entry);
+    }
+}
+
+/// Declare version `to` in `src`, the text `file` was parsed from, the way
+/// [`Registry::migrate`] does when no step applies: version literals are
+/// replaced and a plan without one gets a `version` key after its `{`.
+/// Everything else, comments and layout included, is kept byte for byte.
+pub fn set_version_text(src: &str, file: &AlnFile, to: PlanVersion) -> String {
+    let Ok(tokens) = lexer::lex(src) else {
+        return src.to_string();
+    };
+    let new = printer::quote(&to.to_string());
+    let name = AlnUpdatePlan::schema().name;
+    let mut edits: Vec<(Span, String)> = Vec::new();
+    for root in file.items.iter().map(AlnItem::block).filter(|b| b.name == name) {
+        let found = version::declarations(root);
+        if found.is_empty() {
+            let after = root.args.as_ref().map_or(root.name_span, |a| a.span).end;
+            if let Some(brace) = tokens.iter().find(|t| t.span.start >= after && t.kind == TokenKind::LBrace) {
+                let at = Span::new(brace.span.end, brace.span.end);
+                edits.push((at, format!("\n  version: {},", new)));
+            }
+        }
+        for (value, span) in found {
+            if matches!(value, Value::Str(s) if s.parse() == Ok(to)) {
+                continue;
+            }
+            // A named argument's span starts at its name; the literal ends it.
+            let literal = tokens.iter().rev().find(|t| t.span.start >= span.start && t.span.end <= span.end);
+            if let Some(literal) = literal {
+                edits.push((literal.span, new.clone()));
+            }
+        }
+    }
+    edits.sort_by_key(|(span, _)| span.start);
+    let mut out = src.to_string();
+    for (span, text) in edits.into_iter().rev() {
+        out.replace_range(span.start..span.end, &text);
+    }
+    out
+}
+
+/// Call `f` on every block reached from `block` through nested blocks named
+/// `path`; an empty path is `block` itself.
+fn each_block(block: &mut Block, path: &[&str], f: &mut dyn FnMut(&mut Block)) {
+    let Some((first, rest)) = path.split_first() else {
+        f(block);
+        return;
+    };
+    for entry in &mut block.body {
+        if let BlockEntry::NestedBlock(b) = entry {
+            if b.name == *first {
+                each_block(b, rest, f);
+            }
+        }
+    }
+}
+
+/// Rename key `from` to `to` in the blocks at `path` below `root`, e.g.
+/// `&["INTEROP"]`.
+pub fn rename_key(root: &mut Block, path: &[&str], from: &str, to: &str) {
+    each_block(root, path, &mut |block| {
+        for entry in &mut block.body {
+            if let BlockEntry::KeyValue { key, .. } = entry {
+                if key == from {
+                    *key = to.to_string();
+                }
+            }
+        }
+    });
+}
+
+/// Rename blocks `from` to `to` inside the blocks at `path` below `root`;
+/// an empty path renames blocks directly inside `root`.
+pub fn rename_block(root: &mut Block, path: &[&str], from: &str, to: &str) {
+    each_block(root, path, &mut |block| {
+        for entry in &mut block.body {
+            if let BlockEntry::NestedBlock(b) = entry {
+                if b.name == from {
+                    b.name = to.to_string();
+                }
+            }
+        }
+    });
+}
+
+/// Move `keys` out of the blocks at `path` below `root` into a new block
+/// `name` right after each of them, e.g. splitting `@RENDER_IN_FRAME`'s
+/// `playable_platforms` out into `@PLATFORMS`. Blocks holding none of the
+/// keys are left alone.
+pub fn split_block(root: &mut Block, path: &[&str], name: &str, keys: &[&str]) {
+    let Some((last, parent)) = path.split_last() else {
+        return;
+    };
+    each_block(root, parent, &mut |parent| {
+        let mut i = 0;
+        while i < parent.body.len() {
+            i += 1;
+            let BlockEntry::NestedBlock(block) = &mut parent.body[i - 1] else { continue };
+            if block.name != *last {
+                continue;
+            }
+            let (moved, kept) = std::mem::take(&mut block.body).into_iter().partition(|e| {
+                matches!(e, BlockEntry::KeyValue { key, .. } if keys.contains(&key.as_str()))
+            });
+            block.body = kept;
+            if moved.is_empty() {
+                continue;
+            }
+            let split = Block {
+                name: name.to_string(),
+                args: None,
+                body: moved,
+                comments: Vec::new(),
+                closing_comments: Vec::new(),
+                span: Span::default(),
+                name_span: Span::default(),
+                origin: block.origin.clone(),
+            };
+            parent.body.insert(i, BlockEntry::NestedBlock(split));
+            i += 1;
+        }
+    });
+}
@@ -1,10 +1,34 @@
 pub mod ast;
+pub mod convert;
+pub mod de;
+pub mod diagnostic;
+pub mod diff;
+pub mod ide;
+pub mod include;
+pub mod interpolate;
 pub mod lexer;
+pub mod merge;
+pub mod migrate;
 pub mod parser;
+pub mod printer;
+pub mod query;
 pub mod model;
+pub mod schema;
+pub mod ser;
+pub mod span;
+pub mod version;
+pub mod visit;
 
-pub use parser::{AlnUpdatePlan, LoadAlnError};
+pub use de::{from_str, Error};
+pub use parser::LoadAlnError;
+pub use schema::Strictness;
+pub use ser::to_string;
+pub use version::PlanVersion;
 pub use model::{
+    AlnUpdatePlan,
+    AlnPlanSet,
+    AlnSystem,
+    AlnMeta,
     AlnAction,
     AlnComponentConfig,
     AlnInteropConfig,
@@ -1,14 +1,68 @@
+use crate::aln::ast::{AlnFile, Value, META};
+use crate::aln::schema::{BlockSchema, Field, Type};
+use crate::aln::ser;
+use crate::aln::version::PlanVersion;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
+/// A loaded plan. Serialized field names are the Rust ones (`components`,
+/// `interop`, ...); the ALN block names are accepted as aliases, so the
+/// derives double as the mapping from a validated `@ALN_UPDATE_SYSTEM` block.
+/// [`AlnUpdatePlan::to_file`] writes the plan back with the block names.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AlnUpdatePlan {
-    pub version: String,
+    /// Format version the plan declares, within the supported range.
+    pub version: PlanVersion,
+    #[serde(alias = "SEPARATE")]
     pub components: AlnComponentConfig,
+    #[serde(alias = "INTEROP")]
     pub interop: AlnInteropConfig,
+    #[serde(alias = "RENDER_IN_FRAME")]
     pub render: AlnRenderConfig,
+    #[serde(alias = "EXEC_REGO_POLICY")]
     pub rego_exec: AlnRegoExecConfig,
 }
 
+/// [`AlnUpdatePlan`] as written in ALN, with sections named after their blocks.
+#[derive(Serialize)]
+struct PlanBlocks<'a> {
+    version: &'a PlanVersion,
+    #[serde(rename = "SEPARATE")]
+    components: &'a AlnComponentConfig,
+    #[serde(rename = "INTEROP")]
+    interop: &'a AlnInteropConfig,
+    #[serde(rename = "RENDER_IN_FRAME")]
+    render: &'a AlnRenderConfig,
+    #[serde(rename = "EXEC_REGO_POLICY")]
+    rego_exec: &'a AlnRegoExecConfig,
+}
+
+/// Every update system a file declares, with the file's `@META` block.
+///
+/// A file may declare several `@ALN_UPDATE_SYSTEM` blocks, each with its own
+/// version and a distinct name given as `@ALN_UPDATE_SYSTEM name='web' { ... }`.
+/// The name may be left out when there is only one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlnPlanSet {
+    pub meta: Option<AlnMeta>,
+    /// In file order.
+    pub systems: Vec<AlnSystem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlnSystem {
+    /// The `name` argument; `None` only when the file declares one system.
+    pub name: Option<String>,
+    pub plan: AlnUpdatePlan,
+}
+
+/// Top-level `@META` block: who owns the plans in a file and what they are for.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AlnMeta {
+    pub owner: String,
+    pub description: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AlnComponentConfig {
     pub game_engine: String,
@@ -42,3 +96,119 @@ pub struct AlnAction {
     pub name: String,
     pub params: serde_json::Value,
 }
+
+impl AlnUpdatePlan {
+    /// Schema of the `@ALN_UPDATE_SYSTEM` block and its sections.
+    pub fn schema() -> BlockSchema {
+        BlockSchema::new("ALN_UPDATE_SYSTEM")
+            .doc("Root of an update plan.")
+            .field(Field::optional("version", Type::Str).doc("Plan format version, `major.minor.patch.build`; may also be given as a block argument."))
+            .required_block(AlnComponentConfig::schema())
+            .required_block(AlnInteropConfig::schema())
+            .required_block(AlnRenderConfig::schema())
+            .required_block(AlnRegoExecConfig::schema())
+    }
+
+    /// The plan as a file holding one `@ALN_UPDATE_SYSTEM` block.
+    pub fn to_file(&self) -> AlnFile {
+        let blocks = PlanBlocks {
+            version: &self.version,
+            components: &self.components,
+            interop: &self.interop,
+            render: &self.render,
+            rego_exec: &self.rego_exec,
+        };
+        ser::to_file(&BTreeMap::from([(Self::schema().name, blocks)])).expect("plans always serialize to ALN")
+    }
+}
+
+impl AlnPlanSet {
+    /// The plan of the system called `name`.
+    pub fn get(&self, name: &str) -> Option<&AlnUpdatePlan> {
+        self.systems
+            .iter()
+            .find(|s| s.name.as_deref() == Some(name))
+            .map(|s| &s.plan)
+    }
+}
+
+impl AlnMeta {
+    pub fn schema() -> BlockSchema {
+        BlockSchema::new(META)
+            .doc("Describes the plans in this file. Top level only, at most once.")
+            .field(Field::optional("owner", Type::Str).default(empty_str()).doc("Team or person responsible for the plans."))
+            .field(Field::optional("description", Type::Str).default(empty_str()).doc("What the plans in this file update."))
+    }
+}
+
+fn empty_str() -> Value {
+    Value::Str(String::new())
+}
+
+fn empty_list() -> Value {
+    Value::Array(Vec::new())
+}
+
+impl AlnComponentConfig {
+    pub fn schema() -> BlockSchema {
+        BlockSchema::new("SEPARATE")
+            .doc("Components that are deployed and updated separately.")
+            .field(Field::optional("game_engine", Type::Str).default(empty_str()).doc("Game engine runtime."))
+            .field(Field::optional("ai_chat_ui", Type::Str).default(empty_str()).doc("Chat UI front end."))
+            .field(
+                Field::optional("renderers", Type::array_of(Type::Str))
+                    .default(empty_list())
+                    .doc("Renderers to update."),
+            )
+    }
+}
+
+impl AlnInteropConfig {
+    pub fn schema() -> BlockSchema {
+        BlockSchema::new("INTEROP")
+            .doc("Cross-process links and LAN networking between components.")
+            .field(Field::optional("cross_link", Type::Str).default(empty_str()).doc("How process trees are linked."))
+            .field(
+                Field::optional("maintain_func", Type::Bool)
+                    .default(Value::Bool(true))
+                    .doc("Keep existing functionality working during the update."),
+            )
+            .field(Field::optional("enable_lan", Type::Str).default(empty_str()).doc("LAN service mode."))
+    }
+}
+
+impl AlnRenderConfig {
+    pub fn schema() -> BlockSchema {
+        BlockSchema::new("RENDER_IN_FRAME")
+            .doc("Where and how update progress is rendered.")
+            .field(Field::optional("mode", Type::Str).default(empty_str()).doc("Render mode."))
+            .field(
+                Field::optional("merge_sources", Type::Bool)
+                    .default(Value::Bool(true))
+                    .doc("Merge overlay plans into the base plan."),
+            )
+            .field(
+                Field::optional("playable_platforms", Type::array_of(Type::Str))
+                    .default(empty_list())
+                    .doc("Platforms the output is rendered on."),
+            )
+    }
+}
+
+impl AlnRegoExecConfig {
+    pub fn schema() -> BlockSchema {
+        BlockSchema::new("EXEC_REGO_POLICY")
+            .doc("OPA/Rego policy evaluated before the update runs.")
+            .field(
+                Field::optional("always_active", Type::Bool)
+                    .default(Value::Bool(true))
+                    .doc("Evaluate the policy on every update."),
+            )
+            .field(Field::optional("policy", Type::Str).default(empty_str()).doc("Rego policy file."))
+            .field(
+                Field::optional("features", Type::array_of(Type::Str))
+                    .default(empty_list())
+                    .doc("Features enabled by this update."),
+            )
+    }
+}
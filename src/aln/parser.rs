@@ -1,336 +1,943 @@
 use crate::aln::ast::*;
-use crate::aln::lexer::{lex, LexError, Token, TokenKind};
-use crate::aln::model::{
-    AlnComponentConfig,
-    AlnInteropConfig,
-    AlnRenderConfig,
-    AlnRegoExecConfig,
-    AlnUpdatePlan,
-};
-use serde_json::json;
+use crate::aln::de;
+use crate::aln::diagnostic::{Diagnostic, Report, Severity};
+use crate::aln::include::{self, INCLUDE};
+use crate::aln::interpolate;
+use crate::aln::merge;
+use crate::aln::lexer::{Lexer, Token, TokenKind};
+use crate::aln::model::{AlnMeta, AlnPlanSet, AlnSystem, AlnUpdatePlan};
+use crate::aln::schema::{self, BlockSchema, Strictness};
+use crate::aln::span::{SourceFile, Span};
+use crate::aln::version;
+use serde::de::DeserializeOwned;
+use std::collections::VecDeque;
 use std::fs;
+use std::io::Read;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum LoadAlnError {
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
-    #[error("Lex error: {0}")]
-    Lex(#[from] LexError),
-    #[error("Parse error: {0}")]
-    Parse(String),
+    /// Lex or parse failure, rendered with file name, line and column.
+    #[error("{0}")]
+    Syntax(Report),
+    /// An `@INCLUDE` could not be resolved, or a `${...}` reference is
+    /// undefined or part of a cycle.
+    #[error("{0}")]
+    Resolve(Report),
+    /// The plan parsed but does not match the expected block schema.
+    #[error("{0}")]
+    Schema(Report),
+    /// A loader that merges files was given none.
+    #[error("no input files")]
+    NoInput,
 }
 
+/// How deeply blocks, lists and inline objects may nest. Deeper groups are
+/// reported and skipped rather than parsed, so untrusted input cannot exhaust
+/// the stack.
+pub const MAX_DEPTH: usize = 128;
+
+/// Output of a recovering parse: the AST that could be built, plus every
+/// problem found along the way.
+#[derive(Debug, Clone)]
+pub struct Parsed {
+    pub ast: AlnFile,
+    pub report: Report,
+}
+
+impl Parsed {
+    /// The AST if nothing went wrong, otherwise all diagnostics at once.
+    pub fn into_result(self) -> Result<AlnFile, LoadAlnError> {
+        if self.report.has_errors() {
+            Err(LoadAlnError::Syntax(self.report))
+        } else {
+            Ok(self.ast)
+        }
+    }
+}
+
+/// Parse a file and resolve its `@INCLUDE`s (see [`crate::aln::include`]).
 pub fn parse_file(path: &str) -> Result<AlnFile, LoadAlnError> {
+    parse_file_recovering(path)?.into_result()
+}
+
+/// Parse a file without stopping at the first error. Blocks and entries that
+/// fail to parse are skipped up to the next `}`, `@`, `,` or `key:` boundary,
+/// so `Parsed::ast` holds everything else. Includes are resolved.
+pub fn parse_file_recovering(path: &str) -> Result<Parsed, LoadAlnError> {
+    parse_file_at(path, 0)
+}
+
+/// [`parse_file_recovering`] with spans starting at `base`, for loading
+/// several files into one offset space.
+pub(crate) fn parse_file_at(path: &str, base: usize) -> Result<Parsed, LoadAlnError> {
+    let src = fs::read_to_string(path)?;
+    Ok(include::resolve(parse_source(SourceFile::new(path, src).with_base(base))))
+}
+
+/// Parse a file as written, keeping `@INCLUDE` directives in the AST. Used by
+/// tools that rewrite the file itself.
+pub fn parse_file_unresolved(path: &str) -> Result<AlnFile, LoadAlnError> {
     let src = fs::read_to_string(path)?;
-    let tokens = lex(&src)?;
-    parse_tokens(&tokens).map_err(LoadAlnError::Parse)
+    parse_str(&src, Some(path))
+}
+
+/// File name shown in diagnostics for sources that did not come from a file.
+pub const DEFAULT_NAME: &str = "<string>";
+
+/// Parse in-memory source. `name` is shown in diagnostics and defaults to
+/// [`DEFAULT_NAME`]. `@INCLUDE` directives are kept as written: there is no
+/// directory to resolve them against.
+pub fn parse_str(src: &str, name: Option<&str>) -> Result<AlnFile, LoadAlnError> {
+    parse_source(SourceFile::new(name.unwrap_or(DEFAULT_NAME), src)).into_result()
+}
+
+/// [`parse_str`] over everything `reader` yields, e.g. an HTTP body or a
+/// Kafka payload.
+pub fn parse_reader(mut reader: impl Read, name: Option<&str>) -> Result<AlnFile, LoadAlnError> {
+    let mut src = String::new();
+    reader.read_to_string(&mut src)?;
+    parse_str(&src, name)
 }
 
-fn parse_tokens(tokens: &[Token]) -> Result<AlnFile, String> {
-    let mut p = Parser { tokens, pos: 0 };
-    p.parse_file()
+pub(crate) fn parse_source(file: SourceFile) -> Parsed {
+    let base = file.base();
+    let mut p = Parser {
+        lexer: Lexer::new(file.src()),
+        ahead: VecDeque::new(),
+        base,
+        consumed: 0,
+        prev_span: Span::default(),
+        depth: 0,
+        stray: Vec::new(),
+        value_path: Vec::new(),
+        trivia: ValueTrivia::default(),
+        diagnostics: Vec::new(),
+    };
+    let ast = p.parse_file();
+
+    let mut diagnostics: Vec<Diagnostic> = p
+        .lexer
+        .into_errors()
+        .into_iter()
+        .map(|e| {
+            let mut d = Diagnostic::from(e);
+            d.span = d.span.offset(base);
+            d
+        })
+        .collect();
+    diagnostics.extend(p.diagnostics);
+    diagnostics.sort_by_key(|d| d.span.start);
+    Parsed { ast, report: Report::new(file, diagnostics) }
 }
 
+/// Recursive-descent parser pulling tokens from a [`Lexer`] as it goes.
 struct Parser<'a> {
-    tokens: &'a [Token],
-    pos: usize,
+    lexer: Lexer<'a>,
+    /// Tokens read but not consumed yet: at most two, for the `key:` and
+    /// `name=` lookahead.
+    ahead: VecDeque<Token<'a>>,
+    /// Added to every span, see [`SourceFile::with_base`].
+    base: usize,
+    /// Tokens consumed so far, so `recover` can tell whether parsing moved.
+    consumed: usize,
+    prev_span: Span,
+    /// Groups currently open, see [`MAX_DEPTH`].
+    depth: usize,
+    /// Comments on tokens consumed without claiming them, e.g. before a `:`
+    /// or `,`. The next node to take comments gets them, so none are lost.
+    stray: Vec<Comment>,
+    /// Element and field indices from the entry or argument value being
+    /// parsed down to the current position.
+    value_path: Vec<usize>,
+    /// Trivia of that value, collected so far.
+    trivia: ValueTrivia,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl<'a> Parser<'a> {
-    fn parse_file(&mut self) -> Result<AlnFile, String> {
+    fn parse_file(&mut self) -> AlnFile {
         let mut items = Vec::new();
         while !self.eof() {
-            items.push(AlnItem::Block(self.parse_block()?));
+            if !self.peek_is(TokenKind::At) {
+                let d = self.unexpected("`@` block");
+                self.diagnostics.push(d);
+                while !self.eof() && !self.peek_is(TokenKind::At) {
+                    self.next();
+                }
+                continue;
+            }
+            let start = self.consumed;
+            match self.parse_block() {
+                Ok(block) => items.push(AlnItem::from_block(block)),
+                Err(d) => self.recover(d, start),
+            }
+            self.skip_comma();
         }
-        Ok(AlnFile { items })
+        let trailing_comments = self.take_comments();
+        AlnFile { items, trailing_comments }
     }
 
-    fn parse_block(&mut self) -> Result<Block, String> {
-        self.expect(TokenKind::At, "@")?;
-        let name = match self.next() {
-            Some(Token { kind: TokenKind::Identifier(id), .. }) => id.clone(),
-            other => {
-                return Err(format!("Expected identifier after '@', got {:?}", other));
-            }
-        };
-
-        let mut args = None;
-        if self.peek_is(TokenKind::LBrace) {
-            // no args, block starts
-        } else if let Some(Token { kind: TokenKind::Identifier(raw), .. }) = self.peek() {
-            // lightweight arg payload until '{'
-            let mut s = raw.clone();
-            self.next();
-            while let Some(t) = self.peek() {
-                if matches!(t.kind, TokenKind::LBrace) {
-                    break;
-                }
-                match &t.kind {
-                    TokenKind::Identifier(id) => {
-                        s.push(' ');
-                        s.push_str(id);
-                    }
-                    _ => {}
-                }
-                self.next();
-            }
-            args = Some(BlockArgs { raw: s });
+    fn parse_block(&mut self) -> Result<Block, Diagnostic> {
+        let comments = self.take_comments();
+        let start = self.expect(TokenKind::At, "`@`")?;
+        let (name, name_span) = self.expect_ident("block name after `@`")?;
+
+        if name == INCLUDE {
+            // `@INCLUDE 'path'` takes exactly one argument and has no body.
+            let arg_start = self.peek_span();
+            let (value, trivia) = self.parse_value_trivia()?;
+            let span = arg_start.to(self.prev_span());
+            let arg = BlockArg { name: None, value, trivia, span };
+            return Ok(Block {
+                name,
+                args: Some(BlockArgs { items: vec![arg], span }),
+                body: Vec::new(),
+                comments,
+                closing_comments: Vec::new(),
+                span: start.to(span),
+                name_span,
+                origin: None,
+            });
         }
 
-        self.expect(TokenKind::LBrace, "{")?;
+        let args = self.parse_block_args()?;
+        let (body, closing_comments, end) = self.nested(Self::parse_block_body)?;
+
+        Ok(Block {
+            name,
+            args,
+            body,
+            comments,
+            closing_comments,
+            span: start.to(end),
+            name_span,
+            origin: None,
+        })
+    }
+
+    /// `{ entries }` of a block, with the comments before `}` and the span of
+    /// `}`.
+    fn parse_block_body(&mut self) -> Result<(Vec<BlockEntry>, Vec<Comment>, Span), Diagnostic> {
+        self.expect(TokenKind::LBrace, "`{`")?;
         let mut body = Vec::new();
 
         while !self.peek_is(TokenKind::RBrace) && !self.eof() {
-            if self.peek_is(TokenKind::At) {
-                let nested = self.parse_block()?;
-                body.push(BlockEntry::NestedBlock(nested));
+            let start = self.consumed;
+            let entry = if self.peek_is(TokenKind::At) {
+                self.parse_block().map(BlockEntry::NestedBlock)
+            } else if self.peek_is(TokenKind::LBracket) {
+                self.parse_value_trivia().map(|(value, trivia)| match value {
+                    Value::Array(values) => BlockEntry::List(BareList { values, trivia }),
+                    _ => unreachable!("`[` starts an array"),
+                })
             } else {
-                body.push(self.parse_entry()?);
+                self.parse_entry()
+            };
+            match entry {
+                Ok(entry) => body.push(entry),
+                Err(d) => self.recover(d, start),
             }
+            self.skip_comma();
         }
 
-        self.expect(TokenKind::RBrace, "}")?;
+        let closing_comments = self.take_comments();
+        // The body loop only stops at `}` or end of input; an unclosed block is
+        // still returned so its entries are not lost.
+        let end = match self.expect(TokenKind::RBrace, "`}`") {
+            Ok(span) => span,
+            Err(d) => {
+                self.diagnostics.push(d);
+                self.prev_span()
+            }
+        };
+        Ok((body, closing_comments, end))
+    }
 
-        Ok(Block { name, args, body })
+    /// Positional values and `name=value` pairs up to the block's `{`.
+    fn parse_block_args(&mut self) -> Result<Option<BlockArgs>, Diagnostic> {
+        let start = self.peek_span();
+        let mut items = Vec::new();
+        while !self.peek_is(TokenKind::LBrace) && !self.eof() {
+            let arg_start = self.peek_span();
+            let named = matches!(self.peek_nth(1).map(|t| &t.kind), Some(TokenKind::Eq));
+            let name = if named {
+                let (name, _) = self.expect_ident("argument name")?;
+                self.next();
+                Some(name)
+            } else {
+                None
+            };
+            let (value, trivia) = self.parse_value_trivia()?;
+            items.push(BlockArg { name, value, trivia, span: arg_start.to(self.prev_span()) });
+            self.skip_comma();
+        }
+        if items.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(BlockArgs { items, span: start.to(self.prev_span()) }))
     }
 
-    fn parse_entry(&mut self) -> Result<BlockEntry, String> {
-        // key: value
-        let key = match self.next() {
-            Some(Token { kind: TokenKind::Identifier(id), .. }) => id.clone(),
-            other => return Err(format!("Expected identifier key, got {:?}", other)),
-        };
+    fn parse_entry(&mut self) -> Result<BlockEntry, Diagnostic> {
+        // key: value, or 'quoted key': value
+        let mut comments = self.take_comments();
+        let (key, key_span) = self.parse_key("key or `@` block")?;
 
-        self.expect(TokenKind::Colon, ":")?;
+        self.expect(TokenKind::Colon, &format!("`:` after key `{}`", key))?;
 
-        if self.peek_is(TokenKind::LBracket) {
-            let list = self.parse_list()?;
-            Ok(BlockEntry::KeyValue { key, value: Value::Array(list) })
-        } else {
-            let value = self.parse_value()?;
-            Ok(BlockEntry::KeyValue { key, value })
+        let value_start = self.peek_span();
+        let (value, mut trivia) = self.parse_value_trivia()?;
+        let value_span = value_start.to(self.prev_span());
+        // Comments between the key and its value go with the key's.
+        trivia.comments.retain(|c| {
+            if c.at.is_empty() {
+                comments.push(c.comment.clone());
+            }
+            !c.at.is_empty()
+        });
+        Ok(BlockEntry::KeyValue { key, value, comments, trivia, key_span, value_span })
+    }
+
+    /// An identifier or a quoted string, as in `'my key': 1`.
+    fn parse_key(&mut self, what: &str) -> Result<(String, Span), Diagnostic> {
+        match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::StringLiteral(_)) => match self.next() {
+                Some(Token { kind: TokenKind::StringLiteral(s), span, .. }) => Ok((s.into_owned(), span)),
+                _ => unreachable!("peeked a string"),
+            },
+            _ => self.expect_ident(what),
         }
     }
 
-    fn parse_list(&mut self) -> Result<Vec<Value>, String> {
-        self.expect(TokenKind::LBracket, "[")?;
+    fn parse_list(&mut self) -> Result<Vec<Value>, Diagnostic> {
+        self.expect(TokenKind::LBracket, "`[`")?;
         let mut values = Vec::new();
         while !self.peek_is(TokenKind::RBracket) && !self.eof() {
-            let v = self.parse_value()?;
-            values.push(v);
+            self.value_path.push(values.len());
+            let v = self.parse_value();
+            self.value_path.pop();
+            values.push(v?);
             if self.peek_is(TokenKind::Comma) {
                 self.next();
             }
         }
-        self.expect(TokenKind::RBracket, "]")?;
+        self.note_comments(Some(values.len()));
+        self.expect(TokenKind::RBracket, "`]`")?;
         Ok(values)
     }
 
-    fn parse_value(&mut self) -> Result<Value, String> {
-        match self.next() {
-            Some(Token { kind: TokenKind::StringLiteral(s), .. }) => Ok(Value::Str(s)),
-            Some(Token { kind: TokenKind::Identifier(id), .. }) => Ok(Value::Str(id)),
-            Some(Token { kind: TokenKind::BoolLiteral(b), .. }) => Ok(Value::Bool(b)),
-            Some(Token { kind: TokenKind::NumberLiteral(n), .. }) => {
-                let num: f64 = n.parse().map_err(|e| e.to_string())?;
-                Ok(Value::Number(num))
+    /// Inline map: `{ key: value, 'quoted key': value }`, commas optional.
+    fn parse_object(&mut self) -> Result<Vec<(String, Value)>, Diagnostic> {
+        self.expect(TokenKind::LBrace, "`{`")?;
+        let mut fields: Vec<(String, Value)> = Vec::new();
+        while !self.peek_is(TokenKind::RBrace) && !self.eof() {
+            self.note_comments(Some(fields.len()));
+            let (key, key_span) = self.parse_key("object key")?;
+            self.expect(TokenKind::Colon, &format!("`:` after key `{}`", key))?;
+            self.value_path.push(fields.len());
+            let value = self.parse_value();
+            self.value_path.pop();
+            let value = value?;
+            if fields.iter().any(|(k, _)| *k == key) {
+                self.diagnostics
+                    .push(Diagnostic::error(format!("duplicate key `{}` in object", key), key_span));
+            } else {
+                fields.push((key, value));
             }
-            Some(Token { kind: TokenKind::LBracket, .. }) => {
-                self.pos -= 1;
-                let list = self.parse_list()?;
-                Ok(Value::Array(list))
+            self.skip_comma();
+        }
+        self.note_comments(Some(fields.len()));
+        self.expect(TokenKind::RBrace, "`}`")?;
+        Ok(fields)
+    }
+
+    /// Parse the value of an entry or argument along with its trivia.
+    fn parse_value_trivia(&mut self) -> Result<(Value, ValueTrivia), Diagnostic> {
+        let value = self.parse_value();
+        let trivia = std::mem::take(&mut self.trivia);
+        Ok((value?, trivia))
+    }
+
+    /// Record the comments before the next token as trivia, at child `index`
+    /// of the current position or, with `None`, at the position itself.
+    fn note_comments(&mut self, index: Option<usize>) {
+        for comment in self.take_comments() {
+            let mut at = self.value_path.clone();
+            at.extend(index);
+            self.trivia.comments.push(InnerComment { at, comment });
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, Diagnostic> {
+        self.note_comments(None);
+        if self.peek_is(TokenKind::LBracket) {
+            return Ok(Value::Array(self.nested(Self::parse_list)?));
+        }
+        if self.peek_is(TokenKind::LBrace) {
+            return Ok(Value::Object(self.nested(Self::parse_object)?));
+        }
+        let is_value = matches!(
+            self.peek().map(|t| &t.kind),
+            Some(
+                TokenKind::StringLiteral(_)
+                    | TokenKind::Identifier(_)
+                    | TokenKind::BoolLiteral(_)
+                    | TokenKind::IntegerLiteral(_)
+                    | TokenKind::FloatLiteral(_)
+            )
+        );
+        if !is_value {
+            return Err(self.unexpected("value"));
+        }
+        let token = self.next().expect("peeked a value");
+        // Rawness only changes meaning where interpolation would see a `${`.
+        if let TokenKind::StringLiteral(s) = &token.kind {
+            if token.text.starts_with('r') && s.contains("${") {
+                self.trivia.raw.push(self.value_path.clone());
             }
-            other => Err(format!("Unexpected token in value: {:?}", other)),
         }
+        Ok(match token.kind {
+            TokenKind::StringLiteral(s) => Value::Str(s.into_owned()),
+            TokenKind::Identifier(_) => Value::Str(token.text.to_string()),
+            TokenKind::BoolLiteral(b) => Value::Bool(b),
+            TokenKind::IntegerLiteral(n) => Value::Integer(n),
+            TokenKind::FloatLiteral(n) => Value::Float(n),
+            _ => unreachable!("peeked a value"),
+        })
     }
 
-    fn peek(&self) -> Option<&Token> {
-        self.tokens.get(self.pos)
+    /// Run `parse` on the group opening at the next token, one level deeper.
+    /// Past [`MAX_DEPTH`] the group is skipped whole and reported instead.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, Diagnostic>) -> Result<T, Diagnostic> {
+        if self.depth >= MAX_DEPTH {
+            let span = self.peek_span();
+            self.skip_group();
+            return Err(Diagnostic::error(
+                format!("nesting too deep: at most {} levels of blocks, lists and objects", MAX_DEPTH),
+                span,
+            ));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
     }
 
-    fn peek_is(&self, kind: TokenKind) -> bool {
+    /// Skip the bracketed group opening at the next token, up to and
+    /// including the bracket that closes it.
+    fn skip_group(&mut self) {
+        let mut depth = 0usize;
+        loop {
+            match self.peek().map(|t| &t.kind) {
+                None | Some(TokenKind::Eof) => return,
+                Some(TokenKind::LBrace | TokenKind::LBracket) => depth += 1,
+                Some(TokenKind::RBrace | TokenKind::RBracket) if depth <= 1 => {
+                    if depth == 1 {
+                        self.next();
+                    }
+                    return;
+                }
+                Some(TokenKind::RBrace | TokenKind::RBracket) => depth -= 1,
+                _ => {}
+            }
+            self.next();
+        }
+    }
+
+    /// Read from the lexer until `n` tokens are buffered or input ends.
+    fn fill(&mut self, n: usize) {
+        while self.ahead.len() < n {
+            let Some(mut token) = self.lexer.next() else {
+                return;
+            };
+            if self.base > 0 {
+                token.span = token.span.offset(self.base);
+                for c in &mut token.leading {
+                    c.span = c.span.offset(self.base);
+                }
+            }
+            self.ahead.push_back(token);
+        }
+    }
+
+    fn peek(&mut self) -> Option<&Token<'a>> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Option<&Token<'a>> {
+        self.fill(n + 1);
+        self.ahead.get(n)
+    }
+
+    fn peek_is(&mut self, kind: TokenKind) -> bool {
         matches!(self.peek().map(|t| &t.kind), Some(k) if std::mem::discriminant(k) == std::mem::discriminant(&kind))
     }
 
-    fn next(&mut self) -> Option<&Token> {
-        let res = self.tokens.get(self.pos);
-        if res.is_some() {
-            self.pos += 1;
+    /// Span of the next token, or an empty span at end of input.
+    fn peek_span(&mut self) -> Span {
+        match self.peek() {
+            Some(t) => t.span,
+            // Past `Eof`, which was the last token consumed.
+            None => self.prev_span,
         }
-        res
     }
 
-    fn expect(&mut self, kind: TokenKind, what: &str) -> Result<(), String> {
-        match self.next() {
-            Some(Token { kind: k, .. }) if std::mem::discriminant(k) == std::mem::discriminant(&kind) => Ok(()),
-            other => Err(format!("Expected {}, got {:?}", what, other)),
+    /// Span of the most recently consumed token.
+    fn prev_span(&self) -> Span {
+        self.prev_span
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        self.fill(1);
+        let mut token = self.ahead.pop_front()?;
+        self.stray.append(&mut token.leading);
+        self.consumed += 1;
+        self.prev_span = token.span;
+        Some(token)
+    }
+
+    fn expect(&mut self, kind: TokenKind, what: &str) -> Result<Span, Diagnostic> {
+        if self.peek_is(kind) {
+            Ok(self.next().expect("peeked").span)
+        } else {
+            Err(self.unexpected(what))
         }
     }
 
-    fn eof(&self) -> bool {
-        self.pos >= self.tokens.len()
+    fn expect_ident(&mut self, what: &str) -> Result<(String, Span), Diagnostic> {
+        if matches!(self.peek().map(|t| &t.kind), Some(TokenKind::Identifier(_))) {
+            let token = self.next().expect("peeked");
+            Ok((token.text.to_string(), token.span))
+        } else {
+            Err(self.unexpected(what))
+        }
     }
-}
 
-/// High-level helper: load an ALN update plan from file and map to a model
-impl AlnUpdatePlan {
-    pub fn from_file(path: &str) -> Result<Self, LoadAlnError> {
-        let file = parse_file(path)?;
-        let mut components = None;
-        let mut interop = None;
-        let mut render = None;
-        let mut rego_exec = None;
-
-        for item in file.items {
-            if let AlnItem::Block(b) = item {
-                if b.name == "ALN_UPDATE_SYSTEM" {
-                    for entry in b.body {
-                        match entry {
-                            BlockEntry::KeyValue { key, value } if key == "version" => {
-                                // version is optional, may be missing
-                                let _ = value;
-                            }
-                            BlockEntry::NestedBlock(nb) if nb.name == "SEPARATE" => {
-                                components = Some(map_components(nb)?);
-                            }
-                            BlockEntry::NestedBlock(nb) if nb.name == "INTEROP" => {
-                                interop = Some(map_interop(nb)?);
-                            }
-                            BlockEntry::NestedBlock(nb) if nb.name == "RENDER_IN_FRAME" => {
-                                render = Some(map_render(nb)?);
-                            }
-                            BlockEntry::NestedBlock(nb) if nb.name == "EXEC_REGO_POLICY" => {
-                                rego_exec = Some(map_rego_exec(nb)?);
-                            }
-                            _ => {}
-                        }
-                    }
+    /// "expected X, found Y" pointing at the next token.
+    fn unexpected(&mut self, expected: &str) -> Diagnostic {
+        let found = self
+            .peek()
+            .map(|t| t.describe())
+            .unwrap_or_else(|| TokenKind::Eof.describe());
+        Diagnostic::error(format!("expected {}, found {}", expected, found), self.peek_span())
+    }
+
+    fn eof(&mut self) -> bool {
+        matches!(self.peek().map(|t| &t.kind), None | Some(TokenKind::Eof))
+    }
+
+    /// Record `diagnostic` and skip ahead to a point where parsing can resume.
+    fn recover(&mut self, diagnostic: Diagnostic, start: usize) {
+        self.diagnostics.push(diagnostic);
+        if self.consumed == start {
+            // Always make progress, or the caller's loop never ends.
+            self.next();
+        }
+        self.synchronize();
+    }
+
+    /// Skip tokens until the next `@`, `}`, `key:` or end of input, consuming a
+    /// separating `,`. Bracketed groups are skipped whole so a `}` inside them
+    /// does not end the enclosing block early.
+    fn synchronize(&mut self) {
+        let mut depth = 0usize;
+        loop {
+            let ident = match self.peek().map(|t| &t.kind) {
+                None | Some(TokenKind::Eof) => return,
+                Some(TokenKind::LBrace | TokenKind::LBracket) => {
+                    depth += 1;
+                    false
                 }
+                Some(TokenKind::RBrace | TokenKind::RBracket) if depth > 0 => {
+                    depth -= 1;
+                    false
+                }
+                Some(TokenKind::RBrace | TokenKind::At) if depth == 0 => return,
+                Some(TokenKind::Comma) if depth == 0 => {
+                    self.next();
+                    return;
+                }
+                Some(TokenKind::Identifier(_) | TokenKind::StringLiteral(_)) => depth == 0,
+                _ => false,
+            };
+            if ident && self.at_entry_start() {
+                return;
             }
+            self.next();
         }
+    }
 
-        let components = components.ok_or_else(|| LoadAlnError::Parse("Missing @SEPARATE components".into()))?;
-        let interop = interop.ok_or_else(|| LoadAlnError::Parse("Missing @INTEROP block".into()))?;
-        let render = render.ok_or_else(|| LoadAlnError::Parse("Missing @RENDER_IN_FRAME block".into()))?;
-        let rego_exec = rego_exec.ok_or_else(|| LoadAlnError::Parse("Missing @EXEC_REGO_POLICY block".into()))?;
+    fn at_entry_start(&mut self) -> bool {
+        matches!(self.peek_nth(1).map(|t| &t.kind), Some(TokenKind::Colon))
+    }
 
-        Ok(Self {
-            version: "1.0.1.7".into(),
-            components,
-            interop,
-            render,
-            rego_exec,
-        })
+    /// Commas between entries and blocks are optional separators.
+    fn skip_comma(&mut self) {
+        if self.peek_is(TokenKind::Comma) {
+            self.next();
+        }
+    }
+
+    /// Take the comment trivia attached to the next token, and any stray
+    /// comments before it, leaving both empty so the same comments are never
+    /// claimed by two AST nodes.
+    fn take_comments(&mut self) -> Vec<Comment> {
+        self.fill(1);
+        let mut comments = std::mem::take(&mut self.stray);
+        if let Some(t) = self.ahead.front_mut() {
+            comments.append(&mut t.leading);
+        }
+        comments
     }
 }
 
-fn map_components(block: Block) -> Result<AlnComponentConfig, LoadAlnError> {
-    let mut game_engine = None;
-    let mut ai_chat_ui = None;
-    let mut renderers = Vec::new();
-
-    for entry in block.body {
-        if let BlockEntry::KeyValue { key, value } = entry {
-            match (key.as_str(), value) {
-                ("game_engine", Value::Str(s)) => game_engine = Some(s),
-                ("ai_chat_ui", Value::Str(s)) => ai_chat_ui = Some(s),
-                ("renderers", Value::Array(arr)) => {
-                    for v in arr {
-                        if let Value::Str(s) = v {
-                            renderers.push(s);
-                        }
-                    }
-                }
-                _ => {}
-            }
+/// High-level helper: load an ALN update plan from file and map to a model.
+///
+/// The `from_*` constructors are lenient: keys and blocks the schema does not
+/// know are ignored. The `load_*` variants take a [`Strictness`] and also
+/// return the warnings found, as a report that renders against the plan.
+///
+/// A file declaring several update systems is an error here; load it as an
+/// [`AlnPlanSet`] instead.
+impl AlnUpdatePlan {
+    pub fn from_file(path: &str) -> Result<Self, LoadAlnError> {
+        Self::load_file(path, Strictness::Lenient).map(|(plan, _)| plan)
+    }
+
+    /// Load a plan from in-memory source; `name` is used in diagnostics (see
+    /// [`parse_str`]). `@INCLUDE` is an error here, since an uploaded plan
+    /// must not read local files.
+    pub fn from_str(src: &str, name: Option<&str>) -> Result<Self, LoadAlnError> {
+        Self::load_str(src, name, Strictness::Lenient).map(|(plan, _)| plan)
+    }
+
+    pub fn from_reader(mut reader: impl Read, name: Option<&str>) -> Result<Self, LoadAlnError> {
+        let mut src = String::new();
+        reader.read_to_string(&mut src)?;
+        Self::from_str(&src, name)
+    }
+
+    /// Load a base plan merged with overlays, in order (see
+    /// [`crate::aln::merge`]). Overlays are refused when the merged plan sets
+    /// `merge_sources: false`.
+    pub fn from_files(paths: &[&str]) -> Result<Self, LoadAlnError> {
+        Self::load_files(paths, Strictness::Lenient).map(|(plan, _)| plan)
+    }
+
+    pub fn load_file(path: &str, strictness: Strictness) -> Result<(Self, Report), LoadAlnError> {
+        Self::from_parsed(parse_file_recovering(path)?, strictness)
+    }
+
+    pub fn load_str(src: &str, name: Option<&str>, strictness: Strictness) -> Result<(Self, Report), LoadAlnError> {
+        Self::from_parsed(parse_source(SourceFile::new(name.unwrap_or(DEFAULT_NAME), src)), strictness)
+    }
+
+    pub fn load_files(paths: &[&str], strictness: Strictness) -> Result<(Self, Report), LoadAlnError> {
+        Self::from_parsed(merged(paths)?, strictness)
+    }
+
+    /// Interpolate a parsed plan, validate it against
+    /// [`AlnUpdatePlan::schema`] and map it. Warnings are returned with the
+    /// plan, or included in the report when loading fails.
+    pub(crate) fn from_parsed(parsed: Parsed, strictness: Strictness) -> Result<(Self, Report), LoadAlnError> {
+        let (ast, report) = resolved(parsed)?;
+        let invalid = |diagnostics| LoadAlnError::Schema(Report { diagnostics, ..report.clone() });
+        let systems = systems(&ast).map_err(invalid)?;
+        if let [_, (_, second), ..] = systems[..] {
+            let d = Diagnostic::error(format!("this file declares {} update systems", systems.len()), second.name_span)
+                .with_note("load it as an `AlnPlanSet` to get the plan of each");
+            return Err(invalid(vec![d]));
+        }
+        let mut diagnostics = unknown_items(&ast, strictness);
+        let mut failed = diagnostics.iter().any(|d| d.severity == Severity::Error);
+        let plan = collect(Self::from_root(systems[0].1, strictness), &mut diagnostics, &mut failed);
+        diagnostics.sort_by_key(|d| d.span.start);
+        match plan {
+            Some(plan) if !failed => Ok((plan, Report { diagnostics, ..report })),
+            _ => Err(invalid(diagnostics)),
         }
     }
 
-    Ok(AlnComponentConfig {
-        game_engine: game_engine.unwrap_or_default(),
-        ai_chat_ui: ai_chat_ui.unwrap_or_default(),
-        renderers,
-    })
+    /// Validate and map one `@ALN_UPDATE_SYSTEM` block.
+    fn from_root(root: &Block, strictness: Strictness) -> Result<(Self, Vec<Diagnostic>), Vec<Diagnostic>> {
+        // The version decides how the rest of the plan is read, so a plan
+        // from a newer orchestrator is rejected before its keys are checked.
+        let (version, span) = version::declared(root).map_err(|d| vec![d])?;
+        let undeclared = span.is_none().then(|| version::undeclared(root));
+        let with_warning = |mut diagnostics: Vec<Diagnostic>| {
+            diagnostics.splice(0..0, undeclared.clone());
+            diagnostics
+        };
+        map_block(&Self::schema(), root, strictness, ("version", Value::Str(version.to_string())))
+            .map(|(plan, diagnostics)| (plan, with_warning(diagnostics)))
+            .map_err(with_warning)
+    }
 }
 
-fn map_interop(block: Block) -> Result<AlnInteropConfig, LoadAlnError> {
-    let mut cross_link = None;
-    let mut maintain_func = None;
-    let mut enable_lan = None;
-
-    for entry in block.body {
-        if let BlockEntry::KeyValue { key, value } = entry {
-            match (key.as_str(), value) {
-                ("cross_link", Value::Str(s)) => cross_link = Some(s),
-                ("maintain_func", Value::Bool(b)) => maintain_func = Some(b),
-                ("enable_lan", Value::Str(s)) => enable_lan = Some(s),
-                _ => {}
+/// Loaders for files that declare several update systems or a `@META`
+/// block, with the same variants as the [`AlnUpdatePlan`] ones. A problem in
+/// any system fails the load; all of them are reported.
+impl AlnPlanSet {
+    pub fn from_file(path: &str) -> Result<Self, LoadAlnError> {
+        Self::load_file(path, Strictness::Lenient).map(|(set, _)| set)
+    }
+
+    pub fn from_str(src: &str, name: Option<&str>) -> Result<Self, LoadAlnError> {
+        Self::load_str(src, name, Strictness::Lenient).map(|(set, _)| set)
+    }
+
+    pub fn from_files(paths: &[&str]) -> Result<Self, LoadAlnError> {
+        Self::load_files(paths, Strictness::Lenient).map(|(set, _)| set)
+    }
+
+    pub fn load_file(path: &str, strictness: Strictness) -> Result<(Self, Report), LoadAlnError> {
+        Self::from_parsed(parse_file_recovering(path)?, strictness)
+    }
+
+    pub fn load_str(src: &str, name: Option<&str>, strictness: Strictness) -> Result<(Self, Report), LoadAlnError> {
+        Self::from_parsed(parse_source(SourceFile::new(name.unwrap_or(DEFAULT_NAME), src)), strictness)
+    }
+
+    /// Overlays merge system by system: blocks only merge when their `name`
+    /// arguments match.
+    pub fn load_files(paths: &[&str], strictness: Strictness) -> Result<(Self, Report), LoadAlnError> {
+        Self::from_parsed(merged(paths)?, strictness)
+    }
+
+    pub(crate) fn from_parsed(parsed: Parsed, strictness: Strictness) -> Result<(Self, Report), LoadAlnError> {
+        let (ast, report) = resolved(parsed)?;
+        let mut diagnostics = unknown_items(&ast, strictness);
+        let mut failed = diagnostics.iter().any(|d| d.severity == Severity::Error);
+
+        let mut systems = Vec::new();
+        let roots = self::systems(&ast).map(|roots| (roots, Vec::new()));
+        for (name, root) in collect(roots, &mut diagnostics, &mut failed).unwrap_or_default() {
+            if let Some(plan) = collect(AlnUpdatePlan::from_root(root, strictness), &mut diagnostics, &mut failed) {
+                systems.push(AlnSystem { name, plan });
             }
         }
+
+        let metas: Vec<&Block> = ast
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                AlnItem::Meta(b) => Some(b),
+                AlnItem::Block(_) => None,
+            })
+            .collect();
+        if let [_, second, ..] = metas[..] {
+            diagnostics.push(Diagnostic::error(format!("@{} may only appear once", META), second.name_span));
+            failed = true;
+        }
+        let meta = match metas.first() {
+            Some(b) => collect(map_block(&AlnMeta::schema(), b, strictness, None), &mut diagnostics, &mut failed),
+            None => None,
+        };
+
+        if failed {
+            diagnostics.sort_by_key(|d| d.span.start);
+            return Err(LoadAlnError::Schema(Report { diagnostics, ..report }));
+        }
+        Ok((AlnPlanSet { meta, systems }, Report { diagnostics, ..report }))
+    }
+}
+
+/// The value of `result`, moving its diagnostics to `diagnostics` and noting
+/// whether it failed.
+fn collect<T>(
+    result: Result<(T, Vec<Diagnostic>), Vec<Diagnostic>>,
+    diagnostics: &mut Vec<Diagnostic>,
+    failed: &mut bool,
+) -> Option<T> {
+    match result {
+        Ok((value, found)) => {
+            diagnostics.extend(found);
+            Some(value)
+        }
+        Err(found) => {
+            diagnostics.extend(found);
+            *failed = true;
+            None
+        }
     }
+}
 
-    Ok(AlnInteropConfig {
-        cross_link: cross_link.unwrap_or_default(),
-        maintain_func: maintain_func.unwrap_or(true),
-        enable_lan: enable_lan.unwrap_or_default(),
-    })
+/// Parse and merge `paths` for the `load_files` loaders, refusing overlays
+/// into a system that sets `merge_sources: false`. The setting is read from
+/// the layers merged so far, so an overlay cannot turn it back on.
+fn merged(paths: &[&str]) -> Result<Parsed, LoadAlnError> {
+    let merged = merge::merge_files_with(paths, |body, next| {
+        let (path, span) = merge_disabled(body)?;
+        let d = Diagnostic::error(format!("`{}` is false, so overlays cannot be applied", path), span)
+            .with_note(format!("refusing to merge {}", next));
+        Some(d)
+    })?;
+    if merged.report.has_errors() {
+        return Err(LoadAlnError::Syntax(merged.report));
+    }
+    Ok(merged.into_parsed())
 }
 
-fn map_render(block: Block) -> Result<AlnRenderConfig, LoadAlnError> {
-    let mut mode = None;
-    let mut merge_sources = None;
-    let mut playable_platforms = Vec::new();
-
-    for entry in block.body {
-        if let BlockEntry::KeyValue { key, value } = entry {
-            match (key.as_str(), value) {
-                ("mode", Value::Str(s)) => mode = Some(s),
-                ("merge_sources", Value::Bool(b)) => merge_sources = Some(b),
-                ("playable_platforms", Value::Array(arr)) => {
-                    for v in arr {
-                        if let Value::Str(s) = v {
-                            playable_platforms.push(s);
-                        }
+/// Path and span of the first `merge_sources: false` of an update system in
+/// the top-level `body`.
+fn merge_disabled(body: &[BlockEntry]) -> Option<(String, Span)> {
+    fn blocks(body: &[BlockEntry]) -> impl Iterator<Item = &Block> {
+        body.iter().filter_map(|e| match e {
+            BlockEntry::NestedBlock(b) => Some(b),
+            _ => None,
+        })
+    }
+    for root in blocks(body).filter(|b| b.name == AlnUpdatePlan::schema().name) {
+        for render in blocks(&root.body).filter(|b| merge::segment(b) == "RENDER_IN_FRAME") {
+            for entry in &render.body {
+                if let BlockEntry::KeyValue { key, value: Value::Bool(false), value_span, .. } = entry {
+                    if key == "merge_sources" {
+                        return Some((format!("{}.RENDER_IN_FRAME.merge_sources", merge::segment(root)), *value_span));
                     }
                 }
-                _ => {}
             }
         }
     }
+    None
+}
 
-    Ok(AlnRenderConfig {
-        mode: mode.unwrap_or_default(),
-        merge_sources: merge_sources.unwrap_or(true),
-        playable_platforms,
-    })
+/// Check a parsed file for syntax errors and interpolate it.
+fn resolved(parsed: Parsed) -> Result<(AlnFile, Report), LoadAlnError> {
+    let Parsed { mut ast, report } = parsed;
+    if report.has_errors() {
+        return Err(LoadAlnError::Syntax(report));
+    }
+    let mut unresolved = include::unresolved(&ast);
+    unresolved.extend(interpolate::interpolate_env(&mut ast));
+    if !unresolved.is_empty() {
+        return Err(LoadAlnError::Resolve(Report { diagnostics: unresolved, ..report }));
+    }
+    Ok((ast, report))
 }
 
-fn map_rego_exec(block: Block) -> Result<AlnRegoExecConfig, LoadAlnError> {
-    let mut always_active = None;
-    let mut policy = None;
-    let mut features = Vec::new();
-
-    for entry in block.body {
-        if let BlockEntry::KeyValue { key, value } = entry {
-            match (key.as_str(), value) {
-                ("always_active", Value::Bool(b)) => always_active = Some(b),
-                ("policy", Value::Str(s)) => policy = Some(s),
-                ("features", Value::Array(arr)) => {
-                    for v in arr {
-                        if let Value::Str(s) = v {
-                            features.push(s);
-                        }
-                    }
+/// The update systems of `ast` with their names. Reports a missing root,
+/// names that are not strings or are used twice, and unnamed systems in a
+/// file that has several.
+fn systems(ast: &AlnFile) -> Result<Vec<(Option<String>, &Block)>, Vec<Diagnostic>> {
+    let schema = AlnUpdatePlan::schema();
+    let roots: Vec<&Block> = ast
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            AlnItem::Block(b) if b.name == schema.name => Some(b),
+            _ => None,
+        })
+        .collect();
+    if roots.is_empty() {
+        let message = format!("missing @{} block", schema.name);
+        // Point at a misspelt root block, if there is one.
+        let typo = ast.items.iter().find_map(|item| {
+            let b = item.block();
+            schema::did_you_mean(&b.name, [schema.name]).map(|hint| (b.name_span, hint))
+        });
+        let d = match typo {
+            Some((span, hint)) => Diagnostic::error(message, span).with_note(hint),
+            None => Diagnostic::error(message, Span::default()),
+        };
+        return Err(vec![d]);
+    }
+
+    let mut systems: Vec<(Option<String>, &Block)> = Vec::new();
+    let mut errors = Vec::new();
+    for root in &roots {
+        let arg = root.args.iter().flat_map(|a| &a.items).find(|a| a.name.as_deref() == Some(merge::NAME));
+        let name = match arg {
+            None if roots.len() > 1 => {
+                errors.push(
+                    Diagnostic::error(format!("@{} needs a name when a file declares several", schema.name), root.name_span)
+                        .with_note(format!("add one like `@{} {}='web' {{ ... }}`", schema.name, merge::NAME)),
+                );
+                None
+            }
+            None => None,
+            Some(arg) => match &arg.value {
+                Value::Str(name) if systems.iter().any(|(n, _)| n.as_ref() == Some(name)) => {
+                    errors.push(Diagnostic::error(format!("update system `{}` is declared twice", name), arg.span));
+                    None
                 }
-                _ => {}
+                Value::Str(name) => Some(name.clone()),
+                _ => {
+                    errors.push(Diagnostic::error(format!("the `{}` of @{} must be a string", merge::NAME, schema.name), arg.span));
+                    None
+                }
+            },
+        };
+        systems.push((name, root));
+    }
+    if errors.is_empty() {
+        Ok(systems)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Top-level blocks other than update systems, `@META`, `@VARS` and
+/// `@INCLUDE`, reported like unknown nested blocks: as errors when
+/// `strictness` is strict, otherwise as warnings. A file without an update
+/// system is left to [`systems`] to report.
+fn unknown_items(ast: &AlnFile, strictness: Strictness) -> Vec<Diagnostic> {
+    let schema = AlnUpdatePlan::schema();
+    let known = [schema.name, META, interpolate::VARS, INCLUDE];
+    let blocks = ast.items.iter().map(AlnItem::block);
+    if !blocks.clone().any(|b| b.name == schema.name) {
+        return Vec::new();
+    }
+    let severity = match strictness {
+        Strictness::Strict => Severity::Error,
+        Strictness::Lenient => Severity::Warning,
+    };
+    blocks
+        .filter(|b| !known.contains(&b.name.as_str()))
+        .map(|b| {
+            let hint = match schema.owner_of_block(&b.name) {
+                Some(owner) => Some(format!("@{} belongs in @{}", b.name, owner.name)),
+                None => schema::did_you_mean(&b.name, known)
+                    .or_else(|| schema::did_you_mean(&b.name, schema.block_names())),
+            };
+            let mut d = Diagnostic::error(format!("unknown top-level block @{}", b.name), b.name_span);
+            d.severity = severity;
+            match hint {
+                Some(hint) => d.with_note(hint),
+                None => d,
             }
+        })
+        .collect()
+}
+
+/// Validate `block` against `schema` and map it, with `set` replacing the
+/// field of the same name. Returns the value and its warnings, or every
+/// problem found, warnings included.
+fn map_block<T: DeserializeOwned>(
+    schema: &BlockSchema,
+    block: &Block,
+    strictness: Strictness,
+    set: impl Into<Option<(&'static str, Value)>>,
+) -> Result<(T, Vec<Diagnostic>), Vec<Diagnostic>> {
+    let (validated, warnings) = schema.validate_with(block, strictness);
+    let mut diagnostics: Vec<Diagnostic> =
+        warnings.into_iter().map(|w| w.into_diagnostic(Severity::Warning)).collect();
+    let mut validated = match validated {
+        Ok(validated) => validated,
+        Err(errors) => {
+            diagnostics.extend(errors.into_iter().map(Diagnostic::from));
+            diagnostics.sort_by_key(|d| d.span.start);
+            return Err(diagnostics);
         }
+    };
+    if let Some((key, value)) = set.into() {
+        validated.fields.retain(|(k, _)| k != key);
+        validated.fields.insert(0, (key.to_string(), value));
     }
-
-    Ok(AlnRegoExecConfig {
-        always_active: always_active.unwrap_or(true),
-        policy: policy.unwrap_or_default(),
-        features,
-    })
+    // The schema guarantees every key has the right type, so this only
+    // fails if the model and its schema disagree.
+    let value = de::from_value(&validated.to_value())
+        .map_err(|e| vec![Diagnostic::error(e.to_string(), block.name_span)])?;
+    Ok((value, diagnostics))
 }
@@ -0,0 +1,388 @@
+//! Canonical ALN output.
+//!
+//! Style rules: two-space indentation, one entry per line with commas between
+//! entries (none after the last), a blank line between top-level blocks, and
+//! single-quoted strings. Block arguments that are plain identifiers stay bare.
+//! Strings with newlines use `'''`; strings with backslashes use raw `r'...'`
+//! when that needs no escaping and they hold no `${`, which would no longer be
+//! interpolated. Strings written raw stay raw. Arrays and objects stay on one line when they
+//! fit in [`MAX_WIDTH`] columns, otherwise each element goes on its own line.
+//! Comments are kept above the node they belong to; a comment inside an array
+//! or object puts it on several lines, and comments among block arguments go
+//! above the block.
+
+use crate::aln::ast::*;
+use crate::aln::include::INCLUDE;
+use std::fmt::{self, Write};
+
+/// Line width above which arrays and objects are broken over several lines.
+pub const MAX_WIDTH: usize = 80;
+
+const INDENT: &str = "  ";
+
+/// Render a file in canonical style, ending with a newline.
+pub fn print(file: &AlnFile) -> String {
+    let mut p = Printer::default();
+    for (i, item) in file.items.iter().enumerate() {
+        if i > 0 {
+            p.out.push('\n');
+        }
+        p.block(item.block(), 0);
+        p.out.push('\n');
+    }
+    if !file.trailing_comments.is_empty() && !file.items.is_empty() {
+        p.out.push('\n');
+    }
+    p.comments(&file.trailing_comments, 0);
+    p.out
+}
+
+/// Render a value on a single line (strings may still contain newlines).
+pub fn print_value(value: &Value) -> String {
+    let mut p = Printer::default();
+    p.value(value, &Notes::default(), &mut Vec::new(), 0, usize::MAX);
+    p.out
+}
+
+/// A key as written in source: bare when it is a valid identifier, quoted
+/// otherwise.
+pub fn print_key(key: &str) -> String {
+    if is_identifier(key) {
+        key.to_string()
+    } else {
+        quote(key)
+    }
+}
+
+pub fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    let valid = match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        }
+        _ => false,
+    };
+    valid && s != "true" && s != "false"
+}
+
+/// Quote a string using the canonical style described in the module docs.
+pub fn quote(s: &str) -> String {
+    let has_control = s.chars().any(|c| c.is_control() && c != '\n' && c != '\t');
+    // A raw string is not interpolated, so it would change what `${` means.
+    let can_be_raw = s.contains('\\') && !has_control && !s.contains("${");
+    if s.contains('\n') {
+        if can_be_raw && !s.contains("'''") && !s.ends_with('\'') {
+            return format!("r'''\n{}'''", s);
+        }
+        let mut out = String::from("'''\n");
+        let chars: Vec<char> = s.chars().collect();
+        for (i, &c) in chars.iter().enumerate() {
+            match c {
+                // A quote is only ambiguous when it could close the string.
+                '\'' if chars[i + 1..].starts_with(&['\'', '\'']) || i + 1 == chars.len() => {
+                    out.push_str("\\'")
+                }
+                '\n' | '\t' | '\'' => out.push(c),
+                _ => escape_into(&mut out, c),
+            }
+        }
+        out.push_str("'''");
+        return out;
+    }
+    if can_be_raw && !s.contains('\'') {
+        return format!("r'{}'", s);
+    }
+    let mut out = String::from("'");
+    for c in s.chars() {
+        match c {
+            '\'' => out.push_str("\\'"),
+            _ => escape_into(&mut out, c),
+        }
+    }
+    out.push('\'');
+    out
+}
+
+/// Quote a string as a raw string, adding `#` marks until nothing in it can
+/// close the string early.
+pub fn raw_quote(s: &str) -> String {
+    let (quote, newline) = match (s.contains('\''), s.contains('"')) {
+        _ if s.contains('\n') => ("'''", "\n"),
+        (false, _) => ("'", ""),
+        (true, false) => ("\"", ""),
+        // Both quotes: the `'''` form, which drops the newline after it.
+        (true, true) => ("'''", "\n"),
+    };
+    let mut hashes = String::new();
+    loop {
+        let close = format!("{}{}", quote, hashes);
+        // The lexer ends the string at the first `close`.
+        if format!("{}{}", s, close).find(&close) == Some(s.len()) {
+            return format!("r{}{}{}{}{}", hashes, quote, newline, s, close);
+        }
+        hashes.push('#');
+    }
+}
+
+fn escape_into(out: &mut String, c: char) {
+    match c {
+        '\\' => out.push_str("\\\\"),
+        '\n' => out.push_str("\\n"),
+        '\t' => out.push_str("\\t"),
+        '\r' => out.push_str("\\r"),
+        '\0' => out.push_str("\\0"),
+        c if c.is_control() => {
+            let _ = write!(out, "\\u{{{:x}}}", c as u32);
+        }
+        c => out.push(c),
+    }
+}
+
+fn float(n: f64) -> String {
+    // `{:?}` keeps a `.0` on whole numbers, so the value re-lexes as a float.
+    format!("{:?}", n)
+}
+
+#[derive(Default)]
+struct Printer {
+    out: String,
+}
+
+/// The trivia of the value being printed, see [`ValueTrivia`].
+#[derive(Default)]
+struct Notes<'a> {
+    comments: Vec<&'a InnerComment>,
+    raw: &'a [Vec<usize>],
+}
+
+impl<'a> Notes<'a> {
+    /// Split `trivia` into the comments that have a place inside `value` and
+    /// the rest, which are printed above it so that none are lost.
+    fn place(value: &Value, trivia: &'a ValueTrivia) -> (Self, Vec<&'a Comment>) {
+        let (inside, above): (Vec<_>, Vec<_>) = trivia.comments.iter().partition(|c| holds(value, &c.at));
+        (Notes { comments: inside, raw: &trivia.raw }, above.into_iter().map(|c| &c.comment).collect())
+    }
+
+    fn is_raw(&self, at: &[usize]) -> bool {
+        self.raw.iter().any(|r| r == at)
+    }
+
+    fn at<'s>(&'s self, at: &'s [usize]) -> impl Iterator<Item = &'a Comment> + 's {
+        self.comments.iter().filter(move |c| c.at == at).map(|c| &c.comment)
+    }
+
+    /// Whether there are comments below `at`, which rules out a single line.
+    fn within(&self, at: &[usize]) -> bool {
+        self.comments.iter().any(|c| c.at.len() > at.len() && c.at.starts_with(at))
+    }
+}
+
+/// Whether `at` names an element or field of `value`, or its closing bracket.
+fn holds(value: &Value, at: &[usize]) -> bool {
+    let Some((&i, rest)) = at.split_first() else {
+        return false;
+    };
+    let (len, child) = match value {
+        Value::Array(items) => (items.len(), items.get(i)),
+        Value::Object(fields) => (fields.len(), fields.get(i).map(|(_, v)| v)),
+        _ => return false,
+    };
+    match child {
+        _ if rest.is_empty() => i <= len,
+        Some(child) => holds(child, rest),
+        None => false,
+    }
+}
+
+impl Printer {
+    fn indent(&mut self, depth: usize) {
+        for _ in 0..depth {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    /// Current column, used to decide whether a value fits on the line.
+    fn column(&self) -> usize {
+        self.out.len() - self.out.rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    fn comments<'c>(&mut self, comments: impl IntoIterator<Item = &'c Comment>, depth: usize) {
+        for c in comments {
+            self.indent(depth);
+            self.out.push_str(&c.text);
+            self.out.push('\n');
+        }
+    }
+
+    fn block(&mut self, block: &Block, depth: usize) {
+        self.comments(&block.comments, depth);
+        let args = block.args.iter().flat_map(|args| &args.items);
+        self.comments(args.flat_map(|arg| &arg.trivia.comments).map(|c| &c.comment), depth);
+        self.indent(depth);
+        self.out.push('@');
+        self.out.push_str(&block.name);
+        if let Some(args) = &block.args {
+            for arg in &args.items {
+                self.out.push(' ');
+                if let Some(name) = &arg.name {
+                    self.out.push_str(name);
+                    self.out.push('=');
+                }
+                let notes = Notes { comments: Vec::new(), raw: &arg.trivia.raw };
+                match &arg.value {
+                    // `@SEPARATE components {`: identifier-like arguments stay
+                    // bare, except include paths.
+                    Value::Str(s) if is_identifier(s) && block.name != INCLUDE && !notes.is_raw(&[]) => {
+                        self.out.push_str(s)
+                    }
+                    v => self.value(v, &notes, &mut Vec::new(), depth, usize::MAX),
+                }
+            }
+        }
+        if block.name == INCLUDE {
+            return;
+        }
+        if block.body.is_empty() && block.closing_comments.is_empty() {
+            self.out.push_str(" {}");
+            return;
+        }
+        self.out.push_str(" {\n");
+        for (i, entry) in block.body.iter().enumerate() {
+            self.entry(entry, depth + 1);
+            if i + 1 < block.body.len() {
+                self.out.push(',');
+            }
+            self.out.push('\n');
+        }
+        self.comments(&block.closing_comments, depth + 1);
+        self.indent(depth);
+        self.out.push('}');
+    }
+
+    fn entry(&mut self, entry: &BlockEntry, depth: usize) {
+        match entry {
+            BlockEntry::KeyValue { key, value, comments, trivia, .. } => {
+                let (notes, above) = Notes::place(value, trivia);
+                self.comments(comments.iter().chain(above), depth);
+                self.indent(depth);
+                self.out.push_str(&print_key(key));
+                self.out.push_str(": ");
+                self.value(value, &notes, &mut Vec::new(), depth, MAX_WIDTH);
+            }
+            BlockEntry::NestedBlock(b) => self.block(b, depth),
+            BlockEntry::List(list) => {
+                let value = Value::Array(list.values.clone());
+                let (notes, above) = Notes::place(&value, &list.trivia);
+                self.comments(above, depth);
+                self.indent(depth);
+                self.value(&value, &notes, &mut Vec::new(), depth, MAX_WIDTH);
+            }
+        }
+    }
+
+    /// `at` is the position of `value` within the entry's value, for `notes`.
+    fn value(&mut self, value: &Value, notes: &Notes, at: &mut Vec<usize>, depth: usize, width: usize) {
+        match value {
+            Value::Str(s) if notes.is_raw(at) => self.out.push_str(&raw_quote(s)),
+            Value::Str(s) => self.out.push_str(&quote(s)),
+            Value::Bool(b) => self.out.push_str(if *b { "true" } else { "false" }),
+            Value::Integer(n) => self.out.push_str(&n.to_string()),
+            Value::Float(n) => self.out.push_str(&float(*n)),
+            Value::Array(items) => {
+                let fits = self.flat(value, notes, at).filter(|flat| !notes.within(at) && self.column() + flat.len() <= width);
+                match fits {
+                    Some(flat) => self.out.push_str(&flat),
+                    None => self.multiline(('[', ']'), items.iter().map(|v| (None, v)), notes, at, depth, width),
+                }
+            }
+            Value::Object(fields) => {
+                let fits = self.flat(value, notes, at).filter(|flat| !notes.within(at) && self.column() + flat.len() <= width);
+                match fits {
+                    Some(flat) => self.out.push_str(&flat),
+                    None => self.multiline(
+                        ('{', '}'),
+                        fields.iter().map(|(k, v)| (Some(k.as_str()), v)),
+                        notes,
+                        at,
+                        depth,
+                        width,
+                    ),
+                }
+            }
+        }
+    }
+
+    fn multiline<'v>(
+        &mut self,
+        (open, close): (char, char),
+        items: impl ExactSizeIterator<Item = (Option<&'v str>, &'v Value)>,
+        notes: &Notes,
+        at: &mut Vec<usize>,
+        depth: usize,
+        width: usize,
+    ) {
+        let len = items.len();
+        self.out.push(open);
+        self.out.push('\n');
+        for (i, (key, v)) in items.enumerate() {
+            at.push(i);
+            self.comments(notes.at(at), depth + 1);
+            self.indent(depth + 1);
+            if let Some(key) = key {
+                self.out.push_str(&print_key(key));
+                self.out.push_str(": ");
+            }
+            self.value(v, notes, at, depth + 1, width);
+            at.pop();
+            if i + 1 < len {
+                self.out.push(',');
+            }
+            self.out.push('\n');
+        }
+        at.push(len);
+        self.comments(notes.at(at), depth + 1);
+        at.pop();
+        self.indent(depth);
+        self.out.push(close);
+    }
+
+    /// Single-line rendering, or `None` if the value spans lines.
+    fn flat(&self, value: &Value, notes: &Notes, at: &mut Vec<usize>) -> Option<String> {
+        let mut child = |i: usize, v: &Value| {
+            at.push(i);
+            let flat = self.flat(v, notes, at);
+            at.pop();
+            flat
+        };
+        let s = match value {
+            Value::Array(items) => {
+                let parts: Option<Vec<_>> = items.iter().enumerate().map(|(i, v)| child(i, v)).collect();
+                format!("[{}]", parts?.join(", "))
+            }
+            Value::Object(fields) if fields.is_empty() => "{}".to_string(),
+            Value::Object(fields) => {
+                let parts: Option<Vec<_>> = fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (k, v))| Some(format!("{}: {}", print_key(k), child(i, v)?)))
+                    .collect();
+                format!("{{ {} }}", parts?.join(", "))
+            }
+            Value::Str(s) if notes.is_raw(at) => raw_quote(s),
+            other => print_value(other),
+        };
+        (!s.contains('\n')).then_some(s)
+    }
+}
+
+impl fmt::Display for AlnFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&print(self))
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&print_value(self))
+    }
+}
@@ -0,0 +1,306 @@
+//! Path queries over a parsed file, for scripts that need a few values
+//! without mapping the whole plan.
+//!
+//! A query is a `.`-separated list of names, each optionally followed by
+//! indexes:
+//!
+//! ```text
+//! ALN_UPDATE_SYSTEM.RENDER_IN_FRAME.playable_platforms[1]
+//! *.features
+//! INTEROP.*
+//! STAGE[*].limits.cpu
+//! 'key.with.dots'
+//! ```
+//!
+//! - A name matches blocks and keys of that name, and fields of inline
+//!   objects. `*` matches any name. Names containing `.`, `[` or `*` are
+//!   written in quotes.
+//! - The first name matches at the top level or inside any block, so
+//!   `INTEROP.enable_lan` and `*.features` need not spell out the path from
+//!   the top level. Fields of inline objects are only reached through the key
+//!   holding the object.
+//! - `[n]` picks the n-th element of a list, or the n-th of the blocks the
+//!   name matched in the same parent (`STEP[0]`); `[*]` picks them all.
+//!
+//! Each match comes with its full path from the top level.
+
+use crate::aln::ast::{AlnFile, Block, BlockEntry, Value};
+use crate::aln::visit::{self, BlockPath, Visit};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// A parsed query; see the module docs for the syntax.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Segment {
+    name: Name,
+    indexes: Vec<Index>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Name {
+    Any,
+    Exact(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Index {
+    Any,
+    At(usize),
+}
+
+impl Index {
+    fn matches(self, i: usize) -> bool {
+        match self {
+            Index::Any => true,
+            Index::At(n) => n == i,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid query `{query}` at column {column}: {message}")]
+pub struct QueryError {
+    pub query: String,
+    /// 1-based, in characters.
+    pub column: usize,
+    pub message: String,
+}
+
+/// A block or value a query matched.
+#[derive(Debug, Clone, Copy)]
+pub enum Node<'a> {
+    Block(&'a Block),
+    Value(&'a Value),
+}
+
+#[derive(Debug, Clone)]
+pub struct Selected<'a> {
+    /// Full path from the top level, e.g. `ALN_UPDATE_SYSTEM.EXEC_REGO_POLICY.features[0]`.
+    pub path: String,
+    pub node: Node<'a>,
+}
+
+/// Parse `query` and run it over `file`.
+pub fn select<'a>(file: &'a AlnFile, query: &str) -> Result<Vec<Selected<'a>>, QueryError> {
+    Ok(query.parse::<Query>()?.select(file))
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(query: &str) -> Result<Self, QueryError> {
+        let err = |rest: &str, message: &str| QueryError {
+            query: query.to_string(),
+            column: query[..query.len() - rest.len()].chars().count() + 1,
+            message: message.to_string(),
+        };
+
+        let mut segments = Vec::new();
+        let mut rest = query;
+        loop {
+            let name = if let Some(after) = rest.strip_prefix('*') {
+                rest = after;
+                Name::Any
+            } else if let Some(quote) = rest.chars().next().filter(|c| matches!(c, '\'' | '"')) {
+                let body = &rest[1..];
+                let Some(len) = body.find(quote) else {
+                    return Err(err(rest, "unclosed quote"));
+                };
+                rest = &body[len + 1..];
+                Name::Exact(body[..len].to_string())
+            } else {
+                let len = rest.find(['.', '[', ']', '*', '\'', '"']).unwrap_or(rest.len());
+                if len == 0 {
+                    return Err(err(rest, "expected a name, `*` or a quoted name"));
+                }
+                let name = &rest[..len];
+                rest = &rest[len..];
+                Name::Exact(name.to_string())
+            };
+
+            let mut indexes = Vec::new();
+            while let Some(body) = rest.strip_prefix('[') {
+                let Some(len) = body.find(']') else {
+                    return Err(err(rest, "unclosed `[`"));
+                };
+                indexes.push(match &body[..len] {
+                    "*" => Index::Any,
+                    n => Index::At(n.parse().map_err(|_| err(body, "expected an index or `*`"))?),
+                });
+                rest = &body[len + 1..];
+            }
+            segments.push(Segment { name, indexes });
+
+            match rest.strip_prefix('.') {
+                Some(after) => rest = after,
+                None if rest.is_empty() => break,
+                None => return Err(err(rest, "expected `.` or `[`")),
+            }
+        }
+        Ok(Query { segments })
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.segments.iter().enumerate() {
+            if i > 0 {
+                f.write_str(".")?;
+            }
+            match &segment.name {
+                Name::Any => f.write_str("*")?,
+                Name::Exact(name) => f.write_str(&path_name(name))?,
+            }
+            for index in &segment.indexes {
+                match index {
+                    Index::Any => f.write_str("[*]")?,
+                    Index::At(n) => write!(f, "[{}]", n)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a segment is matched against: the file's top level, or the
+/// children of a block or inline object.
+#[derive(Clone, Copy)]
+enum Parent<'a> {
+    File(&'a AlnFile),
+    Node(Node<'a>),
+}
+
+impl Query {
+    /// Every match in `file`, grouped by the block the first name matched
+    /// in, in document order.
+    pub fn select<'a>(&self, file: &'a AlnFile) -> Vec<Selected<'a>> {
+        let mut anchors = vec![(String::new(), Parent::File(file))];
+        anchors.extend(
+            Blocks::collect(file)
+                .into_iter()
+                .map(|(path, block)| (path, Parent::Node(Node::Block(block)))),
+        );
+
+        let mut out = Vec::new();
+        for anchor in anchors {
+            let mut frontier = vec![anchor];
+            for segment in &self.segments {
+                frontier = frontier
+                    .into_iter()
+                    .flat_map(|(path, parent)| segment.apply(parent, &path))
+                    .map(|(path, node)| (path, Parent::Node(node)))
+                    .collect();
+            }
+            out.extend(frontier.into_iter().filter_map(|(path, parent)| match parent {
+                Parent::Node(node) => Some(Selected { path, node }),
+                Parent::File(_) => None,
+            }));
+        }
+        out
+    }
+}
+
+impl Segment {
+    fn apply<'a>(&self, parent: Parent<'a>, path: &str) -> Vec<(String, Node<'a>)> {
+        let mut matched: Vec<(String, Node<'a>)> = children(parent)
+            .into_iter()
+            .filter(|(name, _)| match &self.name {
+                Name::Any => true,
+                Name::Exact(n) => n == name,
+            })
+            .map(|(name, node)| (join(path, name), node))
+            .collect();
+
+        for &index in &self.indexes {
+            let mut next = Vec::new();
+            let mut blocks = 0;
+            for (path, node) in matched {
+                match node {
+                    Node::Block(_) => {
+                        if index.matches(blocks) {
+                            next.push((format!("{}[{}]", path, blocks), node));
+                        }
+                        blocks += 1;
+                    }
+                    Node::Value(Value::Array(items)) => {
+                        for (i, item) in items.iter().enumerate().filter(|&(i, _)| index.matches(i)) {
+                            next.push((format!("{}[{}]", path, i), Node::Value(item)));
+                        }
+                    }
+                    Node::Value(_) => {}
+                }
+            }
+            matched = next;
+        }
+        matched
+    }
+}
+
+fn children(parent: Parent<'_>) -> Vec<(&str, Node<'_>)> {
+    match parent {
+        Parent::File(file) => file
+            .items
+            .iter()
+            .map(|item| (item.block().name.as_str(), Node::Block(item.block())))
+            .collect(),
+        Parent::Node(Node::Block(block)) => block
+            .body
+            .iter()
+            .filter_map(|entry| match entry {
+                BlockEntry::KeyValue { key, value, .. } => Some((key.as_str(), Node::Value(value))),
+                BlockEntry::NestedBlock(b) => Some((b.name.as_str(), Node::Block(b))),
+                BlockEntry::List(_) => None,
+            })
+            .collect(),
+        Parent::Node(Node::Value(Value::Object(fields))) => {
+            fields.iter().map(|(k, v)| (k.as_str(), Node::Value(v))).collect()
+        }
+        Parent::Node(Node::Value(_)) => Vec::new(),
+    }
+}
+
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        path_name(name)
+    } else {
+        format!("{}.{}", path, path_name(name))
+    }
+}
+
+/// `name` as written in a query: bare, or quoted when it would not parse
+/// back as one name.
+fn path_name(name: &str) -> String {
+    let bare = !name.is_empty() && !name.contains(['.', '[', ']', '*', '\'', '"']);
+    if bare {
+        name.to_string()
+    } else if name.contains('\'') {
+        format!("\"{}\"", name)
+    } else {
+        format!("'{}'", name)
+    }
+}
+
+/// Every block with its path, outermost first.
+struct Blocks<'a>(Vec<(String, &'a Block)>);
+
+impl<'a> Blocks<'a> {
+    fn collect(file: &'a AlnFile) -> Vec<(String, &'a Block)> {
+        let mut blocks = Blocks(Vec::new());
+        blocks.visit_file(file);
+        blocks.0
+    }
+}
+
+impl<'a> Visit<'a> for Blocks<'a> {
+    fn visit_block(&mut self, block: &'a Block, path: &mut BlockPath) {
+        let prefix = path.names().iter().fold(String::new(), |acc, name| join(&acc, name));
+        self.0.push((join(&prefix, &block.name), block));
+        visit::walk_block(self, block, path);
+    }
+}
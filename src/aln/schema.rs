@@ -0,0 +1,460 @@
+//! Declarative block schemas.
+//!
+//! A [`BlockSchema`] lists the keys a block accepts (with types, defaults and
+//! docs) and the nested blocks it may contain. Validating a parsed [`Block`]
+//! against it yields a [`Validated`] view with defaults filled in, or every
+//! [`SchemaError`] found, each pointing at the offending source span.
+//!
+//! Keys and blocks a schema does not mention are reported according to a
+//! [`Strictness`], with a hint when the name is a typo of a known one or
+//! belongs in another block of the schema.
+
+use crate::aln::ast::{Block, BlockEntry, Value};
+use crate::aln::diagnostic::{Diagnostic, Severity};
+use crate::aln::span::Span;
+use std::fmt;
+use thiserror::Error;
+
+/// Expected type of a value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Str,
+    Bool,
+    Integer,
+    /// Integer or float.
+    Number,
+    Array(Box<Type>),
+    Object,
+    /// A string restricted to the listed values.
+    Enum(Vec<&'static str>),
+    Any,
+}
+
+impl Type {
+    pub fn array_of(inner: Type) -> Self {
+        Type::Array(Box::new(inner))
+    }
+
+    /// Check `value`. On mismatch, returns the path suffix (e.g. `[2]`) of the
+    /// offending element, the type expected there and the value found.
+    fn check<'s, 'v>(&'s self, value: &'v Value) -> Result<(), (String, &'s Type, &'v Value)> {
+        let ok = match (self, value) {
+            (Type::Any, _)
+            | (Type::Str, Value::Str(_))
+            | (Type::Bool, Value::Bool(_))
+            | (Type::Integer, Value::Integer(_))
+            | (Type::Number, Value::Integer(_) | Value::Float(_))
+            | (Type::Object, Value::Object(_)) => true,
+            (Type::Enum(allowed), Value::Str(s)) => allowed.contains(&s.as_str()),
+            (Type::Array(inner), Value::Array(items)) => {
+                for (i, item) in items.iter().enumerate() {
+                    inner
+                        .check(item)
+                        .map_err(|(path, ty, v)| (format!("[{}]{}", i, path), ty, v))?;
+                }
+                true
+            }
+            _ => false,
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err((String::new(), self, value))
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Str => f.write_str("string"),
+            Type::Bool => f.write_str("bool"),
+            Type::Integer => f.write_str("integer"),
+            Type::Number => f.write_str("number"),
+            Type::Array(inner) => write!(f, "array of {}", inner),
+            Type::Object => f.write_str("object"),
+            Type::Enum(allowed) => {
+                let quoted: Vec<_> = allowed.iter().map(|a| format!("'{}'", a)).collect();
+                write!(f, "one of {}", quoted.join(", "))
+            }
+            Type::Any => f.write_str("any value"),
+        }
+    }
+}
+
+/// Name of a value's type as used in error messages.
+pub fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Str(_) => "string",
+        Value::Bool(_) => "bool",
+        Value::Integer(_) => "integer",
+        Value::Float(_) => "float",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// How [`BlockSchema::validate_with`] treats keys and blocks the schema does
+/// not mention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strictness {
+    /// Unknown keys and blocks are errors.
+    Strict,
+    /// Unknown keys and blocks are warnings and otherwise ignored.
+    #[default]
+    Lenient,
+}
+
+/// One key a block accepts.
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub key: &'static str,
+    pub ty: Type,
+    pub required: bool,
+    pub default: Option<Value>,
+    pub doc: &'static str,
+}
+
+impl Field {
+    pub fn required(key: &'static str, ty: Type) -> Self {
+        Self { key, ty, required: true, default: None, doc: "" }
+    }
+
+    pub fn optional(key: &'static str, ty: Type) -> Self {
+        Self { key, ty, required: false, default: None, doc: "" }
+    }
+
+    /// Value used when the key is absent. Implies optional.
+    pub fn default(mut self, value: Value) -> Self {
+        self.required = false;
+        self.default = Some(value);
+        self
+    }
+
+    pub fn doc(mut self, doc: &'static str) -> Self {
+        self.doc = doc;
+        self
+    }
+}
+
+/// Keys and nested blocks accepted by one `@NAME { ... }` block.
+#[derive(Debug, Clone)]
+pub struct BlockSchema {
+    pub name: &'static str,
+    pub doc: &'static str,
+    pub fields: Vec<Field>,
+    /// Nested blocks, each with whether it is required.
+    pub blocks: Vec<(BlockSchema, bool)>,
+}
+
+impl BlockSchema {
+    pub fn new(name: &'static str) -> Self {
+        Self { name, doc: "", fields: Vec::new(), blocks: Vec::new() }
+    }
+
+    pub fn doc(mut self, doc: &'static str) -> Self {
+        self.doc = doc;
+        self
+    }
+
+    pub fn field(mut self, field: Field) -> Self {
+        self.fields.push(field);
+        self
+    }
+
+    pub fn required_block(mut self, block: BlockSchema) -> Self {
+        self.blocks.push((block, true));
+        self
+    }
+
+    pub fn optional_block(mut self, block: BlockSchema) -> Self {
+        self.blocks.push((block, false));
+        self
+    }
+
+    pub fn get_field(&self, key: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.key == key)
+    }
+
+    pub fn get_block(&self, name: &str) -> Option<&BlockSchema> {
+        self.blocks.iter().map(|(b, _)| b).find(|b| b.name == name)
+    }
+
+    /// Check `block` and its nested blocks. Keys and blocks the schema does not
+    /// mention are left alone.
+    pub fn validate(&self, block: &Block) -> Result<Validated, Vec<SchemaError>> {
+        self.validate_with(block, Strictness::Lenient).0
+    }
+
+    /// Check `block` like [`BlockSchema::validate`], also reporting keys and
+    /// blocks the schema does not mention: as errors when `strictness` is
+    /// [`Strictness::Strict`], otherwise in the returned warnings.
+    pub fn validate_with(
+        &self,
+        block: &Block,
+        strictness: Strictness,
+    ) -> (Result<Validated, Vec<SchemaError>>, Vec<SchemaError>) {
+        let mut errors = Vec::new();
+        let mut unknown = Vec::new();
+        let validated = self.validate_into(block, self, &mut errors, &mut unknown);
+        let warnings = match strictness {
+            Strictness::Strict => {
+                errors.extend(unknown);
+                errors.sort_by_key(|e| e.span().start);
+                Vec::new()
+            }
+            Strictness::Lenient => unknown,
+        };
+        if errors.is_empty() {
+            (Ok(validated), warnings)
+        } else {
+            (Err(errors), warnings)
+        }
+    }
+
+    /// The schema of a `@name` block nested anywhere below this one.
+    pub fn find_block(&self, name: &str) -> Option<&BlockSchema> {
+        self.get_block(name)
+            .or_else(|| self.blocks.iter().find_map(|(b, _)| b.find_block(name)))
+    }
+
+    /// A block other than this one, anywhere below it, that accepts `key`.
+    fn owner_of_key(&self, key: &str) -> Option<&BlockSchema> {
+        self.blocks.iter().find_map(|(b, _)| {
+            if b.get_field(key).is_some() {
+                Some(b)
+            } else {
+                b.owner_of_key(key)
+            }
+        })
+    }
+
+    /// Names of the blocks nested anywhere below this one.
+    pub(crate) fn block_names(&self) -> Vec<&'static str> {
+        self.blocks.iter().flat_map(|(b, _)| std::iter::once(b.name).chain(b.block_names())).collect()
+    }
+
+    /// The block that may contain a `@name` block, anywhere from this one down.
+    pub(crate) fn owner_of_block(&self, name: &str) -> Option<&BlockSchema> {
+        if self.get_block(name).is_some() {
+            return Some(self);
+        }
+        self.blocks.iter().find_map(|(b, _)| b.owner_of_block(name))
+    }
+
+    fn validate_into(
+        &self,
+        block: &Block,
+        root: &BlockSchema,
+        errors: &mut Vec<SchemaError>,
+        unknown: &mut Vec<SchemaError>,
+    ) -> Validated {
+        let mut out = Validated {
+            name: block.name.clone(),
+            fields: Vec::new(),
+            blocks: Vec::new(),
+            span: block.span,
+        };
+        for entry in &block.body {
+            match entry {
+                BlockEntry::KeyValue { key, value, key_span, value_span, .. } => {
+                    let Some(field) = self.get_field(key) else {
+                        let hint = match root.owner_of_key(key).or(root.get_field(key).map(|_| root)) {
+                            Some(owner) => Some(format!("`{}` belongs in @{}", key, owner.name)),
+                            None => did_you_mean(key, self.fields.iter().map(|f| f.key)),
+                        };
+                        unknown.push(SchemaError::UnknownKey {
+                            block: block.name.clone(),
+                            key: key.clone(),
+                            hint,
+                            span: *key_span,
+                        });
+                        continue;
+                    };
+                    if out.get(key).is_some() {
+                        errors.push(SchemaError::DuplicateKey {
+                            block: block.name.clone(),
+                            key: key.clone(),
+                            span: *key_span,
+                        });
+                        continue;
+                    }
+                    if let Err((path, expected, found)) = field.ty.check(value) {
+                        errors.push(SchemaError::TypeMismatch {
+                            key: format!("{}{}", key, path),
+                            expected: expected.to_string(),
+                            found: type_name(found),
+                            span: *value_span,
+                        });
+                        continue;
+                    }
+                    out.fields.push((key.clone(), value.clone()));
+                }
+                BlockEntry::NestedBlock(nested) => {
+                    let Some(schema) = self.get_block(&nested.name) else {
+                        let hint = match root.owner_of_block(&nested.name) {
+                            Some(owner) => Some(format!("@{} belongs in @{}", nested.name, owner.name)),
+                            None => did_you_mean(&nested.name, self.blocks.iter().map(|(b, _)| b.name)),
+                        };
+                        unknown.push(SchemaError::UnknownBlock {
+                            parent: block.name.clone(),
+                            name: nested.name.clone(),
+                            hint,
+                            span: nested.name_span,
+                        });
+                        continue;
+                    };
+                    if out.block(&nested.name).is_some() {
+                        errors.push(SchemaError::DuplicateBlock {
+                            name: nested.name.clone(),
+                            span: nested.name_span,
+                        });
+                        continue;
+                    }
+                    out.blocks.push(schema.validate_into(nested, root, errors, unknown));
+                }
+                BlockEntry::List(_) => {}
+            }
+        }
+
+        for field in &self.fields {
+            if out.get(field.key).is_some() {
+                continue;
+            }
+            if let Some(default) = &field.default {
+                out.fields.push((field.key.to_string(), default.clone()));
+            } else if field.required {
+                errors.push(SchemaError::MissingKey {
+                    block: block.name.clone(),
+                    key: field.key.to_string(),
+                    span: block.name_span,
+                });
+            }
+        }
+        for (schema, required) in &self.blocks {
+            if *required && out.block(schema.name).is_none() {
+                errors.push(SchemaError::MissingBlock {
+                    parent: block.name.clone(),
+                    name: schema.name.to_string(),
+                    span: block.name_span,
+                });
+            }
+        }
+        out
+    }
+}
+
+/// A block that passed validation, with defaults applied.
+#[derive(Debug, Clone)]
+pub struct Validated {
+    pub name: String,
+    pub fields: Vec<(String, Value)>,
+    pub blocks: Vec<Validated>,
+    pub span: Span,
+}
+
+impl Validated {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.fields.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn block(&self, name: &str) -> Option<&Validated> {
+        self.blocks.iter().find(|b| b.name == name)
+    }
+
+    /// Fields and nested blocks as one object, the shape
+    /// [`crate::aln::de::from_value`] expects for a block.
+    pub fn to_value(&self) -> Value {
+        let mut fields = self.fields.clone();
+        fields.extend(self.blocks.iter().map(|b| (b.name.clone(), b.to_value())));
+        Value::Object(fields)
+    }
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum SchemaError {
+    #[error("`{key}` expects {expected}, got {found}")]
+    TypeMismatch { key: String, expected: String, found: &'static str, span: Span },
+    #[error("missing required key `{key}` in @{block}")]
+    MissingKey { block: String, key: String, span: Span },
+    #[error("missing required block @{name} in @{parent}")]
+    MissingBlock { parent: String, name: String, span: Span },
+    #[error("duplicate key `{key}` in @{block}")]
+    DuplicateKey { block: String, key: String, span: Span },
+    #[error("duplicate block @{name}")]
+    DuplicateBlock { name: String, span: Span },
+    /// A key the block's schema does not mention. `hint` suggests the key
+    /// that was probably meant, or the block the key belongs in.
+    #[error("unknown key `{key}` in @{block}")]
+    UnknownKey { block: String, key: String, hint: Option<String>, span: Span },
+    #[error("unknown block @{name} in @{parent}")]
+    UnknownBlock { parent: String, name: String, hint: Option<String>, span: Span },
+}
+
+impl SchemaError {
+    pub fn span(&self) -> Span {
+        match self {
+            SchemaError::TypeMismatch { span, .. }
+            | SchemaError::MissingKey { span, .. }
+            | SchemaError::MissingBlock { span, .. }
+            | SchemaError::DuplicateKey { span, .. }
+            | SchemaError::DuplicateBlock { span, .. }
+            | SchemaError::UnknownKey { span, .. }
+            | SchemaError::UnknownBlock { span, .. } => *span,
+        }
+    }
+
+    pub fn into_diagnostic(self, severity: Severity) -> Diagnostic {
+        let mut d = Diagnostic::error(self.to_string(), self.span());
+        d.severity = severity;
+        match self {
+            SchemaError::UnknownKey { hint: Some(hint), .. } | SchemaError::UnknownBlock { hint: Some(hint), .. } => {
+                d.with_note(hint)
+            }
+            _ => d,
+        }
+    }
+}
+
+impl From<SchemaError> for Diagnostic {
+    fn from(e: SchemaError) -> Self {
+        e.into_diagnostic(Severity::Error)
+    }
+}
+
+/// A "did you mean" note naming the candidate closest to `name`, if any is
+/// close enough to be a plausible typo. Case is ignored.
+pub fn did_you_mean<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let name = name.to_lowercase();
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|c| (edit_distance(&name, &c.to_lowercase()), c))
+        .filter(|(d, _)| *d <= limit)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| format!("did you mean `{}`?", c))
+}
+
+/// Edits (insertions, deletions, substitutions and swaps of adjacent
+/// characters) needed to turn `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // Three rows of the distance matrix: two rows back, previous, current.
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        cur[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            cur[j] = (prev[j] + 1).min(cur[j - 1] + 1).min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                cur[j] = cur[j].min(before[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before, &mut prev);
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
@@ -0,0 +1,470 @@
+//! serde `Serializer` for ALN, the inverse of [`crate::aln::de`].
+//!
+//! Structs become blocks and maps become inline objects, so a type that
+//! deserializes from a plan serializes back to the same shape:
+//!
+//! - The top-level value must be a struct or map; each of its fields must be a
+//!   struct (one `@FIELD { ... }` block) or a sequence of structs (repeated
+//!   blocks).
+//! - Inside a block, struct fields and sequences of structs become nested
+//!   blocks; everything else becomes `key: value`.
+//! - An `@args` field becomes positional block arguments and `@name` fields
+//!   named ones.
+//! - `None` and unit fields are left out. Unit enum variants are strings;
+//!   other variants are single-key objects.
+
+use crate::aln::ast::{AlnFile, AlnItem, Block, BlockArg, BlockArgs, BlockEntry, Value, ValueTrivia};
+use crate::aln::de::Error;
+use crate::aln::printer::{self, is_identifier};
+use crate::aln::span::Span;
+use serde::ser::{self, Serialize};
+
+/// Serialize `value` as canonical ALN source.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+    Ok(printer::print(&to_file(value)?))
+}
+
+/// Serialize `value` into a syntax tree, following the rules in the module docs.
+pub fn to_file<T: Serialize + ?Sized>(value: &T) -> Result<AlnFile, Error> {
+    let fields = match value.serialize(NodeSerializer)? {
+        Node::Struct(fields) | Node::Map(fields) => fields,
+        _ => return Err(ser::Error::custom("top-level value must be a struct or map")),
+    };
+    let mut items = Vec::new();
+    for (name, node) in fields {
+        match node {
+            Node::Absent => {}
+            node => match blocks(&name, node)? {
+                Ok(blocks) => items.extend(blocks.into_iter().map(AlnItem::from_block)),
+                Err(_) => {
+                    return Err(Error::Data {
+                        path: name,
+                        message: "top-level fields must be structs to become blocks".into(),
+                    })
+                }
+            },
+        }
+    }
+    Ok(AlnFile { items, trailing_comments: Vec::new() })
+}
+
+/// Serialize `value` into a value, with structs as objects.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, Error> {
+    value.serialize(NodeSerializer)?.into_value()
+}
+
+/// Intermediate tree that remembers whether a map came from a struct.
+enum Node {
+    Value(Value),
+    Seq(Vec<Node>),
+    Map(Vec<(String, Node)>),
+    Struct(Vec<(String, Node)>),
+    Absent,
+}
+
+impl Node {
+    fn into_value(self) -> Result<Value, Error> {
+        match self {
+            Node::Value(v) => Ok(v),
+            Node::Seq(items) => {
+                let items = items
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, n)| n.into_value().map_err(|e| e.within(&format!("[{}]", i))))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::Array(items))
+            }
+            Node::Map(fields) | Node::Struct(fields) => {
+                let mut out = Vec::new();
+                for (key, node) in fields {
+                    if !matches!(node, Node::Absent) {
+                        let value = node.into_value().map_err(|e| e.within(&key))?;
+                        out.push((key, value));
+                    }
+                }
+                Ok(Value::Object(out))
+            }
+            Node::Absent => Err(ser::Error::custom("ALN has no null value")),
+        }
+    }
+
+    fn is_struct(&self) -> bool {
+        matches!(self, Node::Struct(_))
+    }
+}
+
+/// The blocks `node` becomes under `name`, or the node back if it is not a
+/// struct or a non-empty sequence of structs.
+fn blocks(name: &str, node: Node) -> Result<Result<Vec<Block>, Node>, Error> {
+    let nodes = match node {
+        Node::Struct(_) => vec![node],
+        Node::Seq(items) if !items.is_empty() && items.iter().all(Node::is_struct) => items,
+        other => return Ok(Err(other)),
+    };
+    let repeated = nodes.len() > 1;
+    let mut out = Vec::new();
+    for (i, node) in nodes.into_iter().enumerate() {
+        let Node::Struct(fields) = node else { unreachable!() };
+        let path = if repeated { format!("{}[{}]", name, i) } else { name.to_string() };
+        out.push(block(name, fields).map_err(|e| e.within(&path))?);
+    }
+    Ok(Ok(out))
+}
+
+fn block(name: &str, fields: Vec<(String, Node)>) -> Result<Block, Error> {
+    if !is_identifier(name) {
+        return Err(ser::Error::custom(format!("`{}` is not a valid block name", name)));
+    }
+    let mut args = Vec::new();
+    let mut body = Vec::new();
+    for (key, node) in fields {
+        if matches!(node, Node::Absent) {
+            continue;
+        }
+        if key == "@args" {
+            match node.into_value().map_err(|e| e.within(&key))? {
+                Value::Array(items) => args.extend(items.into_iter().map(|value| arg(None, value))),
+                value => args.push(arg(None, value)),
+            }
+            continue;
+        }
+        if let Some(name) = key.strip_prefix('@') {
+            let value = node.into_value().map_err(|e| e.within(&key))?;
+            args.push(arg(Some(name.to_string()), value));
+            continue;
+        }
+        match blocks(&key, node)? {
+            Ok(blocks) => body.extend(blocks.into_iter().map(BlockEntry::NestedBlock)),
+            Err(node) => {
+                if !is_identifier(&key) {
+                    return Err(ser::Error::custom(format!("`{}` is not a valid key", key)));
+                }
+                let value = node.into_value().map_err(|e| e.within(&key))?;
+                body.push(BlockEntry::KeyValue {
+                    key,
+                    value,
+                    comments: Vec::new(),
+                    trivia: ValueTrivia::default(),
+                    key_span: Span::default(),
+                    value_span: Span::default(),
+                });
+            }
+        }
+    }
+    Ok(Block {
+        name: name.to_string(),
+        args: (!args.is_empty()).then(|| BlockArgs { items: args, span: Span::default() }),
+        body,
+        comments: Vec::new(),
+        closing_comments: Vec::new(),
+        span: Span::default(),
+        name_span: Span::default(),
+        origin: None,
+    })
+}
+
+fn arg(name: Option<String>, value: Value) -> BlockArg {
+    BlockArg { name, value, trivia: ValueTrivia::default(), span: Span::default() }
+}
+
+struct NodeSerializer;
+
+fn scalar(value: Value) -> Result<Node, Error> {
+    Ok(Node::Value(value))
+}
+
+impl ser::Serializer for NodeSerializer {
+    type Ok = Node;
+    type Error = Error;
+    type SerializeSeq = SeqBuilder;
+    type SerializeTuple = SeqBuilder;
+    type SerializeTupleStruct = SeqBuilder;
+    type SerializeTupleVariant = Variant<SeqBuilder>;
+    type SerializeMap = MapBuilder;
+    type SerializeStruct = MapBuilder;
+    type SerializeStructVariant = Variant<MapBuilder>;
+
+    fn serialize_bool(self, v: bool) -> Result<Node, Error> {
+        scalar(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Node, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Node, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Node, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Node, Error> {
+        scalar(Value::Integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Node, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Node, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Node, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Node, Error> {
+        i64::try_from(v)
+            .map(Value::Integer)
+            .map(Node::Value)
+            .map_err(|_| ser::Error::custom(format!("integer {} is out of range", v)))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Node, Error> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Node, Error> {
+        scalar(Value::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Node, Error> {
+        scalar(Value::Str(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Node, Error> {
+        scalar(Value::Str(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Node, Error> {
+        scalar(Value::Array(v.iter().map(|b| Value::Integer((*b).into())).collect()))
+    }
+
+    fn serialize_none(self) -> Result<Node, Error> {
+        Ok(Node::Absent)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Node, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Node, Error> {
+        Ok(Node::Absent)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Node, Error> {
+        Ok(Node::Absent)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Node, Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Node, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Node, Error> {
+        Ok(Node::Map(vec![(variant.to_string(), value.serialize(self)?)]))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqBuilder, Error> {
+        Ok(SeqBuilder(Vec::with_capacity(len.unwrap_or(0))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqBuilder, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqBuilder, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Variant<SeqBuilder>, Error> {
+        Ok(Variant { name: variant, inner: self.serialize_seq(Some(len))? })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapBuilder, Error> {
+        Ok(MapBuilder { fields: Vec::new(), key: None, is_struct: false })
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<MapBuilder, Error> {
+        Ok(MapBuilder { fields: Vec::new(), key: None, is_struct: true })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Variant<MapBuilder>, Error> {
+        Ok(Variant { name: variant, inner: self.serialize_struct(variant, len)? })
+    }
+}
+
+struct SeqBuilder(Vec<Node>);
+
+impl SeqBuilder {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        match value.serialize(NodeSerializer)? {
+            Node::Absent => Err(ser::Error::custom("ALN arrays cannot hold null values")),
+            node => {
+                self.0.push(node);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl ser::SerializeSeq for SeqBuilder {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Node, Error> {
+        Ok(Node::Seq(self.0))
+    }
+}
+
+impl ser::SerializeTuple for SeqBuilder {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Node, Error> {
+        Ok(Node::Seq(self.0))
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqBuilder {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Node, Error> {
+        Ok(Node::Seq(self.0))
+    }
+}
+
+struct MapBuilder {
+    fields: Vec<(String, Node)>,
+    key: Option<String>,
+    is_struct: bool,
+}
+
+impl MapBuilder {
+    fn finish(self) -> Node {
+        if self.is_struct {
+            Node::Struct(self.fields)
+        } else {
+            Node::Map(self.fields)
+        }
+    }
+}
+
+impl ser::SerializeMap for MapBuilder {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        match key.serialize(NodeSerializer)? {
+            Node::Value(Value::Str(s)) => self.key = Some(s),
+            Node::Value(Value::Integer(n)) => self.key = Some(n.to_string()),
+            Node::Value(Value::Bool(b)) => self.key = Some(b.to_string()),
+            _ => return Err(ser::Error::custom("map keys must be strings")),
+        }
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().expect("serialize_value called before serialize_key");
+        let node = value.serialize(NodeSerializer).map_err(|e| e.within(&key))?;
+        self.fields.push((key, node));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Node, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStruct for MapBuilder {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        let node = value.serialize(NodeSerializer).map_err(|e| e.within(key))?;
+        self.fields.push((key.to_string(), node));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Node, Error> {
+        Ok(self.finish())
+    }
+}
+
+/// A tuple or struct variant: its content wrapped in a single-key map.
+struct Variant<B> {
+    name: &'static str,
+    inner: B,
+}
+
+impl ser::SerializeTupleVariant for Variant<SeqBuilder> {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.inner.push(value)
+    }
+
+    fn end(self) -> Result<Node, Error> {
+        Ok(Node::Map(vec![(self.name.to_string(), Node::Seq(self.inner.0))]))
+    }
+}
+
+impl ser::SerializeStructVariant for Variant<MapBuilder> {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> Result<Node, Error> {
+        Ok(Node::Map(vec![(self.name.to_string(), self.inner.finish())]))
+    }
+}
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Half-open byte range `start..end` into the source text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The same range shifted `by` bytes later.
+    pub fn offset(self, by: usize) -> Span {
+        Span::new(self.start + by, self.end + by)
+    }
+}
+
+/// 1-based line and column (columns count characters, not bytes).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCol {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl std::fmt::Display for LineCol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+/// Source text plus a line index, used to turn spans into positions.
+///
+/// Spans into a file start at its `base` offset. The root file has base 0;
+/// included files are placed after it, so spans from different files never
+/// overlap and a span alone says which file it points into.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    name: Arc<str>,
+    src: Arc<str>,
+    base: usize,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    pub fn new(name: impl Into<String>, src: impl Into<String>) -> Self {
+        let src: String = src.into();
+        let line_starts = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { name: name.into().into(), src: src.into(), base: 0, line_starts }
+    }
+
+    pub fn with_base(mut self, base: usize) -> Self {
+        self.base = base;
+        self
+    }
+
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    /// Offset just past the end of the file.
+    pub fn end(&self) -> usize {
+        self.base + self.src.len()
+    }
+
+    pub fn contains(&self, offset: usize) -> bool {
+        (self.base..=self.end()).contains(&offset)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn src(&self) -> &str {
+        &self.src
+    }
+
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        let offset = offset.saturating_sub(self.base).min(self.src.len());
+        let line = self.line_starts.partition_point(|&s| s <= offset) - 1;
+        let start = self.line_starts[line];
+        let col = self.src[start..floor_char_boundary(&self.src, offset)].chars().count() + 1;
+        LineCol { line: line + 1, col }
+    }
+
+    /// Text of a 1-based line, without its newline.
+    pub fn line_text(&self, line: usize) -> &str {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .map(|&e| e - 1)
+            .unwrap_or(self.src.len());
+        self.src[start..end].trim_end_matches('\r')
+    }
+}
+
+fn floor_char_boundary(s: &str, mut i: usize) -> usize {
+    while !s.is_char_boundary(i) {
+        i -= 1;
+    }
+    i
+}
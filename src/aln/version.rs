@@ -0,0 +1,193 @@
+//! Plan format versions.
+//!
+//! A plan declares its version as a 4-part `major.minor.patch.build` string,
+//! either as a key, as the root block's `version` argument or as its first
+//! positional argument:
+//!
+//! ```text
+//! @ALN_UPDATE_SYSTEM { version: '1.0.1.7', ... }
+//! @ALN_UPDATE_SYSTEM version='1.0.1.7' { ... }
+//! @ALN_UPDATE_SYSTEM '1.0.1.7' { ... }
+//! ```
+//!
+//! Compatibility rule: [`NEWEST_SUPPORTED`] is the format this orchestrator
+//! implements, the one of the shipped plan, the `update_log_v1_7` table and
+//! the `aln_update_state_1.0.1.7` Redis keys. Within a `major.minor` line the
+//! format only grows, so older plans of that line are read as they are and
+//! [`OLDEST_SUPPORTED`] is the line's first version. A change that older
+//! plans do not satisfy registers a step in
+//! [`crate::aln::migrate::Registry::builtin`] and raises [`OLDEST_SUPPORTED`]
+//! to the step's version. Plans in between, both bounds included, are
+//! accepted. Newer plans are rejected: they may use features this
+//! orchestrator does not know about.
+//!
+//! Plans written before versions were declared all claimed 1.0.1.7, so a plan
+//! without a version is read as [`UNDECLARED`], with a warning.
+
+use crate::aln::ast::{Block, BlockEntry, Value};
+use crate::aln::diagnostic::Diagnostic;
+use crate::aln::span::Span;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// A `major.minor.patch.build` plan version. Orders by component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PlanVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    pub build: u32,
+}
+
+/// See the compatibility rule in the module docs.
+pub const NEWEST_SUPPORTED: PlanVersion = PlanVersion::new(1, 0, 1, 7);
+/// The first version of [`NEWEST_SUPPORTED`]'s `major.minor` line, while no
+/// migration step requires a later one.
+pub const OLDEST_SUPPORTED: PlanVersion = PlanVersion::new(NEWEST_SUPPORTED.major, NEWEST_SUPPORTED.minor, 0, 0);
+/// Version of a plan that does not declare one.
+pub const UNDECLARED: PlanVersion = PlanVersion::new(1, 0, 1, 7);
+
+impl PlanVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32, build: u32) -> Self {
+        Self { major, minor, patch, build }
+    }
+
+    /// Check this version against the supported range.
+    pub fn check_supported(&self) -> Result<(), VersionError> {
+        if *self > NEWEST_SUPPORTED {
+            Err(VersionError::TooNew(*self))
+        } else if *self < OLDEST_SUPPORTED {
+            Err(VersionError::TooOld(*self))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Compares with the written form, e.g. `plan.version == "1.0.1.7"`.
+impl PartialEq<&str> for PlanVersion {
+    fn eq(&self, other: &&str) -> bool {
+        other.parse::<PlanVersion>().is_ok_and(|v| v == *self)
+    }
+}
+
+impl fmt::Display for PlanVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}.{}", self.major, self.minor, self.patch, self.build)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum VersionError {
+    #[error("invalid plan version `{0}`: expected four dot-separated numbers like `1.0.1.7`")]
+    Invalid(String),
+    #[error("plan version {0} is newer than this orchestrator supports (up to {NEWEST_SUPPORTED}); upgrade the orchestrator")]
+    TooNew(PlanVersion),
+    #[error("plan version {0} is older than the oldest supported version {OLDEST_SUPPORTED}")]
+    TooOld(PlanVersion),
+}
+
+impl FromStr for PlanVersion {
+    type Err = VersionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || VersionError::Invalid(s.to_string());
+        let parts: Vec<u32> = s
+            .split('.')
+            .map(|p| {
+                // `u32::from_str` accepts a leading `+`; versions do not.
+                if p.is_empty() || !p.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(invalid());
+                }
+                p.parse().map_err(|_| invalid())
+            })
+            .collect::<Result<_, _>>()?;
+        match parts[..] {
+            [major, minor, patch, build] => Ok(Self::new(major, minor, patch, build)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Serialize for PlanVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PlanVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// The version `root` declares and the span of the declaration, or
+/// [`UNDECLARED`] and `None` if it declares none. Reports a malformed
+/// version, conflicting declarations and versions outside the supported
+/// range.
+pub fn declared(root: &Block) -> Result<(PlanVersion, Option<Span>), Diagnostic> {
+    let (version, span) = declared_unchecked(root)?;
+    version
+        .check_supported()
+        .map_err(|e| Diagnostic::error(e.to_string(), span.unwrap_or(root.name_span)))?;
+    Ok((version, span))
+}
+
+/// Warning for a `root` that does not declare a version.
+pub fn undeclared(root: &Block) -> Diagnostic {
+    Diagnostic::warning(
+        format!("@{} does not declare a version; reading it as {}", root.name, UNDECLARED),
+        root.name_span,
+    )
+    .with_note(format!("add `version: '{}'`", UNDECLARED))
+}
+
+/// Every place `root` declares its version, in source order: the `version`
+/// argument and the first positional one, with the argument's span, and a
+/// `version` key, with the value's. Later positional arguments are not
+/// versions.
+pub fn declarations(root: &Block) -> Vec<(&Value, Span)> {
+    let mut found = Vec::new();
+    if let Some(args) = &root.args {
+        let first = args.items.iter().position(|a| a.name.is_none());
+        for (i, arg) in args.items.iter().enumerate() {
+            if Some(i) == first || arg.name.as_deref() == Some("version") {
+                found.push((&arg.value, arg.span));
+            }
+        }
+    }
+    for entry in &root.body {
+        if let BlockEntry::KeyValue { key, value, value_span, .. } = entry {
+            if key == "version" {
+                found.push((value, *value_span));
+            }
+        }
+    }
+    found
+}
+
+/// [`declared`] without the range check, for tools such as
+/// [`crate::aln::migrate`] that handle plans this orchestrator cannot load.
+pub fn declared_unchecked(root: &Block) -> Result<(PlanVersion, Option<Span>), Diagnostic> {
+    let found = declarations(root);
+    let Some(&(value, span)) = found.first() else {
+        return Ok((UNDECLARED, None));
+    };
+    let parse = |value: &Value, span: Span| match value {
+        Value::Str(s) => s.parse::<PlanVersion>().map_err(|e| Diagnostic::error(e.to_string(), span)),
+        _ => Err(Diagnostic::error(VersionError::Invalid(value.to_string()).to_string(), span)),
+    };
+    let version = parse(value, span)?;
+    for &(other, other_span) in &found[1..] {
+        if parse(other, other_span)? != version {
+            return Err(Diagnostic::error(
+                format!("@{} declares conflicting versions", root.name),
+                other_span,
+            ));
+        }
+    }
+    Ok((version, Some(span)))
+}
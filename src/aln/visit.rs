@@ -0,0 +1,302 @@
+//! Visitors over the syntax tree.
+//!
+//! Implement [`Visit`], or [`VisitMut`] to rewrite in place, and override the
+//! methods for the nodes of interest; the defaults walk into every child. An
+//! override that still wants the children visited calls the matching `walk_*`
+//! function. Every method gets the [`BlockPath`] of the blocks enclosing the
+//! node, so a rule can tell `@ALN_UPDATE_SYSTEM.INTEROP` from an `@INTEROP`
+//! nested somewhere else.
+//!
+//! ```
+//! use aln_system_update_orchestrator::aln::ast::BlockEntry;
+//! use aln_system_update_orchestrator::aln::parser::parse_str;
+//! use aln_system_update_orchestrator::aln::visit::{self, BlockPath, Visit};
+//!
+//! /// Dotted paths of every key.
+//! struct Keys(Vec<String>);
+//!
+//! impl Visit<'_> for Keys {
+//!     fn visit_entry(&mut self, entry: &BlockEntry, path: &mut BlockPath) {
+//!         if let BlockEntry::KeyValue { key, .. } = entry {
+//!             self.0.push(path.key(key));
+//!         }
+//!         visit::walk_entry(self, entry, path);
+//!     }
+//! }
+//!
+//! let file = parse_str("@A { x: 1, @B { y: 2 } }", None).unwrap();
+//! let mut keys = Keys(Vec::new());
+//! keys.visit_file(&file);
+//! assert_eq!(keys.0, ["A.x", "A.B.y"]);
+//! ```
+
+use crate::aln::ast::{AlnFile, AlnItem, Block, BlockArg, BlockEntry, Comment, Value};
+use std::fmt;
+
+/// Names of the blocks enclosing the node being visited, outermost first.
+/// Maintained by the `walk_*` functions; visitors only read it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockPath {
+    names: Vec<String>,
+}
+
+impl BlockPath {
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Number of enclosing blocks; 0 for top-level items.
+    pub fn depth(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn innermost(&self) -> Option<&str> {
+        self.names.last().map(String::as_str)
+    }
+
+    /// Whether the enclosing blocks are exactly `names`, outermost first.
+    pub fn is(&self, names: &[&str]) -> bool {
+        self.names.iter().map(String::as_str).eq(names.iter().copied())
+    }
+
+    /// Dotted path of `key` in the innermost block, as in merge provenance:
+    /// `ALN_UPDATE_SYSTEM.INTEROP.enable_lan`.
+    pub fn key(&self, key: &str) -> String {
+        let mut path = self.to_string();
+        if !path.is_empty() {
+            path.push('.');
+        }
+        path.push_str(key);
+        path
+    }
+
+    fn push(&mut self, name: &str) {
+        self.names.push(name.to_string());
+    }
+
+    fn pop(&mut self) {
+        self.names.pop();
+    }
+}
+
+impl fmt::Display for BlockPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.names.join("."))
+    }
+}
+
+/// Read-only traversal. `'ast` lets a visitor keep references into the tree.
+pub trait Visit<'ast> {
+    fn visit_file(&mut self, file: &'ast AlnFile) {
+        walk_file(self, file);
+    }
+
+    fn visit_item(&mut self, item: &'ast AlnItem, path: &mut BlockPath) {
+        walk_item(self, item, path);
+    }
+
+    /// `path` holds the blocks around `block`, not `block` itself.
+    fn visit_block(&mut self, block: &'ast Block, path: &mut BlockPath) {
+        walk_block(self, block, path);
+    }
+
+    fn visit_arg(&mut self, arg: &'ast BlockArg, path: &mut BlockPath) {
+        walk_arg(self, arg, path);
+    }
+
+    fn visit_entry(&mut self, entry: &'ast BlockEntry, path: &mut BlockPath) {
+        walk_entry(self, entry, path);
+    }
+
+    /// Called for argument and entry values, then for each array element and
+    /// object field value inside them.
+    fn visit_value(&mut self, value: &'ast Value, path: &mut BlockPath) {
+        walk_value(self, value, path);
+    }
+
+    fn visit_comment(&mut self, _comment: &'ast Comment, _path: &mut BlockPath) {}
+}
+
+pub fn walk_file<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, file: &'ast AlnFile) {
+    let mut path = BlockPath::default();
+    for item in &file.items {
+        v.visit_item(item, &mut path);
+    }
+    for comment in &file.trailing_comments {
+        v.visit_comment(comment, &mut path);
+    }
+}
+
+pub fn walk_item<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, item: &'ast AlnItem, path: &mut BlockPath) {
+    match item {
+        AlnItem::Block(b) | AlnItem::Meta(b) => v.visit_block(b, path),
+    }
+}
+
+pub fn walk_block<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, block: &'ast Block, path: &mut BlockPath) {
+    for comment in &block.comments {
+        v.visit_comment(comment, path);
+    }
+    path.push(&block.name);
+    for arg in block.args.iter().flat_map(|args| &args.items) {
+        v.visit_arg(arg, path);
+    }
+    for entry in &block.body {
+        v.visit_entry(entry, path);
+    }
+    for comment in &block.closing_comments {
+        v.visit_comment(comment, path);
+    }
+    path.pop();
+}
+
+pub fn walk_arg<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, arg: &'ast BlockArg, path: &mut BlockPath) {
+    for c in &arg.trivia.comments {
+        v.visit_comment(&c.comment, path);
+    }
+    v.visit_value(&arg.value, path);
+}
+
+pub fn walk_entry<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, entry: &'ast BlockEntry, path: &mut BlockPath) {
+    match entry {
+        BlockEntry::KeyValue { value, comments, trivia, .. } => {
+            for comment in comments.iter().chain(trivia.comments.iter().map(|c| &c.comment)) {
+                v.visit_comment(comment, path);
+            }
+            v.visit_value(value, path);
+        }
+        BlockEntry::NestedBlock(b) => v.visit_block(b, path),
+        BlockEntry::List(list) => {
+            for c in &list.trivia.comments {
+                v.visit_comment(&c.comment, path);
+            }
+            for value in &list.values {
+                v.visit_value(value, path);
+            }
+        }
+    }
+}
+
+pub fn walk_value<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, value: &'ast Value, path: &mut BlockPath) {
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                v.visit_value(item, path);
+            }
+        }
+        Value::Object(fields) => {
+            for (_, value) in fields {
+                v.visit_value(value, path);
+            }
+        }
+        Value::Str(_) | Value::Bool(_) | Value::Integer(_) | Value::Float(_) => {}
+    }
+}
+
+/// In-place rewriting. The same traversal as [`Visit`]; a block's name is
+/// read into the path before its body is visited, so renaming a block in
+/// [`VisitMut::visit_block_mut`] before walking it is seen by its children.
+pub trait VisitMut {
+    fn visit_file_mut(&mut self, file: &mut AlnFile) {
+        walk_file_mut(self, file);
+    }
+
+    fn visit_item_mut(&mut self, item: &mut AlnItem, path: &mut BlockPath) {
+        walk_item_mut(self, item, path);
+    }
+
+    /// `path` holds the blocks around `block`, not `block` itself.
+    fn visit_block_mut(&mut self, block: &mut Block, path: &mut BlockPath) {
+        walk_block_mut(self, block, path);
+    }
+
+    fn visit_arg_mut(&mut self, arg: &mut BlockArg, path: &mut BlockPath) {
+        walk_arg_mut(self, arg, path);
+    }
+
+    fn visit_entry_mut(&mut self, entry: &mut BlockEntry, path: &mut BlockPath) {
+        walk_entry_mut(self, entry, path);
+    }
+
+    fn visit_value_mut(&mut self, value: &mut Value, path: &mut BlockPath) {
+        walk_value_mut(self, value, path);
+    }
+
+    fn visit_comment_mut(&mut self, _comment: &mut Comment, _path: &mut BlockPath) {}
+}
+
+pub fn walk_file_mut<V: VisitMut + ?Sized>(v: &mut V, file: &mut AlnFile) {
+    let mut path = BlockPath::default();
+    for item in &mut file.items {
+        v.visit_item_mut(item, &mut path);
+    }
+    for comment in &mut file.trailing_comments {
+        v.visit_comment_mut(comment, &mut path);
+    }
+}
+
+pub fn walk_item_mut<V: VisitMut + ?Sized>(v: &mut V, item: &mut AlnItem, path: &mut BlockPath) {
+    match item {
+        AlnItem::Block(b) | AlnItem::Meta(b) => v.visit_block_mut(b, path),
+    }
+}
+
+pub fn walk_block_mut<V: VisitMut + ?Sized>(v: &mut V, block: &mut Block, path: &mut BlockPath) {
+    for comment in &mut block.comments {
+        v.visit_comment_mut(comment, path);
+    }
+    path.push(&block.name);
+    for arg in block.args.iter_mut().flat_map(|args| &mut args.items) {
+        v.visit_arg_mut(arg, path);
+    }
+    for entry in &mut block.body {
+        v.visit_entry_mut(entry, path);
+    }
+    for comment in &mut block.closing_comments {
+        v.visit_comment_mut(comment, path);
+    }
+    path.pop();
+}
+
+pub fn walk_arg_mut<V: VisitMut + ?Sized>(v: &mut V, arg: &mut BlockArg, path: &mut BlockPath) {
+    for c in &mut arg.trivia.comments {
+        v.visit_comment_mut(&mut c.comment, path);
+    }
+    v.visit_value_mut(&mut arg.value, path);
+}
+
+pub fn walk_entry_mut<V: VisitMut + ?Sized>(v: &mut V, entry: &mut BlockEntry, path: &mut BlockPath) {
+    match entry {
+        BlockEntry::KeyValue { value, comments, trivia, .. } => {
+            for comment in comments.iter_mut().chain(trivia.comments.iter_mut().map(|c| &mut c.comment)) {
+                v.visit_comment_mut(comment, path);
+            }
+            v.visit_value_mut(value, path);
+        }
+        BlockEntry::NestedBlock(b) => v.visit_block_mut(b, path),
+        BlockEntry::List(list) => {
+            for c in &mut list.trivia.comments {
+                v.visit_comment_mut(&mut c.comment, path);
+            }
+            for value in &mut list.values {
+                v.visit_value_mut(value, path);
+            }
+        }
+    }
+}
+
+pub fn walk_value_mut<V: VisitMut + ?Sized>(v: &mut V, value: &mut Value, path: &mut BlockPath) {
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                v.visit_value_mut(item, path);
+            }
+        }
+        Value::Object(fields) => {
+            for (_, value) in fields {
+                v.visit_value_mut(value, path);
+            }
+        }
+        Value::Str(_) | Value::Bool(_) | Value::Integer(_) | Value::Float(_) => {}
+    }
+}
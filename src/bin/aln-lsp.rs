@@ -0,0 +1,256 @@
+//! Language server for `.aln` plans, speaking LSP over stdio.
+//!
+//! The analyses live in `aln::ide`; this binary keeps the open documents,
+//! re-analyses them on every change and translates between LSP positions
+//! (UTF-16 code units) and byte offsets.
+
+use aln_system_update_orchestrator::aln::diagnostic::Severity;
+use aln_system_update_orchestrator::aln::ide::{self, Analysis, CompletionKind};
+use aln_system_update_orchestrator::aln::span::Span;
+use anyhow::Result;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{Completion, Formatting, GotoDefinition, HoverRequest, Request as _};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DocumentFormattingParams, Documentation, GotoDefinitionParams, GotoDefinitionResponse,
+    Hover, HoverContents, HoverParams, HoverProviderCapability, Location, MarkupContent, MarkupKind, OneOf,
+    Position, PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextEdit, Url,
+};
+use std::collections::HashMap;
+
+fn main() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["@".to_string()]),
+            ..CompletionOptions::default()
+        }),
+        definition_provider: Some(OneOf::Left(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..ServerCapabilities::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+    Server::default().run(&connection)?;
+    io_threads.join()?;
+    Ok(())
+}
+
+struct Document {
+    text: String,
+    analysis: Analysis,
+}
+
+impl Document {
+    fn new(uri: &Url, text: String) -> Self {
+        // Includes and policy files resolve against the path on disk; other
+        // schemes (unsaved buffers) just get a name.
+        let path = match uri.to_file_path() {
+            Ok(path) => path.to_string_lossy().into_owned(),
+            Err(()) => uri.to_string(),
+        };
+        let analysis = Analysis::new(&path, &text);
+        Self { text, analysis }
+    }
+
+    fn offset(&self, pos: Position) -> usize {
+        let text = &self.text;
+        let mut start = 0;
+        for _ in 0..pos.line {
+            match text[start..].find('\n') {
+                Some(i) => start += i + 1,
+                None => return text.len(),
+            }
+        }
+        let end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+        let mut units = 0;
+        for (i, c) in text[start..end].char_indices() {
+            if units >= pos.character as usize {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        end
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let file = self.analysis.file();
+        let pos = file.line_col(offset);
+        let line = file.line_text(pos.line);
+        let character: usize = line.chars().take(pos.col - 1).map(char::len_utf16).sum();
+        Position::new(pos.line as u32 - 1, character as u32)
+    }
+
+    fn range(&self, span: Span) -> Range {
+        Range::new(self.position(span.start), self.position(span.end))
+    }
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<Url, Document>,
+}
+
+impl Server {
+    fn run(&mut self, connection: &Connection) -> Result<()> {
+        for message in &connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    connection.sender.send(Message::Response(self.request(request)))?;
+                }
+                Message::Notification(notification) => self.notification(connection, notification)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn request(&self, request: Request) -> Response {
+        match request.method.as_str() {
+            HoverRequest::METHOD => self.respond::<HoverRequest>(request, Self::hover),
+            Completion::METHOD => self.respond::<Completion>(request, Self::completion),
+            GotoDefinition::METHOD => self.respond::<GotoDefinition>(request, Self::definition),
+            Formatting::METHOD => self.respond::<Formatting>(request, Self::format),
+            other => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unhandled method `{}`", other),
+            ),
+        }
+    }
+
+    fn respond<R: lsp_types::request::Request>(
+        &self,
+        request: Request,
+        handler: impl FnOnce(&Self, R::Params) -> R::Result,
+    ) -> Response {
+        match serde_json::from_value(request.params) {
+            Ok(params) => Response::new_ok(request.id, handler(self, params)),
+            Err(e) => Response::new_err(request.id, ErrorCode::InvalidParams as i32, e.to_string()),
+        }
+    }
+
+    fn notification(&mut self, connection: &Connection, notification: Notification) -> Result<()> {
+        let uri = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams = serde_json::from_value(notification.params)?;
+                let doc = params.text_document;
+                self.documents.insert(doc.uri.clone(), Document::new(&doc.uri, doc.text));
+                doc.uri
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams = serde_json::from_value(notification.params)?;
+                // Full sync: the last change holds the whole text.
+                let Some(change) = params.content_changes.into_iter().last() else {
+                    return Ok(());
+                };
+                let uri = params.text_document.uri;
+                self.documents.insert(uri.clone(), Document::new(&uri, change.text));
+                uri
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams = serde_json::from_value(notification.params)?;
+                self.documents.remove(&params.text_document.uri);
+                params.text_document.uri
+            }
+            _ => return Ok(()),
+        };
+        self.publish_diagnostics(connection, uri)
+    }
+
+    fn publish_diagnostics(&self, connection: &Connection, uri: Url) -> Result<()> {
+        let diagnostics = match self.documents.get(&uri) {
+            Some(doc) => doc
+                .analysis
+                .diagnostics()
+                .iter()
+                .map(|d| {
+                    let mut message = d.message.clone();
+                    for note in &d.notes {
+                        message.push('\n');
+                        message.push_str(note);
+                    }
+                    lsp_types::Diagnostic {
+                        range: doc.range(d.span),
+                        severity: Some(match d.severity {
+                            Severity::Error => DiagnosticSeverity::ERROR,
+                            Severity::Warning => DiagnosticSeverity::WARNING,
+                        }),
+                        source: Some("aln".to_string()),
+                        message,
+                        ..lsp_types::Diagnostic::default()
+                    }
+                })
+                .collect(),
+            // Closed: clear what was shown.
+            None => Vec::new(),
+        };
+        let params = PublishDiagnosticsParams { uri, diagnostics, version: None };
+        let notification = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+        connection.sender.send(Message::Notification(notification))?;
+        Ok(())
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let at = params.text_document_position_params;
+        let doc = self.documents.get(&at.text_document.uri)?;
+        let hover = doc.analysis.hover(doc.offset(at.position))?;
+        Some(Hover {
+            contents: HoverContents::Markup(markdown(hover.contents)),
+            range: Some(doc.range(hover.span)),
+        })
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let at = params.text_document_position;
+        let doc = self.documents.get(&at.text_document.uri)?;
+        let items = doc
+            .analysis
+            .completions(doc.offset(at.position))
+            .into_iter()
+            .map(|c: ide::Completion| CompletionItem {
+                label: c.label,
+                kind: Some(match c.kind {
+                    CompletionKind::Key => CompletionItemKind::PROPERTY,
+                    CompletionKind::Block => CompletionItemKind::MODULE,
+                }),
+                detail: Some(c.detail),
+                documentation: Some(Documentation::MarkupContent(markdown(c.documentation))),
+                insert_text: Some(c.insert_text),
+                ..CompletionItem::default()
+            })
+            .collect();
+        Some(CompletionResponse::Array(items))
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let at = params.text_document_position_params;
+        let doc = self.documents.get(&at.text_document.uri)?;
+        let path = doc.analysis.definition(doc.offset(at.position))?;
+        let uri = Url::from_file_path(path.canonicalize().ok()?).ok()?;
+        Some(GotoDefinitionResponse::Scalar(Location::new(uri, Range::default())))
+    }
+
+    fn format(&self, params: DocumentFormattingParams) -> Option<Vec<TextEdit>> {
+        let doc = self.documents.get(&params.text_document.uri)?;
+        let formatted = doc.analysis.format()?;
+        if formatted == doc.text {
+            return Some(Vec::new());
+        }
+        let whole = Range::new(Position::new(0, 0), doc.position(doc.text.len()));
+        Some(vec![TextEdit::new(whole, formatted)])
+    }
+}
+
+fn markdown(value: String) -> MarkupContent {
+    MarkupContent { kind: MarkupKind::Markdown, value }
+}
@@ -0,0 +1,359 @@
+//! Offline ALN tooling. When the binary is started with a subcommand it runs
+//! that command and exits instead of starting the orchestrator service.
+
+use aln_system_update_orchestrator::aln::{
+    ast::{AlnFile, AlnItem, Value},
+    convert,
+    convert::Format,
+    diff, merge, migrate, parser, printer,
+    query::{Node, Query},
+    version::NEWEST_SUPPORTED,
+    AlnPlanSet, LoadAlnError, PlanVersion, Strictness,
+};
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+use std::fs;
+
+const USAGE: &str = "\
+usage: aln-system-update-orchestrator [COMMAND]
+
+With no command, runs the orchestrator service.
+
+commands:
+  validate [--strict] <file.aln>...
+                                check plans for syntax and schema errors, listing
+                                every problem found; unknown keys and blocks are
+                                warnings, or errors with --strict
+  fmt [--check] <file.aln>...   rewrite plans in canonical style; with --check,
+                                list files that are not formatted and exit 1
+  convert <file> --to <format> [--from <format>] [--out <file>]
+                                convert between aln, json, yaml and toml; formats
+                                default to the file extensions
+  merge <base.aln> <overlay.aln>... [--explain] [--out <file>]
+                                deep-merge overlays into a base plan and print
+                                the result; with --explain, list where each
+                                value came from instead
+  query <file.aln> <path>... [--json] [--paths]
+                                print what each path matches, e.g.
+                                'INTEROP.enable_lan' or '*.features[0]': strings
+                                as plain text, other values and blocks as ALN,
+                                or one JSON document per line with --json;
+                                --paths prefixes each match with its full
+                                path; exits 1 if nothing matches
+  diff <old.aln> <new.aln> [--plan] [--format <human|json|markdown>]
+                                list what changed between two plans, ignoring
+                                formatting, comments and the order of keys and
+                                blocks; with --plan, compare the loaded plans,
+                                defaults included, matching update systems by
+                                name; exits 1 if they differ
+  migrate [--write] [--to <version>] <file.aln>...
+                                upgrade plans written for older format versions
+                                to the newest one, or --to the given version;
+                                previews the steps for each update system and
+                                the changes to each file, with --write rewrites
+                                the files instead; no format change needs a
+                                step yet, so this only declares the new
+                                version and leaves the rest of each file as
+                                written";
+
+/// Run the subcommand in `args` (program name excluded) and return the
+/// process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let result = match args[0].as_str() {
+        "validate" => validate(&args[1..]),
+        "fmt" => fmt(&args[1..]),
+        "convert" => convert(&args[1..]),
+        "merge" => merge(&args[1..]),
+        "query" => query(&args[1..]),
+        "diff" => diff(&args[1..]),
+        "migrate" => migrate(&args[1..]),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(0)
+        }
+        other => {
+            eprintln!("unknown command `{}`\n\n{}", other, USAGE);
+            Ok(2)
+        }
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        1
+    })
+}
+
+/// Command-line arguments split into `--switches`, `--option value` pairs
+/// (also accepted as `--option=value`) and positional arguments.
+#[derive(Default)]
+struct Args<'a> {
+    switches: Vec<&'a str>,
+    options: HashMap<&'a str, &'a str>,
+    positional: Vec<&'a str>,
+}
+
+impl<'a> Args<'a> {
+    fn parse(args: &'a [String], switches: &[&str], options: &[&str]) -> Result<Self> {
+        let mut out = Args::default();
+        let mut iter = args.iter().map(String::as_str);
+        while let Some(arg) = iter.next() {
+            if !arg.starts_with("--") {
+                out.positional.push(arg);
+                continue;
+            }
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (arg, None),
+            };
+            if switches.contains(&name) && inline.is_none() {
+                out.switches.push(name);
+            } else if options.contains(&name) {
+                let value = inline
+                    .or_else(|| iter.next())
+                    .ok_or_else(|| anyhow!("option `{}` needs a value", name))?;
+                out.options.insert(name, value);
+            } else {
+                bail!("unknown option `{}`\n\n{}", arg, USAGE);
+            }
+        }
+        Ok(out)
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.switches.contains(&name)
+    }
+
+    fn option(&self, name: &str) -> Option<&'a str> {
+        self.options.get(name).copied()
+    }
+}
+
+fn validate(args: &[String]) -> Result<i32> {
+    let args = Args::parse(args, &["--strict"], &[])?;
+    if args.positional.is_empty() {
+        bail!("validate: no input files\n\n{}", USAGE);
+    }
+    let strictness = if args.switch("--strict") { Strictness::Strict } else { Strictness::Lenient };
+
+    let mut failed = 0;
+    for path in args.positional {
+        match AlnPlanSet::load_file(path, strictness) {
+            Ok((set, warnings)) => {
+                if !warnings.diagnostics.is_empty() {
+                    eprintln!("{}\n", warnings);
+                }
+                let names: Vec<&str> = set.systems.iter().filter_map(|s| s.name.as_deref()).collect();
+                if names.len() > 1 {
+                    println!("{}: ok ({})", path, names.join(", "));
+                } else {
+                    println!("{}: ok", path);
+                }
+            }
+            Err(LoadAlnError::Io(e)) => bail!("{}: {}", path, e),
+            Err(e) => {
+                eprintln!("{}\n", e);
+                failed += 1;
+            }
+        }
+    }
+    Ok(if failed > 0 { 1 } else { 0 })
+}
+
+fn fmt(args: &[String]) -> Result<i32> {
+    let args = Args::parse(args, &["--check"], &[])?;
+    let check = args.switch("--check");
+    let files = args.positional;
+    if files.is_empty() {
+        bail!("fmt: no input files\n\n{}", USAGE);
+    }
+
+    let mut unformatted = 0;
+    for path in files {
+        let src = fs::read_to_string(path)?;
+        let formatted = printer::print(&parser::parse_str(&src, Some(path))?);
+        if formatted == src {
+            continue;
+        }
+        if check {
+            println!("{}", path);
+            unformatted += 1;
+        } else {
+            fs::write(path, formatted)?;
+            println!("formatted {}", path);
+        }
+    }
+    Ok(if unformatted > 0 { 1 } else { 0 })
+}
+
+/// Resolve a format from an explicit `--from`/`--to` value or a file extension.
+fn format_for(explicit: Option<&str>, path: Option<&str>, flag: &str) -> Result<Format> {
+    match (explicit, path) {
+        (Some(name), _) => Ok(name.parse()?),
+        (None, Some(path)) => Format::from_path(path)
+            .ok_or_else(|| anyhow!("cannot tell the format of `{}`; pass {}", path, flag)),
+        (None, None) => bail!("missing {}", flag),
+    }
+}
+
+fn convert(args: &[String]) -> Result<i32> {
+    let args = Args::parse(args, &[], &["--from", "--to", "--out"])?;
+    let [input] = args.positional[..] else {
+        bail!("convert: expected one input file\n\n{}", USAGE);
+    };
+    let out = args.option("--out");
+    let from = format_for(args.option("--from"), Some(input), "--from")?;
+    let to = format_for(args.option("--to"), out, "--to")?;
+
+    let src = fs::read_to_string(input)?;
+    let file = convert::from_str(&src, from, input)?;
+    let converted = convert::to_string(&file, to)?;
+    match out {
+        Some(path) => fs::write(path, converted)?,
+        None => print!("{}", converted),
+    }
+    Ok(0)
+}
+
+fn merge(args: &[String]) -> Result<i32> {
+    let args = Args::parse(args, &["--explain"], &["--out"])?;
+    if args.positional.len() < 2 {
+        bail!("merge: expected a base plan and at least one overlay\n\n{}", USAGE);
+    }
+
+    let merged = merge::merge_files(&args.positional)?;
+    if merged.report.has_errors() {
+        eprintln!("{}", merged.report);
+        return Ok(1);
+    }
+    let out = if args.switch("--explain") {
+        merged.explain()
+    } else {
+        printer::print(&merged.ast)
+    };
+    match args.option("--out") {
+        Some(path) => fs::write(path, out)?,
+        None => print!("{}", out),
+    }
+    Ok(0)
+}
+
+fn query(args: &[String]) -> Result<i32> {
+    let args = Args::parse(args, &["--json", "--paths"], &[])?;
+    let [path, queries @ ..] = &args.positional[..] else {
+        bail!("query: no input file\n\n{}", USAGE);
+    };
+    if queries.is_empty() {
+        bail!("query: no paths given\n\n{}", USAGE);
+    }
+    let queries = queries.iter().map(|q| q.parse::<Query>()).collect::<Result<Vec<_>, _>>()?;
+    let file = parser::parse_file(path)?;
+
+    let mut found = false;
+    for query in &queries {
+        for selected in query.select(&file) {
+            found = true;
+            let line = if args.switch("--json") {
+                let value = match selected.node {
+                    Node::Block(b) => serde_json::to_value(b)?,
+                    Node::Value(v) => serde_json::to_value(v)?,
+                };
+                if args.switch("--paths") {
+                    serde_json::json!({ "path": selected.path, "value": value }).to_string()
+                } else {
+                    value.to_string()
+                }
+            } else {
+                let text = match selected.node {
+                    Node::Block(b) => {
+                        let file = AlnFile { items: vec![AlnItem::from_block(b.clone())], trailing_comments: Vec::new() };
+                        printer::print(&file).trim_end().to_string()
+                    }
+                    Node::Value(Value::Str(s)) => s.clone(),
+                    Node::Value(v) => printer::print_value(v),
+                };
+                if args.switch("--paths") {
+                    format!("{}\t{}", selected.path, text)
+                } else {
+                    text
+                }
+            };
+            println!("{}", line);
+        }
+    }
+    Ok(if found { 0 } else { 1 })
+}
+
+fn diff(args: &[String]) -> Result<i32> {
+    let args = Args::parse(args, &["--plan"], &["--format"])?;
+    let [old, new] = args.positional[..] else {
+        bail!("diff: expected two plans\n\n{}", USAGE);
+    };
+    let diff = if args.switch("--plan") {
+        diff::diff_plan_sets(&AlnPlanSet::from_file(old)?, &AlnPlanSet::from_file(new)?)
+    } else {
+        diff::diff_files(&parser::parse_file(old)?, &parser::parse_file(new)?)
+    };
+    match args.option("--format").unwrap_or("human") {
+        "human" => print!("{}", diff),
+        "json" => println!("{}", serde_json::to_string_pretty(&diff)?),
+        "markdown" => print!("{}", diff.to_markdown()),
+        other => bail!("unknown diff format `{}`; expected human, json or markdown", other),
+    }
+    Ok(if diff.is_empty() { 0 } else { 1 })
+}
+
+fn migrate(args: &[String]) -> Result<i32> {
+    let args = Args::parse(args, &["--write"], &["--to"])?;
+    if args.positional.is_empty() {
+        bail!("migrate: no input files\n\n{}", USAGE);
+    }
+    let to = match args.option("--to") {
+        Some(version) => version.parse::<PlanVersion>()?,
+        None => NEWEST_SUPPORTED,
+    };
+    to.check_supported()?;
+
+    let write = args.switch("--write");
+    let registry = migrate::Registry::builtin();
+    for path in args.positional {
+        // Includes stay as written: each included file is migrated on its own.
+        let src = fs::read_to_string(path)?;
+        let before = parser::parse_str(&src, Some(path))?;
+        let mut after = before.clone();
+        let migrated = registry.migrate(&mut after, to).map_err(|e| anyhow!("{}: {}", path, e))?;
+        for system in &migrated {
+            let name = match &system.system {
+                Some(name) => format!("{} ({})", path, name),
+                None => path.to_string(),
+            };
+            let from = if system.declared {
+                system.from.to_string()
+            } else {
+                format!("{} (undeclared)", system.from)
+            };
+            if !system.changed() {
+                println!("{}: already at {}", name, system.to);
+            } else if write {
+                println!("migrated {} from {} to {}", name, from, system.to);
+            } else {
+                println!("{}: {} -> {}", name, from, system.to);
+                for step in &system.steps {
+                    println!("  step: {}", step);
+                }
+            }
+        }
+        if !migrated.iter().any(migrate::Migrated::changed) {
+            continue;
+        }
+        if write && migrated.iter().all(|m| m.steps.is_empty()) {
+            // Only the version changes, so leave the rest of the file as written.
+            fs::write(path, migrate::set_version_text(&src, &before, to))?;
+        } else if write {
+            fs::write(path, printer::print(&after))?;
+        } else {
+            for change in diff::diff_files(&before, &after).changes {
+                println!("  {}: {}", change.path, change.kind);
+            }
+        }
+    }
+    Ok(0)
+}
@@ -1,13 +1,13 @@
 mod steps;
 
 use crate::{
-    aln::AlnUpdatePlan,
+    aln::{AlnPlanSet, Strictness},
     db::{postgres::PgPool, redis::RedisClient},
     kafka::{Config as KafkaConfig, consumer::KafkaConsumer, producer::KafkaProducer},
     opa::Client as OpaClient,
 };
 use anyhow::Result;
-use tracing::{info, error};
+use tracing::{info, warn, error};
 
 pub struct Orchestrator {
     kafka_cfg: KafkaConfig,
@@ -31,14 +31,21 @@ impl Orchestrator {
     }
 
     pub async fn run(&self) -> Result<()> {
-        info!("Loading ALN update plan...");
-        let plan = AlnUpdatePlan::from_file("aln/system_update_integration_v1.7.aln")?;
+        info!("Loading ALN update plans...");
+        let (set, warnings) =
+            AlnPlanSet::load_file("aln/system_update_integration_v1.7.aln", Strictness::Lenient)?;
+        if !warnings.diagnostics.is_empty() {
+            warn!("ALN update plans have warnings:\n{}", warnings);
+        }
 
-        info!("Validating plan with OPA...");
-        steps::validate_with_opa(&self.opa, &plan).await?;
+        for system in &set.systems {
+            let name = system.name.as_deref().unwrap_or("default");
+            info!("Validating plan for update system {} with OPA...", name);
+            steps::validate_with_opa(&self.opa, &system.plan).await?;
 
-        info!("Processing files, syncing DBs, and publishing Kafka events...");
-        steps::process_files_and_sync(self, &plan).await?;
+            info!("Processing files, syncing DBs, and publishing Kafka events for {}...", name);
+            steps::process_files_and_sync(self, &system.plan).await?;
+        }
 
         info!("Update pipeline completed successfully.");
         Ok(())
@@ -44,10 +44,11 @@ pub async fn process_files_and_sync(
     let compliance_score = Some(0.99999999999_f64);
     let latency = Some("10^-17s");
     let sync_status = Some("all_nodes_databases_vm_lan");
+    let version = plan.version.to_string();
 
     let payload = json!({
         "token_id": token_id,
-        "version": plan.version,
+        "version": version,
         "features": plan.rego_exec.features,
         "timestamp": Utc::now(),
     });
@@ -55,7 +56,7 @@ pub async fn process_files_and_sync(
     // Write update record to PostgreSQL
     postgres::insert_update_record(
         &orchestrator.pg_pool,
-        &plan.version,
+        &version,
         &plan.rego_exec.features,
     )
     .await?;
@@ -64,7 +65,7 @@ pub async fn process_files_and_sync(
     postgres::insert_update_log(
         &orchestrator.pg_pool,
         token_id,
-        &plan.version,
+        &version,
         43,
         plan.rego_exec.features.len() as i32,
         compliance_score,
@@ -78,7 +79,7 @@ pub async fn process_files_and_sync(
     redis::save_state(
         &orchestrator.redis,
         token_id,
-        &plan.version,
+        &version,
         &plan.rego_exec.features,
     )
     .await?;
@@ -87,14 +88,14 @@ pub async fn process_files_and_sync(
     kafka::producer::publish_file_update(
         &orchestrator.producer,
         &orchestrator.kafka_cfg.file_update_topic,
-        &plan.version,
+        &version,
     )
     .await?;
 
     kafka::producer::publish_progress(
         &orchestrator.producer,
         &orchestrator.kafka_cfg.progress_topic,
-        &plan.version,
+        &version,
         43,
         plan.rego_exec.features.len() as i32,
     )
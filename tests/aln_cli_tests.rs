@@ -0,0 +1,54 @@
+use std::fs;
+use std::process::{Command, Output};
+
+mod common;
+use common::TempDir;
+
+const PLAN: &str = "aln/system_update_integration_v1.7.aln";
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_aln-system-update-orchestrator")).args(args).output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn migrate_declares_the_version_of_unversioned_plans() {
+    let dir = TempDir::new("migrate_declares_the_version_of_unversioned_plans");
+    let src = fs::read_to_string(PLAN).unwrap();
+    let path = dir.write("unversioned.aln", &src);
+
+    let preview = run(&["migrate", &path]);
+    assert!(preview.status.success(), "{preview:?}");
+    assert_eq!(
+        stdout(&preview),
+        format!("{path}: 1.0.1.7 (undeclared) -> 1.0.1.7\n  ALN_UPDATE_SYSTEM.version: added as '1.0.1.7'\n")
+    );
+    assert_eq!(fs::read_to_string(&path).unwrap(), src, "a preview must not write");
+
+    let write = run(&["migrate", "--write", &path]);
+    assert!(write.status.success(), "{write:?}");
+    assert_eq!(stdout(&write), format!("migrated {path} from 1.0.1.7 (undeclared) to 1.0.1.7\n"));
+    let migrated = fs::read_to_string(&path).unwrap();
+    assert_eq!(migrated, src.replacen("@ALN_UPDATE_SYSTEM {", "@ALN_UPDATE_SYSTEM {\n  version: '1.0.1.7',", 1));
+
+    let again = run(&["migrate", &path]);
+    assert_eq!(stdout(&again), format!("{path}: already at 1.0.1.7\n"));
+    let validate = run(&["validate", &path]);
+    assert!(validate.status.success(), "{validate:?}");
+    assert!(validate.stderr.is_empty(), "{}", String::from_utf8_lossy(&validate.stderr));
+}
+
+#[test]
+fn migrate_refuses_plans_newer_than_the_target() {
+    let dir = TempDir::new("migrate_refuses_plans_newer_than_the_target");
+    let path = dir.write("newer.aln", "@ALN_UPDATE_SYSTEM '1.0.1.7' { }\n");
+    let output = run(&["migrate", "--to", "1.0.1.0", &path]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!("{path}: plan version 1.0.1.7 is newer than the target version 1.0.1.0\n")
+    );
+}
@@ -0,0 +1,107 @@
+use aln_system_update_orchestrator::aln::convert::{from_str, to_string, Format};
+use aln_system_update_orchestrator::aln::printer::print;
+
+const PLAN: &str = "\
+# header
+@STEP deploy after=build timeout=30 {
+  image: 'web:1.2',
+  retries: 3,
+  ratio: 1.0,
+  limits: { mem: '4Gi', cpu: 2, nested: { b: false, a: [1, 2.5] } },
+  // hooks follow
+  @HOOK {},
+  script: '''
+set -e
+echo \"done\"
+'''
+  /* end of step */
+}
+
+@META {
+  owner: 'platform'
+}
+
+// trailing
+";
+
+#[test]
+fn converts_to_every_format_and_back_losslessly() {
+    let original = from_str(PLAN, Format::Aln, "plan.aln").expect("failed to parse");
+    let canonical = print(&original);
+    for format in [Format::Json, Format::Yaml, Format::Toml] {
+        let converted = to_string(&original, format).unwrap_or_else(|e| panic!("{format:?}: {e}"));
+        let back = from_str(&converted, format, "plan").unwrap_or_else(|e| panic!("{format:?}: {e}\n{converted}"));
+        assert_eq!(print(&back), canonical, "{format:?} output:\n{converted}");
+    }
+}
+
+#[test]
+fn json_mapping_is_plain_and_ordered() {
+    let file = from_str(PLAN, Format::Aln, "plan.aln").unwrap();
+    let json: serde_json::Value = serde_json::from_str(&to_string(&file, Format::Json).unwrap()).unwrap();
+
+    let step = &json["items"][0];
+    assert_eq!(step["block"], "STEP");
+    assert_eq!(step["args"][0], serde_json::json!({ "value": "deploy" }));
+    assert_eq!(step["args"][2], serde_json::json!({ "name": "timeout", "value": 30 }));
+    assert_eq!(step["comments"][0], "# header");
+    assert_eq!(step["body"][1], serde_json::json!({ "key": "retries", "value": 3 }));
+    assert!(step["body"][2]["value"].is_f64());
+    assert_eq!(step["body"][4]["block"], "HOOK");
+    assert_eq!(json["trailing_comments"][0], "// trailing");
+
+    let text = to_string(&file, Format::Json).unwrap();
+    assert!(text.find("\"mem\"").unwrap() < text.find("\"cpu\"").unwrap());
+}
+
+#[test]
+fn format_is_inferred_from_extension() {
+    assert_eq!(Format::from_path("plans/prod.yml"), Some(Format::Yaml));
+    assert_eq!(Format::from_path("plan.ALN"), Some(Format::Aln));
+    assert_eq!(Format::from_path("plan.txt"), None);
+}
+
+#[test]
+fn quoted_keys_and_bare_lists_survive_a_round_trip() {
+    let json = r#"{
+  "items": [
+    {
+      "block": "PORTS",
+      "body": [
+        { "key": "my key", "value": 1 },
+        { "key": "x-forwarded-for", "value": "on" },
+        [80, 443]
+      ]
+    }
+  ]
+}
+"#;
+    let file = from_str(json, Format::Json, "plan.json").unwrap();
+    let aln = to_string(&file, Format::Aln).unwrap();
+    assert!(aln.contains("'my key': 1"), "{aln}");
+    let back = from_str(&aln, Format::Aln, "plan.aln").unwrap_or_else(|e| panic!("{e}\n{aln}"));
+    assert_eq!(to_string(&back, Format::Json).unwrap(), to_string(&file, Format::Json).unwrap());
+}
+
+#[test]
+fn names_without_an_aln_spelling_are_refused() {
+    let json = r#"{ "items": [{ "block": "my block", "body": [] }] }"#;
+    let file = from_str(json, Format::Json, "plan.json").unwrap();
+    let err = to_string(&file, Format::Aln).unwrap_err().to_string();
+    assert_eq!(err, "cannot write block name `my block` as ALN: it is not an identifier");
+
+    let json = r#"{ "items": [{ "block": "X", "args": [{ "value": { "a": 1 } }], "body": [] }] }"#;
+    let file = from_str(json, Format::Json, "plan.json").unwrap();
+    let err = to_string(&file, Format::Aln).unwrap_err().to_string();
+    assert_eq!(err, "cannot write argument `{ a: 1 }` of @X as ALN: a positional argument cannot be an object");
+
+    let json = r#"{ "items": [{ "block": "X", "args": [{ "name": "limits", "value": { "a": 1 } }], "body": [] }] }"#;
+    let file = from_str(json, Format::Json, "plan.json").unwrap();
+    let aln = to_string(&file, Format::Aln).unwrap();
+    let back = from_str(&aln, Format::Aln, "plan.aln").unwrap_or_else(|e| panic!("{e}\n{aln}"));
+    assert_eq!(to_string(&back, Format::Json).unwrap(), to_string(&file, Format::Json).unwrap());
+
+    let json = r#"{ "items": [{ "block": "STEP", "args": [{ "name": "a b", "value": 1 }], "body": [] }] }"#;
+    let file = from_str(json, Format::Json, "plan.json").unwrap();
+    assert!(to_string(&file, Format::Aln).unwrap_err().to_string().contains("argument name `a b`"));
+}
@@ -0,0 +1,167 @@
+use aln_system_update_orchestrator::aln::ast::Value;
+use aln_system_update_orchestrator::aln::diff::{diff_files, diff_plans, Change, ChangeKind};
+use aln_system_update_orchestrator::aln::parser::{parse_file, parse_str};
+use aln_system_update_orchestrator::aln::printer::print;
+use aln_system_update_orchestrator::aln::AlnUpdatePlan;
+use std::fs;
+
+const PLAN: &str = "aln/system_update_integration_v1.7.aln";
+
+fn str(s: &str) -> Value {
+    Value::Str(s.to_string())
+}
+
+#[test]
+fn reports_what_a_plan_change_means() {
+    let src = fs::read_to_string(PLAN).unwrap();
+    let edited = src
+        .replace("'manifest_scaling'", "'manifest_scaling', 'audit_log'")
+        .replace("chat_window_drop_with_history", "full_screen")
+        .replace("'web_browser', ", "");
+    let interop = edited.find("  // cross").unwrap()..edited.find("  @RENDER").unwrap();
+    let edited = format!("{}{}", &edited[..interop.start], &edited[interop.end..]);
+
+    let diff = diff_files(&parse_file(PLAN).unwrap(), &parse_str(&edited, None).unwrap());
+    assert_eq!(
+        diff.changes,
+        [
+            Change {
+                path: "ALN_UPDATE_SYSTEM.RENDER_IN_FRAME.mode".into(),
+                kind: ChangeKind::Changed { old: str("chat_window_drop_with_history"), new: str("full_screen") },
+            },
+            Change {
+                path: "ALN_UPDATE_SYSTEM.RENDER_IN_FRAME.playable_platforms".into(),
+                kind: ChangeKind::ItemsRemoved { items: vec![str("web_browser")] },
+            },
+            Change {
+                path: "ALN_UPDATE_SYSTEM.EXEC_REGO_POLICY.features".into(),
+                kind: ChangeKind::ItemsAdded { items: vec![str("audit_log")] },
+            },
+            Change { path: "ALN_UPDATE_SYSTEM.INTEROP".into(), kind: ChangeKind::BlockRemoved },
+        ]
+    );
+    assert_eq!(
+        diff.to_markdown().lines().last().unwrap(),
+        "| `ALN_UPDATE_SYSTEM.INTEROP` | block removed |"
+    );
+    assert_eq!(
+        serde_json::to_value(&diff).unwrap()[2],
+        serde_json::json!({
+            "path": "ALN_UPDATE_SYSTEM.EXEC_REGO_POLICY.features",
+            "change": "items_added",
+            "items": ["audit_log"],
+        })
+    );
+}
+
+#[test]
+fn formatting_comments_and_order_are_not_changes() {
+    let old = parse_file(PLAN).unwrap();
+    let mut reordered = parse_file(PLAN).unwrap();
+    let root = reordered.items[0].block_mut();
+    root.body.reverse();
+    root.comments.clear();
+    let reprinted = parse_str(&print(&reordered).replace("  ", "\t"), None).unwrap();
+
+    assert!(diff_files(&old, &reprinted).is_empty());
+    assert_eq!(diff_files(&old, &reprinted).to_markdown(), "No changes.\n");
+}
+
+#[test]
+fn reordering_a_list_is_a_change() {
+    let old = parse_file(PLAN).unwrap();
+    let src = fs::read_to_string(PLAN).unwrap();
+    let swapped = src.replace("'any_ai_chat', 'web_browser'", "'web_browser', 'any_ai_chat'");
+    let diff = diff_files(&old, &parse_str(&swapped, None).unwrap());
+    assert_eq!(
+        diff.changes,
+        [Change {
+            path: "ALN_UPDATE_SYSTEM.RENDER_IN_FRAME.playable_platforms".into(),
+            kind: ChangeKind::Reordered {
+                old: vec![str("any_ai_chat"), str("web_browser"), str("all_systems_with_k8s")],
+                new: vec![str("web_browser"), str("any_ai_chat"), str("all_systems_with_k8s")],
+            },
+        }]
+    );
+
+    // Only the items kept in both lists are compared for order.
+    let edited = src.replace("'repo_tracking', 'commit_automation', ", "'commit_automation', 'audit_log', ");
+    assert_eq!(
+        diff_files(&old, &parse_str(&edited, None).unwrap()).to_string(),
+        "ALN_UPDATE_SYSTEM.EXEC_REGO_POLICY.features: added 'audit_log'\n\
+         ALN_UPDATE_SYSTEM.EXEC_REGO_POLICY.features: removed 'repo_tracking'\n"
+    );
+}
+
+#[test]
+fn matches_blocks_by_arguments_then_name() {
+    let old = parse_str(
+        "@PIPE {
+  @STAGE build timeout=30 { cpu: 2 }
+  @STAGE test { env: { tier: 'dev', tags: [['a'], ['b']] } }
+  @JOB { n: 1 }
+  @JOB { n: 2 }
+}",
+        None,
+    )
+    .unwrap();
+    let new = parse_str(
+        "@PIPE {
+  @STAGE test { env: { tags: [['a'], ['c'], ['d']], tier: 'prod' } }
+  @STAGE compile timeout=60 { cpu: 2 }
+  @JOB { n: 1 }
+  @JOB { n: 3 }
+  @NOTIFY { }
+}",
+        None,
+    )
+    .unwrap();
+    assert_eq!(
+        diff_files(&old, &new).to_string(),
+        "PIPE.STAGE(test).env.tags[1]: added 'c'
+PIPE.STAGE(test).env.tags[1]: removed 'b'
+PIPE.STAGE(test).env.tags: added ['d']
+PIPE.STAGE(test).env.tier: changed from 'dev' to 'prod'
+PIPE.STAGE(compile).@args: changed from ['build'] to ['compile']
+PIPE.STAGE(compile).@timeout: changed from 30 to 60
+PIPE.JOB[1].n: changed from 2 to 3
+PIPE.NOTIFY: block added
+"
+    );
+}
+
+#[test]
+fn plan_diffs_compare_loaded_values() {
+    let explicit = AlnUpdatePlan::from_file(PLAN).unwrap();
+    let src = fs::read_to_string(PLAN).unwrap();
+    // `maintain_func` defaults to true, and so does the version.
+    let implicit = src
+        .replace("    maintain_func: true,\n", "")
+        .replace("@ALN_UPDATE_SYSTEM {", "@ALN_UPDATE_SYSTEM '1.0.1.7' {");
+    let implicit = AlnUpdatePlan::from_str(&implicit, None).unwrap();
+    assert!(diff_plans(&explicit, &implicit).is_empty());
+
+    let older = AlnUpdatePlan::from_str(&src.replace("@ALN_UPDATE_SYSTEM {", "@ALN_UPDATE_SYSTEM '1.0.1.6' {"), None).unwrap();
+    assert_eq!(
+        diff_plans(&older, &explicit).to_string(),
+        "ALN_UPDATE_SYSTEM.version: changed from '1.0.1.6' to '1.0.1.7'\n"
+    );
+}
+
+#[test]
+fn markdown_uses_the_same_words_and_keeps_code_in_its_cell() {
+    let old = parse_str("@A { cmd: 'a | b', tags: ['x', 'y'] }", None).unwrap();
+    let new = parse_str("@A { cmd: 'echo `date`', tags: ['y', 'x'] }", None).unwrap();
+    let diff = diff_files(&old, &new);
+
+    assert_eq!(
+        diff.to_markdown(),
+        "| Path | Change |\n| --- | --- |\n\
+         | `A.cmd` | changed from `'a \\| b'` to ``'echo `date`'`` |\n\
+         | `A.tags` | reordered from `['x', 'y']` to `['y', 'x']` |\n"
+    );
+    assert_eq!(
+        diff.to_string(),
+        "A.cmd: changed from 'a | b' to 'echo `date`'\nA.tags: reordered from ['x', 'y'] to ['y', 'x']\n"
+    );
+}
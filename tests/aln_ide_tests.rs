@@ -0,0 +1,96 @@
+use aln_system_update_orchestrator::aln::diagnostic::Severity;
+use aln_system_update_orchestrator::aln::ide::{Analysis, CompletionKind};
+use std::path::Path;
+
+mod common;
+use common::TempDir;
+
+const PLAN: &str = "aln/system_update_integration_v1.7.aln";
+
+/// Analyse `src` with the cursor at the `|` marker, which is removed.
+fn at_cursor(path: &str, src: &str) -> (Analysis, usize) {
+    let offset = src.find('|').expect("no cursor marker");
+    let text = src.replacen('|', "", 1);
+    (Analysis::new(path, &text), offset)
+}
+
+#[test]
+fn reports_syntax_errors_then_schema_warnings() {
+    let analysis = Analysis::new("plan.aln", "@INTEROP {\n  cross_link 'x'\n}\n");
+    let [d] = analysis.diagnostics() else { panic!("{:?}", analysis.diagnostics()) };
+    assert_eq!(d.severity, Severity::Error);
+    assert!(d.message.starts_with("expected `:` after key `cross_link`"), "{}", d.message);
+
+    let src = std::fs::read_to_string(PLAN)
+        .unwrap()
+        .replace("maintain_func", "maintian_func")
+        .replace("@ALN_UPDATE_SYSTEM {", "@ALN_UPDATE_SYSTEM '1.0.1.7' {");
+    let analysis = Analysis::new(PLAN, &src);
+    let [d] = analysis.diagnostics() else { panic!("{:?}", analysis.diagnostics()) };
+    assert_eq!(d.severity, Severity::Warning);
+    assert_eq!(d.message, "unknown key `maintian_func` in @INTEROP");
+    assert_eq!(d.notes, ["did you mean `maintain_func`?"]);
+    assert_eq!(&src[d.span.start..d.span.end], "maintian_func");
+
+    // Fragments meant for inclusion are not checked as whole plans.
+    assert!(Analysis::new("interop.aln", "@INTEROP { enable_lan: 'on' }").diagnostics().is_empty());
+}
+
+#[test]
+fn hovers_show_block_and_key_docs() {
+    let (analysis, offset) = at_cursor("plan.aln", "@ALN_UPDATE_SYSTEM {\n  @INTE|ROP {\n    maintain_func: true\n  }\n}\n");
+    let hover = analysis.hover(offset).unwrap();
+    assert!(hover.contents.starts_with("```aln\n@INTEROP { ... }\n```\nCross-process links"), "{}", hover.contents);
+    assert!(hover.contents.contains("- `maintain_func`: bool"), "{}", hover.contents);
+
+    // Blocks are known outside a plan too, as in an included file.
+    let (analysis, offset) = at_cursor("interop.aln", "@INTEROP {\n  maintain|_func: true\n}\n");
+    let hover = analysis.hover(offset).unwrap();
+    assert_eq!(
+        hover.contents,
+        "```aln\nmaintain_func: bool\n```\nKeep existing functionality working during the update.\n\nDefault: `true`"
+    );
+
+    let (analysis, offset) = at_cursor("plan.aln", "@CUSTOM {\n  ke|y: 1\n}\n");
+    assert_eq!(analysis.hover(offset), None);
+}
+
+#[test]
+fn completes_missing_keys_and_blocks() {
+    let (analysis, offset) = at_cursor("plan.aln", "@ALN_UPDATE_SYSTEM {\n  @RENDER_IN_FRAME {\n    mode: 'x',\n    |\n  }\n}\n");
+    let labels: Vec<_> = analysis.completions(offset).into_iter().map(|c| c.label).collect();
+    assert_eq!(labels, ["merge_sources", "playable_platforms"]);
+
+    let (analysis, offset) = at_cursor("plan.aln", "@ALN_UPDATE_SYSTEM {\n  version: '1.0.1.7',\n  @SEPARATE {},\n  @|\n}\n");
+    let completions = analysis.completions(offset);
+    assert!(completions.iter().all(|c| c.kind == CompletionKind::Block));
+    let labels: Vec<_> = completions.iter().map(|c| c.label.as_str()).collect();
+    assert_eq!(labels, ["@INTEROP", "@RENDER_IN_FRAME", "@EXEC_REGO_POLICY"]);
+    assert_eq!(completions[0].insert_text, "INTEROP {\n}");
+
+    let (analysis, offset) = at_cursor("plan.aln", "|");
+    let labels: Vec<_> = analysis.completions(offset).into_iter().map(|c| c.insert_text).collect();
+    assert_eq!(labels, ["@ALN_UPDATE_SYSTEM {\n}", "@META {\n}"]);
+}
+
+#[test]
+fn goes_to_included_files_and_policies() {
+    let src = std::fs::read_to_string(PLAN).unwrap();
+    let offset = src.find("system_update_policy_v1.7.rego").unwrap() + 3;
+    let analysis = Analysis::new(PLAN, &src);
+    assert_eq!(analysis.definition(offset).as_deref(), Some(Path::new("aln/system_update_policy_v1.7.rego")));
+    assert_eq!(analysis.definition(src.find("chat_interface").unwrap()), None);
+
+    let dir = TempDir::new("aln_ide_tests_include");
+    let shared = dir.write("shared.aln", "@INTEROP {}\n");
+    let main = Path::new(&shared).with_file_name("main.aln");
+    let (analysis, offset) = at_cursor(main.to_str().unwrap(), "@ALN_UPDATE_SYSTEM {\n  @INCLUDE 'sha|red.aln'\n}\n");
+    assert_eq!(analysis.definition(offset), Some(shared.into()));
+}
+
+#[test]
+fn formats_only_valid_documents() {
+    let analysis = Analysis::new("plan.aln", "@INTEROP{cross_link:\"x\"}");
+    assert_eq!(analysis.format().as_deref(), Some("@INTEROP {\n  cross_link: 'x'\n}\n"));
+    assert_eq!(Analysis::new("plan.aln", "@INTEROP {").format(), None);
+}
@@ -0,0 +1,82 @@
+use aln_system_update_orchestrator::aln::ast::BlockEntry;
+use aln_system_update_orchestrator::aln::parser::{parse_file, parse_file_recovering, parse_file_unresolved};
+use aln_system_update_orchestrator::aln::{printer, AlnUpdatePlan};
+
+mod common;
+use common::TempDir;
+
+const SHARED_INTEROP: &str = "@INTEROP {\n  cross_link: 'shared',\n  enable_lan: 'on'\n}\n";
+const SHARED_REGO: &str = "@EXEC_REGO_POLICY {\n  policy: 'shared.rego',\n  features: ['a']\n}\n";
+
+#[test]
+fn includes_resolve_relative_to_the_including_file() {
+    let dir = TempDir::new("aln_include_tests_nested");
+    let root = dir.write(
+        "plans/service.aln",
+        "@ALN_UPDATE_SYSTEM {\n  @SEPARATE components { game_engine: 'x' },\n  \
+         @INCLUDE '../shared/all.aln',\n  @RENDER_IN_FRAME {}\n}\n",
+    );
+    dir.write("shared/all.aln", "@INCLUDE 'interop.aln'\n@INCLUDE 'policy/rego.aln'\n");
+    dir.write("shared/interop.aln", SHARED_INTEROP);
+    dir.write("shared/policy/rego.aln", SHARED_REGO);
+    let plan = AlnUpdatePlan::from_file(&root).unwrap();
+    assert_eq!(plan.interop.cross_link, "shared");
+    assert_eq!(plan.rego_exec.policy, "shared.rego");
+
+    let ast = parse_file(&root).unwrap();
+    let system = ast.items[0].block();
+    let origins: Vec<_> = system
+        .body
+        .iter()
+        .filter_map(|e| match e {
+            BlockEntry::NestedBlock(b) => Some((b.name.as_str(), b.origin.as_ref())),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(origins[0], ("SEPARATE", None));
+    let (name, origin) = origins[2];
+    let origin = origin.unwrap();
+    assert_eq!(name, "EXEC_REGO_POLICY");
+    assert!(origin.file.ends_with("rego.aln"), "{}", origin.file);
+    assert_eq!(origin.included_from.len(), 2);
+    assert!(origin.included_from[1].to_string().ends_with("service.aln:3:3"), "{:?}", origin);
+}
+
+#[test]
+fn include_cycles_are_reported_with_the_chain() {
+    let dir = TempDir::new("aln_include_tests_cycle");
+    let a = dir.write("a.aln", "@INCLUDE 'b.aln'\n");
+    dir.write("b.aln", "@INCLUDE 'a.aln'\n");
+    let parsed = parse_file_recovering(&a).unwrap();
+    let report = parsed.report.to_string();
+
+    assert!(report.contains("include cycle: "), "{report}");
+    assert!(report.contains("a.aln -> ") && report.contains("b.aln -> "), "{report}");
+    assert!(report.contains("b.aln:1:1"), "{report}");
+    assert!(report.contains("= note: included from "), "{report}");
+}
+
+#[test]
+fn errors_in_included_files_point_into_them() {
+    let dir = TempDir::new("aln_include_tests_errors");
+    let main = dir.write("main.aln", "@ALN_UPDATE_SYSTEM {\n  @INCLUDE 'broken.aln'\n}\n@INCLUDE 'missing.aln'\n");
+    dir.write("broken.aln", "@INTEROP {\n  cross_link 'x'\n}\n");
+    let parsed = parse_file_recovering(&main).unwrap();
+    let report = parsed.report.to_string();
+
+    assert!(report.contains("error: expected `:` after key `cross_link`, found string"), "{report}");
+    assert!(report.contains("broken.aln:2:14"), "{report}");
+    assert!(report.contains("2 |   cross_link 'x'"), "{report}");
+    assert!(report.contains("included from "), "{report}");
+    assert!(report.contains("main.aln:2:3"), "{report}");
+    assert!(report.contains("cannot include `"), "{report}");
+    assert!(report.contains("main.aln:4:10"), "{report}");
+}
+
+#[test]
+fn unresolved_parse_keeps_directives_for_formatting() {
+    let dir = TempDir::new("aln_include_tests_fmt");
+    let main = dir.write("main.aln", "@INCLUDE 'shared.aln'\n\n@ROOT {}\n");
+    let ast = parse_file_unresolved(&main).unwrap();
+    assert_eq!(printer::print(&ast), "@INCLUDE 'shared.aln'\n\n@ROOT {}\n");
+}
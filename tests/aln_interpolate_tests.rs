@@ -0,0 +1,104 @@
+use aln_system_update_orchestrator::aln::ast::{AlnFile, AlnItem, BlockEntry, Value};
+use aln_system_update_orchestrator::aln::interpolate::interpolate;
+use aln_system_update_orchestrator::aln::parser::parse_str;
+use aln_system_update_orchestrator::aln::{AlnUpdatePlan, LoadAlnError};
+
+fn env(name: &str) -> Option<String> {
+    match name {
+        "TIER" => Some("staging".into()),
+        "REGION" => Some("eu-1".into()),
+        _ => None,
+    }
+}
+
+/// Value of `key` in the top-level block `block`.
+fn get<'a>(file: &'a AlnFile, block: &str, key: &str) -> &'a Value {
+    file.items
+        .iter()
+        .map(AlnItem::block)
+        .filter(|b| b.name == block)
+        .flat_map(|b| &b.body)
+        .find_map(|e| match e {
+            BlockEntry::KeyValue { key: k, value, .. } if k == key => Some(value),
+            _ => None,
+        })
+        .unwrap()
+}
+
+#[test]
+fn resolves_env_vars_and_references() {
+    let src = "@VARS { tier: '${env.TIER}', maintain: false, ports: [80, 443] }\n\
+               @APP {\n  \
+                 host: 'app.${vars.tier}.${env.REGION}.internal',\n  \
+                 maintain: '${vars.maintain}',\n  \
+                 ports: '${vars.ports}',\n  \
+                 mirror: '${host}',\n  \
+                 literal: 'cost: $5, template: $${vars.tier}'\n\
+               }\n";
+    let mut file = parse_str(src, None).unwrap();
+    let errors = interpolate(&mut file, env);
+
+    assert!(errors.is_empty(), "{errors:?}");
+    assert_eq!(get(&file, "APP", "host"), &Value::Str("app.staging.eu-1.internal".into()));
+    assert_eq!(get(&file, "APP", "maintain"), &Value::Bool(false));
+    assert_eq!(get(&file, "APP", "ports"), &Value::Array(vec![Value::Integer(80), Value::Integer(443)]));
+    assert_eq!(get(&file, "APP", "mirror"), get(&file, "APP", "host"));
+    assert_eq!(get(&file, "APP", "literal"), &Value::Str("cost: $5, template: ${vars.tier}".into()));
+}
+
+#[test]
+fn reports_undefined_names_and_cycles_once() {
+    let src = "@APP {\n  a: '${b}',\n  b: 'x-${a}',\n  c: '${env.MISSING}',\n  d: '${c}',\n  \
+               e: '${vars.nope}',\n  f: '${NOPE.key}',\n  g: 'list ${ports}',\n  ports: [1]\n}\n";
+    let mut file = parse_str(src, None).unwrap();
+    let messages: Vec<String> = interpolate(&mut file, env).into_iter().map(|d| d.message).collect();
+
+    assert_eq!(
+        messages,
+        [
+            "reference cycle: APP.a -> APP.b -> APP.a",
+            "undefined environment variable `MISSING`",
+            "undefined variable `nope`",
+            "`NOPE.key` does not name a key",
+            "`ports` is not a scalar and cannot be embedded in a string",
+        ]
+    );
+}
+
+#[test]
+fn plans_reference_other_sections() {
+    let src = "@ALN_UPDATE_SYSTEM {\n  @SEPARATE components {},\n  @INTEROP { enable_lan: 'lan-on' },\n  \
+               @RENDER_IN_FRAME { mode: '${INTEROP.enable_lan}' },\n  @EXEC_REGO_POLICY {}\n}\n";
+    let plan = AlnUpdatePlan::from_str(src, None).unwrap();
+    assert_eq!(plan.render.mode, "lan-on");
+
+    let src = "@ALN_UPDATE_SYSTEM {\n  @RENDER_IN_FRAME { mode: '${INTEROP.missing}' }\n}\n";
+    match AlnUpdatePlan::from_str(src, Some("plan.aln")) {
+        Err(LoadAlnError::Resolve(report)) => {
+            let text = report.to_string();
+            assert!(text.contains("error: `INTEROP.missing` does not name a key"), "{text}");
+            assert!(text.contains("plan.aln:2:28"), "{text}");
+        }
+        other => panic!("expected a resolve error, got {other:?}"),
+    }
+}
+
+#[test]
+fn leaves_raw_strings_alone() {
+    let src = "@VARS { tier: 'prod' }\n\
+               @APP {\n  \
+                 hook: r'echo ${HOME}',\n  \
+                 steps: [r\"cd ${DIR}\", '${vars.tier}'],\n  \
+                 name: '${vars.tier}'\n\
+               }\n";
+    let mut file = parse_str(src, None).unwrap();
+    let errors = interpolate(&mut file, env);
+
+    assert!(errors.is_empty(), "{errors:?}");
+    assert_eq!(get(&file, "APP", "hook"), &Value::Str("echo ${HOME}".into()));
+    assert_eq!(
+        get(&file, "APP", "steps"),
+        &Value::Array(vec![Value::Str("cd ${DIR}".into()), Value::Str("prod".into())])
+    );
+    assert_eq!(get(&file, "APP", "name"), &Value::Str("prod".into()));
+}
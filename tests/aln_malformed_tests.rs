@@ -0,0 +1,147 @@
+use aln_system_update_orchestrator::aln::diagnostic::Report;
+use aln_system_update_orchestrator::aln::lexer::lex_recovering;
+use aln_system_update_orchestrator::aln::parser::{parse_str, MAX_DEPTH};
+use aln_system_update_orchestrator::aln::printer::print;
+use aln_system_update_orchestrator::aln::span::Span;
+use aln_system_update_orchestrator::aln::{AlnUpdatePlan, LoadAlnError};
+use proptest::prelude::*;
+
+/// Malformed inputs, also the seed corpus for `fuzz/`.
+const CORPUS: &str = "tests/malformed";
+
+const PLAN: &str = "aln/system_update_integration_v1.7.aln";
+
+/// Fragments that tend to reach error paths when glued together.
+const PIECES: &[&str] = &[
+    "@", "{", "}", "[", "]", ":", ",", "=", "'", "\"", "'''", "r#\"", "\"#", "\\", "\\u{", "/*", "*/", "#", "//",
+    "\n", "\r", " ", "INCLUDE", "key", "ALN_UPDATE_SYSTEM", "version", "1.0.1.7", "-", "1e", "0.5", "9999999999999999999",
+    "true", "${", "$${", "env.", "vars.", "é", "\u{feff}",
+];
+
+fn assert_in_bounds(src: &str, span: Span) {
+    assert!(
+        span.start <= span.end && span.end <= src.len(),
+        "span {span:?} outside input of {} bytes",
+        src.len()
+    );
+    assert!(src.is_char_boundary(span.start) && src.is_char_boundary(span.end), "span {span:?} splits a character");
+}
+
+fn assert_renders(src: &str, report: &Report) {
+    for d in &report.diagnostics {
+        assert_in_bounds(src, d.span);
+    }
+    assert!(report.has_errors());
+    assert!(report.to_string().contains("error"));
+}
+
+/// Everything the front end does with untrusted text: lex, parse, and load as
+/// a plan. None of it may panic; errors must point inside the input; a file
+/// that parses must print to something that parses the same way.
+fn check(src: &str) {
+    let (tokens, errors) = lex_recovering(src);
+    for t in &tokens {
+        assert_in_bounds(src, t.span);
+        assert_eq!(t.text, &src[t.span.start..t.span.end]);
+    }
+    for e in &errors {
+        assert_in_bounds(src, e.span());
+    }
+
+    match parse_str(src, None) {
+        Ok(ast) => {
+            let printed = print(&ast);
+            let again = parse_str(&printed, None).unwrap_or_else(|e| panic!("printed form does not parse:\n{e}"));
+            assert_eq!(print(&again), printed);
+        }
+        Err(LoadAlnError::Syntax(report)) => assert_renders(src, &report),
+        Err(e) => panic!("unexpected error kind: {e:?}"),
+    }
+
+    match AlnUpdatePlan::from_str(src, None) {
+        Ok(_) => {}
+        Err(LoadAlnError::Syntax(report) | LoadAlnError::Resolve(report) | LoadAlnError::Schema(report)) => {
+            for d in &report.diagnostics {
+                // A missing root block has nothing to point at.
+                if d.span != Span::default() {
+                    assert_in_bounds(src, d.span);
+                }
+            }
+            let _ = report.to_string();
+        }
+        Err(e) => panic!("unexpected error kind: {e:?}"),
+    }
+}
+
+#[test]
+fn malformed_corpus_is_rejected_with_located_errors() {
+    let mut paths: Vec<_> = std::fs::read_dir(CORPUS).unwrap().map(|e| e.unwrap().path()).collect();
+    paths.sort();
+    assert!(paths.len() >= 20, "corpus went missing");
+    for path in paths {
+        let bytes = std::fs::read(&path).unwrap();
+        let src = String::from_utf8(bytes).unwrap_or_else(|_| panic!("{}: not UTF-8", path.display()));
+        check(&src);
+        match AlnUpdatePlan::from_str(&src, Some("case.aln")) {
+            Err(LoadAlnError::Syntax(report) | LoadAlnError::Resolve(report)) => assert_renders(&src, &report),
+            other => panic!("{}: expected a syntax or resolve error, got {other:?}", path.display()),
+        }
+    }
+}
+
+#[test]
+fn deep_nesting_is_an_error_not_a_stack_overflow() {
+    let deep = |open: &str, close: &str, n: usize| format!("@A {{ k: {}{} }}", open.repeat(n), close.repeat(n));
+    let cases = [
+        format!("{}{}", "@A {\n".repeat(10_000), "}\n".repeat(10_000)),
+        "@A {\n".repeat(10_000),
+        deep("[", "]", 30_000),
+        deep("{ a: ", "}", 30_000),
+        deep("[{ a: ", "}]", 15_000),
+        deep("[", "", 30_000),
+    ];
+    for src in &cases {
+        check(src);
+        let err = parse_str(src, None).unwrap_err().to_string();
+        assert!(err.contains("nesting too deep"), "{err}");
+        assert_eq!(err.matches("nesting too deep").count(), 1, "{err}");
+    }
+
+    // Up to the limit is fine.
+    let src = format!("{}{}", "@A {\n".repeat(MAX_DEPTH), "}\n".repeat(MAX_DEPTH));
+    parse_str(&src, None).unwrap();
+    parse_str(&deep("[", "]", MAX_DEPTH - 1), None).unwrap();
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(1024))]
+
+    #[test]
+    fn arbitrary_text_never_panics(src in any::<String>()) {
+        check(&src);
+    }
+
+    #[test]
+    fn token_soup_never_panics(pieces in prop::collection::vec(prop::sample::select(PIECES), 0..48)) {
+        check(&pieces.concat());
+    }
+
+    #[test]
+    fn damaged_plans_never_panic(
+        edits in prop::collection::vec((any::<prop::sample::Index>(), 0..4usize, prop::sample::select(PIECES)), 1..4)
+    ) {
+        let mut src = std::fs::read_to_string(PLAN).unwrap();
+        for (at, cut, piece) in edits {
+            let mut start = at.index(src.len() + 1);
+            while !src.is_char_boundary(start) {
+                start -= 1;
+            }
+            let mut end = (start + cut).min(src.len());
+            while !src.is_char_boundary(end) {
+                end += 1;
+            }
+            src.replace_range(start..end, piece);
+        }
+        check(&src);
+    }
+}
@@ -0,0 +1,188 @@
+use aln_system_update_orchestrator::aln::merge::{merge, merge_files, Action};
+use aln_system_update_orchestrator::aln::parser::parse_str;
+use aln_system_update_orchestrator::aln::printer::print;
+use aln_system_update_orchestrator::aln::{AlnUpdatePlan, LoadAlnError};
+
+mod common;
+use common::TempDir;
+
+const BASE: &str = "\
+@ALN_UPDATE_SYSTEM {
+  @SEPARATE components {
+    game_engine: 'unity',
+    renderers: ['gl']
+  },
+  @INTEROP {
+    cross_link: 'ipc',
+    enable_lan: 'off'
+  },
+  @RENDER_IN_FRAME {
+    mode: 'debug',
+    playable_platforms: ['linux']
+  },
+  @EXEC_REGO_POLICY {
+    policy: 'base.rego',
+    features: ['a', 'b']
+  }
+}
+";
+
+const PROD: &str = "\
+@ALN_UPDATE_SYSTEM {
+  @SEPARATE components {
+    renderers: ['vulkan']
+  },
+  @INTEROP merge='replace' {
+    enable_lan: 'on'
+  },
+  @RENDER_IN_FRAME {
+    mode: 'prod',
+    labels: { tier: 'prod' }
+  },
+  @EXEC_REGO_POLICY replace=['features'] {
+    features: ['c']
+  }
+}
+";
+
+#[test]
+fn overlays_merge_by_the_documented_rules() {
+    let dir = TempDir::new("overlays_merge_by_the_documented_rules");
+    let base = dir.write("base.aln", BASE);
+    let prod = dir.write("prod.aln", PROD);
+    let merged = merge_files(&[&base, &prod]).unwrap();
+
+    assert_eq!(
+        print(&merged.ast),
+        "\
+@ALN_UPDATE_SYSTEM {
+  @SEPARATE components {
+    game_engine: 'unity',
+    renderers: ['gl', 'vulkan']
+  },
+  @INTEROP {
+    enable_lan: 'on'
+  },
+  @RENDER_IN_FRAME {
+    mode: 'prod',
+    playable_platforms: ['linux'],
+    labels: { tier: 'prod' }
+  },
+  @EXEC_REGO_POLICY {
+    policy: 'base.rego',
+    features: ['c']
+  }
+}
+"
+    );
+
+    let plan = AlnUpdatePlan::from_files(&[&base, &prod]).unwrap();
+    assert_eq!(plan.components.renderers, ["gl", "vulkan"]);
+    assert_eq!(plan.interop.cross_link, "");
+    assert_eq!(plan.rego_exec.features, ["c"]);
+}
+
+#[test]
+fn replace_names_lists_inside_inline_objects_by_dotted_path() {
+    let base = "@APP {\n  limits: { cpu: 1, tags: ['a', 'b'], net: { ports: [1] } },\n  extra: { tags: ['x'] }\n}\n";
+    let overlay = "@APP replace=['limits.tags', 'limits.net.ports'] {\n  \
+                   limits: { tags: ['c'], net: { ports: [2] } },\n  extra: { tags: ['y'] }\n}\n";
+    let merged = merge(parse_str(base, None).unwrap(), parse_str(overlay, None).unwrap());
+
+    let expected = "@APP {\n  limits: { cpu: 1, tags: ['c'], net: { ports: [2] } },\n  extra: { tags: ['x', 'y'] }\n}\n";
+    assert_eq!(print(&merged), print(&parse_str(expected, None).unwrap()));
+}
+
+#[test]
+fn explains_where_each_value_came_from() {
+    let dir = TempDir::new("explains_where_each_value_came_from");
+    let base = dir.write("explain_base.aln", BASE);
+    let prod = dir.write("explain_prod.aln", PROD);
+    let merged = merge_files(&[&base, &prod]).unwrap();
+
+    let mode = merged
+        .provenance
+        .iter()
+        .find(|p| p.path == "ALN_UPDATE_SYSTEM.RENDER_IN_FRAME.mode")
+        .unwrap();
+    let actions: Vec<Action> = mode.history.iter().map(|(a, _)| *a).collect();
+    assert_eq!(actions, [Action::Set, Action::Replaced]);
+    assert!(mode.history[0].1.ends_with("explain_base.aln:11:11"), "{:?}", mode);
+    assert!(mode.history[1].1.ends_with("explain_prod.aln:9:11"), "{:?}", mode);
+
+    let explained = merged.explain();
+    assert!(explained.contains("ALN_UPDATE_SYSTEM.SEPARATE(components).renderers = ['gl', 'vulkan']\n  set at "));
+    assert!(explained.contains("  appended at "), "{explained}");
+    assert!(!explained.contains("cross_link"), "{explained}");
+}
+
+#[test]
+fn merge_sources_false_refuses_overlays() {
+    let dir = TempDir::new("merge_sources_false_refuses_overlays");
+    let base = dir.write(
+        "locked.aln",
+        &BASE.replace("mode: 'debug',", "mode: 'debug',\n    merge_sources: false,"),
+    );
+    let prod = dir.write("locked_prod.aln", PROD);
+
+    assert!(AlnUpdatePlan::from_files(&[&base]).is_ok());
+    match AlnUpdatePlan::from_files(&[&base, &prod]) {
+        Err(LoadAlnError::Schema(report)) => {
+            let text = report.to_string();
+            assert!(text.contains("merge_sources` is false, so overlays cannot be applied"), "{text}");
+            assert!(text.contains("locked.aln:12:20"), "{text}");
+        }
+        other => panic!("expected a schema error, got {other:?}"),
+    }
+}
+
+#[test]
+fn overlays_cannot_turn_merge_sources_back_on() {
+    let dir = TempDir::new("overlays_cannot_turn_merge_sources_back_on");
+    let base = dir.write(
+        "relock.aln",
+        &BASE.replace("mode: 'debug',", "mode: 'debug',\n    merge_sources: false,"),
+    );
+    let unlock = dir.write(
+        "unlock.aln",
+        "@ALN_UPDATE_SYSTEM {\n  @RENDER_IN_FRAME { merge_sources: true }\n}\n",
+    );
+    let prod = dir.write("relock_prod.aln", PROD);
+
+    for paths in [vec![base.as_str(), &unlock], vec![&base, &unlock, &prod]] {
+        match AlnUpdatePlan::from_files(&paths) {
+            Err(LoadAlnError::Schema(report)) => {
+                let text = report.to_string();
+                assert!(text.contains("merge_sources` is false, so overlays cannot be applied"), "{text}");
+                assert!(text.contains("relock.aln:12:20"), "{text}");
+                assert!(text.contains(&format!("refusing to merge {unlock}")), "{text}");
+            }
+            other => panic!("expected a schema error, got {other:?}"),
+        }
+    }
+
+    // A lock set by an overlay holds for the overlays after it.
+    let lock = dir.write(
+        "lock.aln",
+        "@ALN_UPDATE_SYSTEM {\n  @RENDER_IN_FRAME { merge_sources: false }\n}\n",
+    );
+    let plain = dir.write("plain.aln", BASE);
+    assert!(AlnUpdatePlan::from_files(&[&plain, &lock]).is_ok());
+    assert!(AlnUpdatePlan::from_files(&[&plain, &lock, &prod]).is_err());
+}
+
+#[test]
+fn merging_nothing_is_an_error() {
+    assert!(matches!(merge_files(&[]), Err(LoadAlnError::NoInput)));
+    assert!(matches!(AlnUpdatePlan::from_files(&[]), Err(LoadAlnError::NoInput)));
+}
+
+#[test]
+fn syntax_errors_point_into_the_overlay() {
+    let dir = TempDir::new("syntax_errors_point_into_the_overlay");
+    let base = dir.write("syntax_base.aln", BASE);
+    let bad = dir.write("syntax_bad.aln", "@INTEROP {\n  enable_lan 'on'\n}\n");
+    let merged = merge_files(&[&base, &bad]).unwrap();
+    let text = merged.report.to_string();
+    assert!(text.contains("syntax_bad.aln:2:14"), "{text}");
+}
@@ -0,0 +1,167 @@
+use aln_system_update_orchestrator::aln::migrate::{
+    rename_block, rename_key, set_version_text, split_block, MigrateError, Migration, Registry,
+};
+use aln_system_update_orchestrator::aln::parser::{parse_file, parse_str};
+use aln_system_update_orchestrator::aln::printer::print;
+use aln_system_update_orchestrator::aln::version::NEWEST_SUPPORTED;
+use aln_system_update_orchestrator::aln::{AlnUpdatePlan, PlanVersion};
+
+const PLAN: &str = "aln/system_update_integration_v1.7.aln";
+
+/// A made-up history: 1.0.0.5 renamed `@LINKS` to `@INTEROP`, 1.0.1.0 renamed
+/// `lan` to `enable_lan` and split the platforms out of `@RENDER_IN_FRAME`.
+fn history() -> Registry {
+    Registry::new()
+        .register(Migration {
+            to: PlanVersion::new(1, 0, 1, 0),
+            description: "move playable_platforms into @PLATFORMS",
+            apply: |root| {
+                split_block(root, &["RENDER_IN_FRAME"], "PLATFORMS", &["playable_platforms"]);
+                Ok(())
+            },
+        })
+        .register(Migration {
+            to: PlanVersion::new(1, 0, 0, 5),
+            description: "rename @LINKS to @INTEROP",
+            apply: |root| {
+                rename_block(root, &[], "LINKS", "INTEROP");
+                Ok(())
+            },
+        })
+        .register(Migration {
+            to: PlanVersion::new(1, 0, 1, 0),
+            description: "rename INTEROP.lan to enable_lan",
+            apply: |root| {
+                rename_key(root, &["INTEROP"], "lan", "enable_lan");
+                Ok(())
+            },
+        })
+}
+
+#[test]
+fn applies_the_steps_a_plan_needs_in_version_order() {
+    let src = "@ALN_UPDATE_SYSTEM version='1.0.0.1' {
+  @LINKS { lan: 'auto' }
+  @RENDER_IN_FRAME { mode: 'x', playable_platforms: ['web'] }
+}";
+    let mut file = parse_str(src, None).unwrap();
+    let migrated = history().migrate(&mut file, PlanVersion::new(1, 0, 1, 7)).unwrap();
+    assert_eq!(
+        migrated[0].steps,
+        [
+            "rename @LINKS to @INTEROP",
+            "move playable_platforms into @PLATFORMS",
+            "rename INTEROP.lan to enable_lan",
+        ]
+    );
+    assert_eq!(
+        print(&file),
+        "@ALN_UPDATE_SYSTEM version='1.0.1.7' {
+  @INTEROP {
+    enable_lan: 'auto'
+  },
+  @RENDER_IN_FRAME {
+    mode: 'x'
+  },
+  @PLATFORMS {
+    playable_platforms: ['web']
+  }
+}
+"
+    );
+
+    // A plan past the first step only gets the later ones.
+    let mut file = parse_str("@ALN_UPDATE_SYSTEM { version: '1.0.0.5', @INTEROP { lan: 'auto' } }", None).unwrap();
+    let migrated = history().migrate(&mut file, PlanVersion::new(1, 0, 1, 0)).unwrap();
+    assert_eq!(migrated[0].steps.len(), 2);
+    assert!(print(&file).contains("version: '1.0.1.0'"));
+
+    // Each update system is migrated from its own version.
+    let src = "@ALN_UPDATE_SYSTEM name='web' '1.0.0.1' { @LINKS { lan: 'auto' } }
+@ALN_UPDATE_SYSTEM name=api '1.0.1.0' { @INTEROP { enable_lan: 'off' } }";
+    let mut file = parse_str(src, None).unwrap();
+    let migrated = history().migrate(&mut file, PlanVersion::new(1, 0, 1, 0)).unwrap();
+    assert_eq!(migrated[0].system.as_deref(), Some("web"));
+    assert_eq!(migrated[0].steps.len(), 3);
+    assert_eq!(migrated[1].system.as_deref(), Some("api"));
+    assert!(!migrated[1].changed());
+    assert_eq!(
+        print(&file),
+        "@ALN_UPDATE_SYSTEM name=web '1.0.1.0' {
+  @INTEROP {
+    enable_lan: 'auto'
+  }
+}
+
+@ALN_UPDATE_SYSTEM name=api '1.0.1.0' {
+  @INTEROP {
+    enable_lan: 'off'
+  }
+}
+"
+    );
+}
+
+#[test]
+fn builtin_registry_upgrades_older_plans_to_the_newest_version() {
+    let versioned = |version: &str| {
+        let src = std::fs::read_to_string(PLAN).unwrap();
+        src.replace("@ALN_UPDATE_SYSTEM {", &format!("@ALN_UPDATE_SYSTEM {{\n  version: '{version}',"))
+    };
+    let src = versioned("1.0.0.0");
+    let mut file = parse_str(&src, None).unwrap();
+    let migrated = Registry::builtin().migrate(&mut file, NEWEST_SUPPORTED).unwrap();
+    assert!(migrated[0].changed());
+    assert_eq!(migrated[0].from, PlanVersion::new(1, 0, 0, 0));
+    assert_eq!(print(&file), print(&parse_str(&versioned("1.0.1.7"), None).unwrap()));
+    AlnUpdatePlan::from_str(&print(&file), None).unwrap();
+
+    // The shipped plan predates versions: it is 1.0.1.7 and says so afterwards.
+    let mut legacy = parse_file(PLAN).unwrap();
+    let migrated = Registry::builtin().migrate(&mut legacy, NEWEST_SUPPORTED).unwrap();
+    assert!(migrated[0].changed());
+    assert!(!migrated[0].declared);
+    assert_eq!(migrated[0].from, NEWEST_SUPPORTED);
+    assert_eq!(print(&legacy), print(&file));
+    assert!(!Registry::builtin().migrate(&mut legacy, NEWEST_SUPPORTED).unwrap()[0].changed());
+}
+
+#[test]
+fn refuses_plans_it_cannot_upgrade() {
+    let migrate = |src: &str| history().migrate(&mut parse_str(src, None).unwrap(), PlanVersion::new(1, 0, 1, 0));
+    assert_eq!(
+        migrate("@ALN_UPDATE_SYSTEM '1.0.1.7' { }").unwrap_err().to_string(),
+        "plan version 1.0.1.7 is newer than the target version 1.0.1.0"
+    );
+    assert_eq!(migrate("@OTHER { }").unwrap_err(), MigrateError::MissingRoot("ALN_UPDATE_SYSTEM"));
+    assert!(matches!(migrate("@ALN_UPDATE_SYSTEM version=1.7 { }").unwrap_err(), MigrateError::Version(_)));
+
+    let failing = Registry::new().register(Migration {
+        to: PlanVersion::new(1, 0, 1, 0),
+        description: "split @SEPARATE",
+        apply: |_| Err("components are ambiguous".to_string()),
+    });
+    let mut file = parse_str("@ALN_UPDATE_SYSTEM '1.0.0.0' { }", None).unwrap();
+    assert_eq!(
+        failing.migrate(&mut file, NEWEST_SUPPORTED).unwrap_err().to_string(),
+        "migration to 1.0.1.0 (split @SEPARATE) failed: components are ambiguous"
+    );
+}
+
+#[test]
+fn version_only_migrations_keep_the_source_as_written() {
+    let src = "// owned by infra\n@ALN_UPDATE_SYSTEM name=web  '1.0.0.2' {\n  version:'1.0.0.2', # old\n  @INTEROP {lan:'auto'}\n}\n\
+               @ALN_UPDATE_SYSTEM name=api version=\"1.0.1.7\" { @INTEROP {} }\n\
+               @ALN_UPDATE_SYSTEM name=cli {   @INTEROP {} }\n";
+    let file = parse_str(src, None).unwrap();
+    assert_eq!(
+        set_version_text(src, &file, NEWEST_SUPPORTED),
+        "// owned by infra\n@ALN_UPDATE_SYSTEM name=web  '1.0.1.7' {\n  version:'1.0.1.7', # old\n  @INTEROP {lan:'auto'}\n}\n\
+         @ALN_UPDATE_SYSTEM name=api version=\"1.0.1.7\" { @INTEROP {} }\n\
+         @ALN_UPDATE_SYSTEM name=cli {\n  version: '1.0.1.7',   @INTEROP {} }\n"
+    );
+
+    let mut migrated = parse_str(&set_version_text(src, &file, NEWEST_SUPPORTED), None).unwrap();
+    let again = Registry::builtin().migrate(&mut migrated, NEWEST_SUPPORTED).unwrap();
+    assert!(again.iter().all(|m| !m.changed()), "{again:?}");
+}
@@ -1,5 +1,8 @@
 use aln_system_update_orchestrator::aln::AlnUpdatePlan;
 
+mod common;
+use common::TempDir;
+
 #[test]
 fn parses_update_system_file() {
     let plan = AlnUpdatePlan::from_file("aln/system_update_integration_v1.7.aln")
@@ -7,3 +10,336 @@ fn parses_update_system_file() {
     assert_eq!(plan.version, "1.0.1.7");
     assert!(!plan.components.renderers.is_empty());
 }
+
+#[test]
+fn lexer_keeps_comments_as_leading_trivia() {
+    use aln_system_update_orchestrator::aln::lexer::{lex, TokenKind};
+
+    let src = "# plan header\n@A { /* inline */ key: 'v' // trailing\n}\n// eof";
+    let tokens = lex(src).expect("failed to lex");
+
+    assert_eq!(tokens[0].kind, TokenKind::At);
+    assert_eq!(tokens[0].leading[0].text, "# plan header");
+    assert!(matches!(tokens[3].kind, TokenKind::Identifier(_)));
+    assert_eq!(tokens[3].text, "key");
+    assert!(tokens[3].leading[0].is_block());
+    assert_eq!(tokens[6].kind, TokenKind::RBrace);
+    assert_eq!(tokens[6].leading[0].text, "// trailing");
+    let eof = tokens.last().unwrap();
+    assert_eq!(eof.kind, TokenKind::Eof);
+    assert_eq!(eof.leading[0].text, "// eof");
+}
+
+#[test]
+fn parser_keeps_comments_inside_values_and_arguments() {
+    use aln_system_update_orchestrator::aln::ast::BlockEntry;
+    use aln_system_update_orchestrator::aln::parser::parse_str;
+
+    let src = "@A x /* arg */ y=1 {
+  features: [
+    'a',
+    # keep for audit
+    'b' // experimental
+  ],
+  limits: { cpu: 2, /* per node */ mem: 4 },
+  key: # after the colon
+    1
+  [1, # in a bare list
+   2]
+}";
+    let file = parse_str(src, None).unwrap();
+    let block = file.items[0].block();
+    let args = &block.args.as_ref().unwrap().items;
+    assert_eq!(args[1].trivia.comments[0].comment.text, "/* arg */");
+
+    let inner = |entry: &BlockEntry| -> Vec<(Vec<usize>, String)> {
+        let trivia = match entry {
+            BlockEntry::KeyValue { trivia, .. } => trivia,
+            BlockEntry::List(list) => &list.trivia,
+            BlockEntry::NestedBlock(_) => unreachable!(),
+        };
+        trivia.comments.iter().map(|c| (c.at.clone(), c.comment.text.clone())).collect()
+    };
+    assert_eq!(
+        inner(&block.body[0]),
+        [(vec![1], "# keep for audit".to_string()), (vec![2], "// experimental".to_string())]
+    );
+    assert_eq!(inner(&block.body[1]), [(vec![1], "/* per node */".to_string())]);
+    match &block.body[2] {
+        BlockEntry::KeyValue { comments, trivia, .. } => {
+            assert_eq!(comments[0].text, "# after the colon");
+            assert!(trivia.is_empty());
+        }
+        other => panic!("expected a key, got {other:?}"),
+    }
+    assert_eq!(inner(&block.body[3]), [(vec![1], "# in a bare list".to_string())]);
+}
+
+#[test]
+fn parse_errors_render_file_line_and_caret() {
+    use aln_system_update_orchestrator::aln::parser::parse_str;
+
+    let src = "@INTEROP {\n  cross_link: 'x',\n  maintain_func true\n}\n";
+    let err = parse_str(src, Some("bad_colon.aln")).unwrap_err().to_string();
+
+    assert!(err.starts_with("error: expected `:` after key `maintain_func`, found `true`"));
+    assert!(err.contains("bad_colon.aln:3:17"), "{err}");
+    assert!(err.contains("3 |   maintain_func true\n  |                 ^^^^"), "{err}");
+}
+
+#[test]
+fn recovering_parse_reports_every_error_and_keeps_good_entries() {
+    use aln_system_update_orchestrator::aln::ast::{AlnItem, BlockEntry};
+    use aln_system_update_orchestrator::aln::parser::parse_file_recovering;
+
+    let dir = TempDir::new("aln_parser_tests_recovery");
+    let src = "@A {\n  one 1,\n  two: 2,\n  three: ]\n  @ { x: 1 }\n  four: 4\n}\n} @B { five: 5 }\n";
+    let parsed = parse_file_recovering(&dir.write("recovery.aln", src)).unwrap();
+
+    let messages: Vec<_> = parsed.report.diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        [
+            "expected `:` after key `one`, found integer `1`",
+            "expected value, found `]`",
+            "expected block name after `@`, found `{`",
+            "expected `@` block, found `}`",
+        ]
+    );
+
+    let blocks: Vec<_> = parsed.ast.items.iter().map(AlnItem::block).collect();
+    assert_eq!(blocks.len(), 2);
+    let keys: Vec<_> = blocks[0]
+        .body
+        .iter()
+        .filter_map(|e| match e {
+            BlockEntry::KeyValue { key, .. } => Some(key.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(keys, ["two", "four"]);
+    assert_eq!(blocks[1].name, "B");
+}
+
+#[test]
+fn lexer_decodes_escapes_multiline_and_raw_strings() {
+    use aln_system_update_orchestrator::aln::lexer::{lex, lex_recovering, LexError, TokenKind};
+    use aln_system_update_orchestrator::aln::span::Span;
+
+    let strings = |src: &str| -> Vec<String> {
+        lex(src)
+            .expect("failed to lex")
+            .into_iter()
+            .filter_map(|t| match t.kind {
+                TokenKind::StringLiteral(s) => Some(s.into_owned()),
+                _ => None,
+            })
+            .collect()
+    };
+
+    assert_eq!(
+        strings(r#"'it\'s' "say \"hi\"\n" '\u{1F680}\t\\'"#),
+        ["it's", "say \"hi\"\n", "\u{1F680}\t\\"]
+    );
+    assert_eq!(
+        strings("'''\n  allow {\n    input.x == 'y'\n  }\n'''"),
+        ["  allow {\n    input.x == 'y'\n  }\n"]
+    );
+    assert_eq!(
+        strings(r####"r'C:\tmp' r#"say "hi" \n"# r'''a\b'''"####),
+        [r"C:\tmp", r#"say "hi" \n"#, r"a\b"]
+    );
+    assert!(matches!(
+        lex(r"'bad \q'"),
+        Err(LexError::InvalidEscape { text, .. }) if text == r"\q"
+    ));
+    // An unclosed `\u{` stops at the end of its hex digits, not at a later `}`.
+    let (tokens, errors) = lex_recovering(r"'\u{41' x: '}'");
+    match &errors[..] {
+        [LexError::InvalidEscape { text, span }] => {
+            assert_eq!(text, r"\u{41");
+            assert_eq!(*span, Span::new(1, 6));
+        }
+        other => panic!("expected one invalid escape, got {other:?}"),
+    }
+    assert_eq!(tokens.len(), 5, "{tokens:?}");
+    assert!(matches!(
+        lex(r"'\u{0000041}'"),
+        Err(LexError::InvalidEscape { text, .. }) if text == r"\u{000004"
+    ));
+}
+
+#[test]
+fn lexer_borrows_from_the_source_and_interns_identifiers() {
+    use aln_system_update_orchestrator::aln::lexer::{Lexer, TokenKind};
+    use std::borrow::Cow;
+
+    let src = "@A mode=fast { mode: 'plain', note: 'it\\'s', other: fast, ~ }";
+    let mut lexer = Lexer::new(src);
+    let tokens: Vec<_> = lexer.by_ref().collect();
+    assert_eq!(tokens.last().map(|t| &t.kind), Some(&TokenKind::Eof));
+    assert!(lexer.next().is_none(), "the stream ends after `Eof`");
+    assert_eq!(lexer.errors().len(), 1);
+
+    for t in &tokens {
+        assert_eq!(t.text, &src[t.span.start..t.span.end]);
+    }
+    let strings: Vec<_> = tokens
+        .iter()
+        .filter_map(|t| match &t.kind {
+            TokenKind::StringLiteral(s) => Some(s),
+            _ => None,
+        })
+        .collect();
+    assert!(matches!(strings[0], Cow::Borrowed("plain")));
+    assert!(matches!(strings[1], Cow::Owned(s) if s == "it's"));
+
+    let symbol = |i: usize| match tokens[i].kind {
+        TokenKind::Identifier(symbol) => symbol,
+        ref other => panic!("token {i} is {other:?}"),
+    };
+    let interner = lexer.interner();
+    assert_eq!(interner.len(), 5);
+    assert_eq!(symbol(2), symbol(6));
+    assert_eq!(symbol(4), symbol(16));
+    assert_ne!(symbol(2), symbol(4));
+    assert_eq!(interner.resolve(symbol(4)), "fast");
+    assert_eq!(interner.get("other"), Some(symbol(14)));
+    assert_eq!(interner.get("plain"), None);
+}
+
+#[test]
+fn lexer_distinguishes_integers_and_floats() {
+    use aln_system_update_orchestrator::aln::lexer::{lex, LexError, TokenKind};
+
+    let kinds: Vec<_> = lex("8080 -5 +3 0.25 -1.5e-3 1e9 2E+2")
+        .expect("failed to lex")
+        .into_iter()
+        .map(|t| t.kind)
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            TokenKind::IntegerLiteral(8080),
+            TokenKind::IntegerLiteral(-5),
+            TokenKind::IntegerLiteral(3),
+            TokenKind::FloatLiteral(0.25),
+            TokenKind::FloatLiteral(-0.0015),
+            TokenKind::FloatLiteral(1e9),
+            TokenKind::FloatLiteral(200.0),
+            TokenKind::Eof,
+        ]
+    );
+
+    for (src, reason) in [
+        ("1.2.3", "expected digits with at most one '.'"),
+        ("10px", "expected digits with at most one '.'"),
+        ("1e", "expected digits after the exponent"),
+        ("99999999999999999999", "integer out of range"),
+    ] {
+        match lex(src) {
+            Err(LexError::InvalidNumber { text, reason: r, .. }) => {
+                assert_eq!((text.as_str(), r), (src, reason));
+            }
+            other => panic!("{src}: expected InvalidNumber, got {other:?}"),
+        }
+    }
+}
+
+#[test]
+fn parses_inline_objects_in_values_and_arrays() {
+    use aln_system_update_orchestrator::aln::ast::{BlockEntry, Value};
+    use aln_system_update_orchestrator::aln::parser::parse_str;
+
+    let src = "@DEPLOY {\n  limits: { cpu: 2, mem: '4Gi' },\n  \
+               targets: [{ name: web, 'k8s.io/zone': { primary: true } }, { name: lan }]\n}\n";
+    let file = parse_str(src, None).expect("failed to parse");
+
+    let block = file.items[0].block();
+    let values: Vec<&Value> = block
+        .body
+        .iter()
+        .filter_map(|e| match e {
+            BlockEntry::KeyValue { value, .. } => Some(value),
+            _ => None,
+        })
+        .collect();
+
+    match values[0] {
+        Value::Object(fields) => {
+            assert_eq!(fields[0].0, "cpu");
+            assert!(matches!(fields[0].1, Value::Integer(2)));
+            assert!(matches!(&fields[1].1, Value::Str(s) if s == "4Gi"));
+        }
+        other => panic!("expected object, got {other:?}"),
+    }
+    match values[1] {
+        Value::Array(items) => {
+            assert_eq!(items.len(), 2);
+            let Value::Object(first) = &items[0] else { panic!("expected object") };
+            assert_eq!(first[1].0, "k8s.io/zone");
+            assert!(matches!(&first[1].1, Value::Object(zone) if zone[0].0 == "primary"));
+        }
+        other => panic!("expected array, got {other:?}"),
+    }
+}
+
+#[test]
+fn parses_typed_block_arguments() {
+    use aln_system_update_orchestrator::aln::ast::{BlockEntry, Value};
+    use aln_system_update_orchestrator::aln::parser::parse_str;
+
+    let src = "@PIPELINE {\n  @SEPARATE components 'x' 3 { }\n  \
+               @STEP deploy after=build timeout=30 { }\n}\n";
+    let file = parse_str(src, None).expect("failed to parse");
+
+    let pipeline = file.items[0].block();
+    let blocks: Vec<_> = pipeline
+        .body
+        .iter()
+        .filter_map(|e| match e {
+            BlockEntry::NestedBlock(b) => Some(b),
+            _ => None,
+        })
+        .collect();
+
+    let separate = blocks[0].args.as_ref().expect("missing args");
+    let positional: Vec<_> = separate.positional().collect();
+    assert!(matches!(positional[..], [Value::Str(a), Value::Str(b), Value::Integer(3)] if a == "components" && b == "x"));
+
+    let step = blocks[1].args.as_ref().expect("missing args");
+    assert!(matches!(step.positional().next(), Some(Value::Str(s)) if s == "deploy"));
+    assert!(matches!(step.named("after"), Some(Value::Str(s)) if s == "build"));
+    assert!(matches!(step.named("timeout"), Some(Value::Integer(30))));
+    assert_eq!(&src[step.items[2].span.start..step.items[2].span.end], "timeout=30");
+}
+
+#[test]
+fn parses_strings_and_readers_with_virtual_names() {
+    use aln_system_update_orchestrator::aln::parser::{parse_reader, parse_str};
+
+    let src = "@INTEROP {\n  maintain_func true\n}\n";
+    let err = parse_str(src, None).unwrap_err().to_string();
+    assert!(err.contains("--> <string>:2:17"), "{err}");
+
+    let err = parse_reader(src.as_bytes(), Some("kafka://plans/42")).unwrap_err().to_string();
+    assert!(err.contains("--> kafka://plans/42:2:17"), "{err}");
+
+    let file = parse_reader("@A { x: 1 }".as_bytes(), None).unwrap();
+    assert_eq!(file.items.len(), 1);
+}
+
+#[test]
+fn loads_plans_from_memory() {
+    let src = std::fs::read_to_string("aln/system_update_integration_v1.7.aln").unwrap();
+    let plan = AlnUpdatePlan::from_str(&src, Some("upload.aln")).unwrap();
+    assert!(!plan.components.renderers.is_empty());
+
+    let plan = AlnUpdatePlan::from_reader(src.as_bytes(), None).unwrap();
+    assert!(plan.interop.maintain_func);
+
+    let with_include = src.replace("@INTEROP", "@INCLUDE '/etc/passwd'\n  @INTEROP");
+    let err = AlnUpdatePlan::from_str(&with_include, Some("upload.aln")).unwrap_err().to_string();
+    assert!(err.contains("error: @INCLUDE is only supported in plans loaded from files"), "{err}");
+    assert!(err.contains("--> upload.aln:"), "{err}");
+}
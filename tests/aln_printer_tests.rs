@@ -0,0 +1,102 @@
+use aln_system_update_orchestrator::aln::lexer::{lex, TokenKind};
+use aln_system_update_orchestrator::aln::parser::{parse_file, parse_str};
+use aln_system_update_orchestrator::aln::printer::{print, quote};
+
+#[test]
+fn shipped_plan_is_canonical() {
+    let path = "aln/system_update_integration_v1.7.aln";
+    let src = std::fs::read_to_string(path).unwrap();
+    assert_eq!(print(&parse_file(path).expect("failed to parse")), src);
+}
+
+#[test]
+fn prints_messy_plan_in_canonical_style() {
+    let src = "# head\n@A   components  retries=3{x:1 y : [ 1,2 ,3 ] // note\n\
+               z:{a:true} @B{} q: 1.0}";
+    let printed = print(&parse_str(src, None).expect("failed to parse"));
+    assert_eq!(
+        printed,
+        "# head\n@A components retries=3 {\n  x: 1,\n  y: [1, 2, 3],\n  // note\n  \
+         z: { a: true },\n  @B {},\n  q: 1.0\n}\n"
+    );
+
+    assert_eq!(print(&parse_str(&printed, None).unwrap()), printed);
+}
+
+#[test]
+fn keeps_comments_inside_values_and_arguments() {
+    let src = "@EXEC_REGO_POLICY components /* rollout */ retries=3 {
+  features: ['repo_tracking', # keep for audit
+    'commit_automation', // experimental
+  ],
+  limits: { cpu: 2, /* per node */ mem: '4Gi' },
+  [80, # plain http
+   443]
+}
+";
+    let printed = print(&parse_str(src, None).unwrap());
+    assert_eq!(
+        printed,
+        "/* rollout */
+@EXEC_REGO_POLICY components retries=3 {
+  features: [
+    'repo_tracking',
+    # keep for audit
+    'commit_automation'
+    // experimental
+  ],
+  limits: {
+    cpu: 2,
+    /* per node */
+    mem: '4Gi'
+  },
+  [
+    80,
+    # plain http
+    443
+  ]
+}
+"
+    );
+    assert_eq!(print(&parse_str(&printed, None).unwrap()), printed);
+}
+
+#[test]
+fn breaks_long_arrays_over_lines() {
+    let items: Vec<String> = (0..8).map(|i| format!("'platform_number_{}'", i)).collect();
+    let src = format!("@R {{ platforms: [{}] }}", items.join(", "));
+    let printed = print(&parse_str(&src, None).unwrap());
+    assert!(printed.starts_with("@R {\n  platforms: [\n    'platform_number_0',\n"), "{printed}");
+    assert!(printed.ends_with("    'platform_number_7'\n  ]\n}\n"), "{printed}");
+}
+
+#[test]
+fn quoted_strings_lex_back_to_the_same_value() {
+    for s in [
+        "plain",
+        "it's",
+        "C:\\tmp",
+        "tab\there",
+        "line\r\nfeed",
+        "package x\nallow { input.user == 'admin' }\n",
+        "ends with quote'\n",
+        "'''triple'''\n",
+        "back\\slash\nand 'quote'",
+        "bell\u{7}",
+    ] {
+        let quoted = quote(s);
+        let tokens = lex(&quoted).unwrap_or_else(|e| panic!("{quoted}: {e}"));
+        assert_eq!(tokens[0].kind, TokenKind::StringLiteral(s.into()), "{quoted}");
+        assert_eq!(tokens.len(), 2, "{quoted}");
+    }
+}
+
+#[test]
+fn keeps_raw_strings_raw() {
+    let src = "@APP {\n  hook: r'echo ${HOME}',\n  steps: [r\"cd ${DIR}\"],\n  path: 'C:\\\\tmp ${x}'\n}\n";
+    let printed = print(&parse_str(src, None).unwrap());
+    assert!(printed.contains("hook: r'echo ${HOME}'"), "{printed}");
+    assert!(printed.contains("r'cd ${DIR}'") || printed.contains("r\"cd ${DIR}\""), "{printed}");
+    assert!(printed.contains("path: 'C:\\\\tmp ${x}'"), "{printed}");
+    assert_eq!(print(&parse_str(&printed, None).unwrap()), printed);
+}
@@ -0,0 +1,69 @@
+use aln_system_update_orchestrator::aln::ast::{AlnFile, Value};
+use aln_system_update_orchestrator::aln::parser::{parse_file, parse_str};
+use aln_system_update_orchestrator::aln::query::{select, Node, Query, QueryError};
+
+fn plan() -> AlnFile {
+    parse_file("aln/system_update_integration_v1.7.aln").unwrap()
+}
+
+fn paths(file: &AlnFile, query: &str) -> Vec<String> {
+    select(file, query).unwrap().into_iter().map(|s| s.path).collect()
+}
+
+#[test]
+fn selects_values_from_the_shipped_plan() {
+    let file = plan();
+    let selected = select(&file, "ALN_UPDATE_SYSTEM.RENDER_IN_FRAME.playable_platforms[1]").unwrap();
+    assert_eq!(selected.len(), 1);
+    assert_eq!(selected[0].path, "ALN_UPDATE_SYSTEM.RENDER_IN_FRAME.playable_platforms[1]");
+    assert!(matches!(selected[0].node, Node::Value(Value::Str(s)) if s == "web_browser"));
+
+    assert_eq!(paths(&file, "*.features"), ["ALN_UPDATE_SYSTEM.EXEC_REGO_POLICY.features"]);
+    assert_eq!(
+        paths(&file, "INTEROP.*"),
+        [
+            "ALN_UPDATE_SYSTEM.INTEROP.cross_link",
+            "ALN_UPDATE_SYSTEM.INTEROP.maintain_func",
+            "ALN_UPDATE_SYSTEM.INTEROP.enable_lan",
+        ]
+    );
+    assert_eq!(paths(&file, "features[*]").len(), 3);
+    assert!(matches!(select(&file, "SEPARATE").unwrap()[0].node, Node::Block(b) if b.name == "SEPARATE"));
+    assert!(paths(&file, "INTEROP.policy").is_empty());
+}
+
+#[test]
+fn indexes_pick_repeated_blocks_and_nested_values() {
+    let file = parse_str(
+        "@PIPE {
+  @STAGE build { limits: { cpu: 2 } }
+  @STAGE test { limits: { cpu: 4 }, meta: { 'a.b': [[1, 2], [3]] } }
+}",
+        None,
+    )
+    .unwrap();
+    assert_eq!(paths(&file, "STAGE[1].limits.cpu"), ["PIPE.STAGE[1].limits.cpu"]);
+    assert_eq!(paths(&file, "STAGE[*].limits.cpu"), ["PIPE.STAGE[0].limits.cpu", "PIPE.STAGE[1].limits.cpu"]);
+    assert_eq!(paths(&file, "meta.'a.b'[0][1]"), ["PIPE.STAGE.meta.'a.b'[0][1]"]);
+    assert_eq!(paths(&file, "meta.\"a.b\"[*][*]").len(), 3);
+    assert!(paths(&file, "STAGE[2]").is_empty());
+    assert!(paths(&file, "'a.b'").is_empty());
+}
+
+#[test]
+fn queries_round_trip_and_report_the_bad_column() {
+    for query in ["A.b[0]", "*.x[*]", "'a.b'.c", "\"it's\""] {
+        assert_eq!(query.parse::<Query>().unwrap().to_string(), query);
+    }
+
+    let column = |query: &str| query.parse::<Query>().unwrap_err().column;
+    assert_eq!(column(""), 1);
+    assert_eq!(column("a..b"), 3);
+    assert_eq!(column("a[x]"), 3);
+    assert_eq!(column("a[1"), 2);
+    assert_eq!(column("'ab"), 1);
+    assert_eq!(column("a]"), 2);
+
+    let err: QueryError = "a.".parse::<Query>().unwrap_err();
+    assert_eq!(err.to_string(), "invalid query `a.` at column 3: expected a name, `*` or a quoted name");
+}
@@ -0,0 +1,143 @@
+use aln_system_update_orchestrator::aln::ast::*;
+use aln_system_update_orchestrator::aln::include::INCLUDE;
+use aln_system_update_orchestrator::aln::parser::parse_str;
+use aln_system_update_orchestrator::aln::printer::{is_identifier, print};
+use aln_system_update_orchestrator::aln::span::Span;
+use proptest::prelude::*;
+
+fn ident() -> impl Strategy<Value = String> {
+    "[A-Za-z_][A-Za-z0-9_.]{0,10}".prop_filter("keyword", |s| is_identifier(s) && s != INCLUDE)
+}
+
+fn comment() -> impl Strategy<Value = Comment> {
+    let text = "[ -~]{0,20}";
+    prop_oneof![
+        text.prop_map(|s| format!("# {}", s).trim_end().to_string()),
+        text.prop_map(|s| format!("// {}", s).trim_end().to_string()),
+        text.prop_filter("ends the comment", |s| !s.contains("*/")).prop_map(|s| format!("/* {} */", s)),
+    ]
+    .prop_map(Comment::from)
+}
+
+fn comments() -> impl Strategy<Value = Vec<Comment>> {
+    prop::collection::vec(comment(), 0..2)
+}
+
+fn value() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        any::<String>().prop_map(Value::Str),
+        ident().prop_map(Value::Str),
+        any::<bool>().prop_map(Value::Bool),
+        any::<i64>().prop_map(Value::Integer),
+        any::<f64>().prop_filter("finite", |n| n.is_finite()).prop_map(Value::Float),
+    ];
+    leaf.prop_recursive(3, 24, 4, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..4).prop_map(Value::Array),
+            prop::collection::vec((any::<String>(), inner), 0..4).prop_map(|mut fields| {
+                // Duplicate keys are a parse error.
+                let mut seen = std::collections::HashSet::new();
+                fields.retain(|(k, _)| seen.insert(k.clone()));
+                Value::Object(fields)
+            }),
+        ]
+    })
+}
+
+fn args() -> impl Strategy<Value = Option<BlockArgs>> {
+    let arg = prop_oneof![
+        // A positional `{` would start the block body.
+        value()
+            .prop_filter("object", |v| !matches!(v, Value::Object(_)))
+            .prop_map(|value| BlockArg { name: None, value, trivia: ValueTrivia::default(), span: Span::default() }),
+        (ident(), value()).prop_map(|(name, value)| BlockArg {
+            name: Some(name),
+            value,
+            trivia: ValueTrivia::default(),
+            span: Span::default(),
+        }),
+    ];
+    prop::option::of(
+        prop::collection::vec(arg, 1..4).prop_map(|items| BlockArgs { items, span: Span::default() }),
+    )
+}
+
+fn block(depth: u32) -> BoxedStrategy<Block> {
+    let key_value = (ident(), value(), comments()).prop_map(|(key, value, comments)| BlockEntry::KeyValue {
+        key,
+        value,
+        comments,
+        trivia: ValueTrivia::default(),
+        key_span: Span::default(),
+        value_span: Span::default(),
+    });
+    let entry = if depth == 0 {
+        key_value.boxed()
+    } else {
+        prop_oneof![3 => key_value, 1 => block(depth - 1).prop_map(BlockEntry::NestedBlock)].boxed()
+    };
+    (ident(), args(), prop::collection::vec(entry, 0..5), comments(), comments())
+        .prop_map(|(name, args, body, comments, closing_comments)| Block {
+            name,
+            args,
+            body,
+            comments,
+            closing_comments,
+            span: Span::default(),
+            name_span: Span::default(),
+            origin: None,
+        })
+        .boxed()
+}
+
+fn file() -> impl Strategy<Value = AlnFile> {
+    (prop::collection::vec(block(2).prop_map(AlnItem::from_block), 0..4), comments())
+        .prop_map(|(items, trailing_comments)| AlnFile { items, trailing_comments })
+}
+
+/// Everything but spans, which differ between a generated and a parsed AST.
+fn shape(file: &AlnFile) -> serde_json::Value {
+    serde_json::to_value(file).unwrap()
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn printed_files_parse_back_to_the_same_ast(file in file()) {
+        let printed = print(&file);
+        let parsed = parse_str(&printed, None).map_err(|e| TestCaseError::fail(format!("{e}\n{printed}")))?;
+        prop_assert_eq!(shape(&parsed), shape(&file), "{}", printed);
+        prop_assert_eq!(print(&parsed), printed);
+    }
+
+    #[test]
+    fn printed_values_parse_back_to_the_same_value(value in value()) {
+        let printed = print(&AlnFile {
+            items: vec![AlnItem::Block(Block {
+                name: "V".to_string(),
+                args: None,
+                body: vec![BlockEntry::KeyValue {
+                    key: "v".to_string(),
+                    value: value.clone(),
+                    comments: Vec::new(),
+                    trivia: ValueTrivia::default(),
+                    key_span: Span::default(),
+                    value_span: Span::default(),
+                }],
+                comments: Vec::new(),
+                closing_comments: Vec::new(),
+                span: Span::default(),
+                name_span: Span::default(),
+                origin: None,
+            })],
+            trailing_comments: Vec::new(),
+        });
+        let parsed = parse_str(&printed, None).map_err(|e| TestCaseError::fail(format!("{e}\n{printed}")))?;
+        let block = parsed.items[0].block();
+        match &block.body[..] {
+            [BlockEntry::KeyValue { value: parsed, .. }] => prop_assert_eq!(parsed, &value, "{}", printed),
+            other => prop_assert!(false, "{:?}\n{}", other, printed),
+        }
+    }
+}
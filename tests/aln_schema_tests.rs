@@ -0,0 +1,162 @@
+use aln_system_update_orchestrator::aln::ast::Value;
+use aln_system_update_orchestrator::aln::parser::parse_str;
+use aln_system_update_orchestrator::aln::schema::{did_you_mean, edit_distance, BlockSchema, Field, SchemaError, Type};
+use aln_system_update_orchestrator::aln::{AlnUpdatePlan, LoadAlnError, Strictness};
+
+#[test]
+fn plan_schema_reports_typed_errors_with_positions() {
+    let src = "@ALN_UPDATE_SYSTEM {\n  @SEPARATE components {\n    renderers: ['a', 3]\n  },\n  \
+               @INTEROP {\n    maintain_func: 'yes'\n  },\n  @RENDER_IN_FRAME {}\n}\n";
+    let err = AlnUpdatePlan::from_str(src, Some("plan.aln")).unwrap_err().to_string();
+
+    assert!(err.contains("error: `renderers[1]` expects string, got integer"), "{err}");
+    assert!(err.contains("error: `maintain_func` expects bool, got string"), "{err}");
+    assert!(err.contains("plan.aln:6:20"), "{err}");
+    assert!(err.contains("error: missing required block @EXEC_REGO_POLICY in @ALN_UPDATE_SYSTEM"), "{err}");
+}
+
+#[test]
+fn plan_schema_fills_in_defaults() {
+    let src = "@ALN_UPDATE_SYSTEM {\n  @SEPARATE components {}\n  @INTEROP {}\n  \
+               @RENDER_IN_FRAME { mode: 'web' }\n  @EXEC_REGO_POLICY { policy: 'p.rego' }\n}\n";
+    let plan = AlnUpdatePlan::from_str(src, None).expect("failed to load plan");
+
+    assert!(plan.interop.maintain_func);
+    assert!(plan.render.merge_sources);
+    assert_eq!(plan.render.mode, "web");
+    assert!(plan.rego_exec.features.is_empty());
+    assert_eq!(plan.rego_exec.policy, "p.rego");
+}
+
+#[test]
+fn custom_schema_checks_enums_required_keys_and_nested_blocks() {
+    let schema = BlockSchema::new("STEP")
+        .field(Field::required("image", Type::Str))
+        .field(Field::optional("strategy", Type::Enum(vec!["rolling", "recreate"])))
+        .field(Field::optional("ratio", Type::Number).default(Value::Float(1.0)))
+        .required_block(BlockSchema::new("HOOK").field(Field::required("run", Type::Str)));
+
+    let src = "@STEP { strategy: 'blue_green', ratio: 2, @HOOK { run: 'x' }, @HOOK { run: 'y' } }";
+    let file = parse_str(src, None).unwrap();
+    let step = file.items[0].block();
+    let errors = schema.validate(step).unwrap_err();
+    let messages: Vec<_> = errors.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        messages,
+        [
+            "`strategy` expects one of 'rolling', 'recreate', got string",
+            "duplicate block @HOOK",
+            "missing required key `image` in @STEP",
+        ]
+    );
+    assert!(matches!(errors[1], SchemaError::DuplicateBlock { .. }));
+
+    let file = parse_str("@STEP { image: 'web', @HOOK { run: 'x' } }", None).unwrap();
+    let step = file.items[0].block();
+    let validated = schema.validate(step).expect("should validate");
+    assert_eq!(validated.get("ratio"), Some(&Value::Float(1.0)));
+    assert_eq!(validated.block("HOOK").unwrap().get("run"), Some(&Value::Str("x".into())));
+}
+
+const TYPOS: &str = "\
+@ALN_UPDATE_SYSTEM version='1.0.1.7' {
+  @SEPARATE components {},
+  @INTEROP {
+    maintian_func: false,
+    policy: 'p.rego'
+  },
+  @SEPERATE {},
+  @RENDER_IN_FRAME {},
+  @EXEC_REGO_POLICY {
+    @INTEROP {}
+  }
+}
+";
+
+#[test]
+fn lenient_plans_warn_about_unknown_keys_and_blocks() {
+    let (plan, warnings) = AlnUpdatePlan::load_str(TYPOS, Some("typos.aln"), Strictness::Lenient).unwrap();
+    assert!(plan.interop.maintain_func, "the misspelt key falls back to the default");
+    assert_eq!(warnings.diagnostics.len(), 4);
+    assert!(!warnings.has_errors());
+
+    let text = warnings.to_string();
+    assert!(text.contains("warning: unknown key `maintian_func` in @INTEROP\n --> typos.aln:4:5"), "{text}");
+    assert!(text.contains("= note: did you mean `maintain_func`?"), "{text}");
+    assert!(text.contains("warning: unknown key `policy` in @INTEROP"), "{text}");
+    assert!(text.contains("= note: `policy` belongs in @EXEC_REGO_POLICY"), "{text}");
+    assert!(text.contains("warning: unknown block @SEPERATE in @ALN_UPDATE_SYSTEM"), "{text}");
+    assert!(text.contains("= note: did you mean `SEPARATE`?"), "{text}");
+    assert!(text.contains("= note: @INTEROP belongs in @ALN_UPDATE_SYSTEM"), "{text}");
+}
+
+#[test]
+fn strict_plans_reject_unknown_keys_and_blocks() {
+    match AlnUpdatePlan::load_str(TYPOS, Some("typos.aln"), Strictness::Strict) {
+        Err(LoadAlnError::Schema(report)) => {
+            let messages: Vec<_> = report.diagnostics.iter().map(|d| format!("{}: {}", d.severity, d.message)).collect();
+            assert_eq!(
+                messages,
+                [
+                    "error: unknown key `maintian_func` in @INTEROP",
+                    "error: unknown key `policy` in @INTEROP",
+                    "error: unknown block @SEPERATE in @ALN_UPDATE_SYSTEM",
+                    "error: unknown block @INTEROP in @EXEC_REGO_POLICY",
+                ]
+            );
+        }
+        other => panic!("expected a schema error, got {other:?}"),
+    }
+
+    let src = std::fs::read_to_string("aln/system_update_integration_v1.7.aln").unwrap();
+    let (_, warnings) = AlnUpdatePlan::load_str(&src, None, Strictness::Strict).unwrap();
+    // Only the version it predates is worth a warning.
+    let [d] = &warnings.diagnostics[..] else { panic!("{warnings}") };
+    assert!(d.message.contains("does not declare a version"), "{}", d.message);
+}
+
+#[test]
+fn unknown_top_level_blocks_are_reported_too() {
+    let src = "\
+@VARS { tier: 'dev' }
+@ALN_UPDATE_SYSTEM '1.0.1.7' {
+  @SEPARATE components {},
+  @INTEROP {},
+  @RENDER_IN_FRAME {},
+  @EXEC_REGO_POLICY {}
+}
+@RENDER_IN_FRAM {}
+@INTEROP {}
+@METAA {}
+@CUSTOM {}
+";
+    let (_, warnings) = AlnUpdatePlan::load_str(src, Some("extra.aln"), Strictness::Lenient).unwrap();
+    assert_eq!(warnings.diagnostics.len(), 4, "{warnings}");
+    assert!(!warnings.has_errors());
+
+    let err = AlnUpdatePlan::load_str(src, Some("extra.aln"), Strictness::Strict).unwrap_err();
+    let LoadAlnError::Schema(report) = err else { panic!("expected a schema error, got {err:?}") };
+    let messages: Vec<_> = report.diagnostics.iter().map(|d| (d.message.as_str(), d.notes.join(""))).collect();
+    assert_eq!(
+        messages,
+        [
+            ("unknown top-level block @RENDER_IN_FRAM", "did you mean `RENDER_IN_FRAME`?".to_string()),
+            ("unknown top-level block @INTEROP", "@INTEROP belongs in @ALN_UPDATE_SYSTEM".to_string()),
+            ("unknown top-level block @METAA", "did you mean `META`?".to_string()),
+            ("unknown top-level block @CUSTOM", String::new()),
+        ]
+    );
+}
+
+#[test]
+fn suggestions_tolerate_small_typos_only() {
+    assert_eq!(edit_distance("maintian_func", "maintain_func"), 1);
+    assert_eq!(edit_distance("mode", "mode"), 0);
+    assert_eq!(edit_distance("", "abc"), 3);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+
+    let keys = ["mode", "merge_sources", "playable_platforms"];
+    assert_eq!(did_you_mean("merge_source", keys).as_deref(), Some("did you mean `merge_sources`?"));
+    assert_eq!(did_you_mean("MODE", keys).as_deref(), Some("did you mean `mode`?"));
+    assert_eq!(did_you_mean("colour", keys), None);
+}
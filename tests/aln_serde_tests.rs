@@ -0,0 +1,112 @@
+use aln_system_update_orchestrator::aln::{self, AlnUpdatePlan};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Pipeline {
+    #[serde(rename = "PIPELINE")]
+    pipeline: Stages,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Stages {
+    name: String,
+    retries: u8,
+    #[serde(default)]
+    dry_run: bool,
+    labels: BTreeMap<String, String>,
+    #[serde(rename = "STAGE")]
+    stages: Vec<Stage>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Stage {
+    #[serde(rename = "@args")]
+    args: Vec<String>,
+    #[serde(rename = "@after", skip_serializing_if = "Option::is_none")]
+    after: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout: Option<f64>,
+    mode: Mode,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Mode {
+    Rolling,
+    Canary { percent: u8 },
+}
+
+const PIPELINE: &str = "\
+@PIPELINE {
+  name: 'nightly',
+  retries: 2,
+  labels: { team: 'infra' },
+  @STAGE build { mode: 'rolling' },
+  @STAGE ship after=build { timeout: 1.5, mode: { canary: { percent: 10 } } }
+}
+";
+
+#[test]
+fn deserializes_blocks_args_and_repeated_blocks() {
+    let p: Pipeline = aln::from_str(PIPELINE).unwrap();
+    let stages = &p.pipeline.stages;
+
+    assert_eq!(p.pipeline.name, "nightly");
+    assert!(!p.pipeline.dry_run);
+    assert_eq!(p.pipeline.labels["team"], "infra");
+    assert_eq!(stages.len(), 2);
+    assert_eq!(stages[1].args, ["ship"]);
+    assert_eq!(stages[1].after.as_deref(), Some("build"));
+    assert_eq!(stages[1].mode, Mode::Canary { percent: 10 });
+}
+
+#[test]
+fn serializer_round_trips_through_the_deserializer() {
+    let p: Pipeline = aln::from_str(PIPELINE).unwrap();
+    let src = aln::to_string(&p).unwrap();
+
+    assert!(src.contains("@STAGE ship after=build {"), "{src}");
+    assert!(src.contains("labels: { team: 'infra' }"), "{src}");
+    assert_eq!(aln::from_str::<Pipeline>(&src).unwrap(), p);
+}
+
+#[test]
+fn single_block_fills_a_vec() {
+    let p: Pipeline =
+        aln::from_str("@PIPELINE { name: 'x', retries: 0, labels: {}, @STAGE only { mode: 'rolling' } }").unwrap();
+    assert_eq!(p.pipeline.stages.len(), 1);
+}
+
+#[test]
+fn errors_carry_the_value_path() {
+    let src = PIPELINE.replace("percent: 10", "percent: 300");
+    let err = aln::from_str::<Pipeline>(&src).unwrap_err().to_string();
+    assert!(err.starts_with("PIPELINE.STAGE[1].mode.canary.percent: "), "{err}");
+
+    let err = aln::from_str::<Pipeline>("@PIPELINE { name: 'x' }").unwrap_err().to_string();
+    assert_eq!(err, "PIPELINE: missing field `retries`");
+}
+
+#[test]
+fn syntax_errors_surface_as_load_errors() {
+    let err = aln::from_str::<Pipeline>("@PIPELINE { name: }").unwrap_err();
+    assert!(matches!(err, aln::Error::Load(_)), "{err}");
+}
+
+#[test]
+fn update_plan_maps_through_serde() {
+    let plan = AlnUpdatePlan::from_file("aln/system_update_integration_v1.7.aln").unwrap();
+    let src = aln::printer::print(&plan.to_file());
+
+    assert!(src.starts_with("@ALN_UPDATE_SYSTEM {\n  version: '1.0.1.7',\n  @SEPARATE {"), "{src}");
+    assert!(src.contains("@EXEC_REGO_POLICY {"), "{src}");
+    assert_eq!(aln::from_str::<BTreeMap<String, AlnUpdatePlan>>(&src).unwrap()["ALN_UPDATE_SYSTEM"].render.mode, plan.render.mode);
+
+    let json = serde_json::to_value(&plan).unwrap();
+    let mut keys: Vec<_> = json.as_object().unwrap().keys().map(String::as_str).collect();
+    keys.sort_unstable();
+    assert_eq!(keys, ["components", "interop", "rego_exec", "render", "version"]);
+    let back: AlnUpdatePlan = serde_json::from_value(json).unwrap();
+    assert_eq!(back.components.renderers, plan.components.renderers);
+}
@@ -0,0 +1,105 @@
+use aln_system_update_orchestrator::aln::diff::diff_plan_sets;
+use aln_system_update_orchestrator::aln::{AlnPlanSet, AlnUpdatePlan, LoadAlnError, PlanVersion, Strictness};
+
+mod common;
+use common::TempDir;
+
+const PLAN: &str = "aln/system_update_integration_v1.7.aln";
+
+/// The shipped plan declared twice, as `web` and `api`, under a `@META` block.
+fn two_systems() -> String {
+    let plan = std::fs::read_to_string(PLAN).unwrap();
+    let api = plan
+        .replace("@ALN_UPDATE_SYSTEM {", "@ALN_UPDATE_SYSTEM name='api' version='1.0.1.6' {")
+        .replace("'web_browser', ", "");
+    format!(
+        "@META {{ owner: 'platform-team' }}\n{}\n{}",
+        plan.replace("@ALN_UPDATE_SYSTEM {", "@ALN_UPDATE_SYSTEM name='web' {"),
+        api
+    )
+}
+
+fn errors(result: Result<AlnPlanSet, LoadAlnError>) -> String {
+    match result {
+        Err(LoadAlnError::Schema(report)) => report.to_string(),
+        other => panic!("expected a schema error, got {other:?}"),
+    }
+}
+
+#[test]
+fn loads_each_named_system_and_the_metadata() {
+    let set = AlnPlanSet::from_str(&two_systems(), None).unwrap();
+    let names: Vec<_> = set.systems.iter().map(|s| s.name.as_deref()).collect();
+    assert_eq!(names, [Some("web"), Some("api")]);
+    assert_eq!(set.get("web").unwrap().version, PlanVersion::new(1, 0, 1, 7));
+    assert_eq!(set.get("api").unwrap().version, PlanVersion::new(1, 0, 1, 6));
+    assert_eq!(set.get("api").unwrap().render.playable_platforms, ["any_ai_chat", "all_systems_with_k8s"]);
+    assert!(set.get("desktop").is_none());
+
+    let meta = set.meta.unwrap();
+    assert_eq!(meta.owner, "platform-team");
+    assert_eq!(meta.description, "");
+
+    // A single plan needs no name and no metadata.
+    let set = AlnPlanSet::from_file(PLAN).unwrap();
+    assert!(set.meta.is_none());
+    assert_eq!(set.systems.len(), 1);
+    assert_eq!(set.systems[0].name, None);
+
+    let err = AlnUpdatePlan::from_str(&two_systems(), None).unwrap_err().to_string();
+    assert!(err.contains("this file declares 2 update systems"), "{err}");
+    assert!(err.contains("load it as an `AlnPlanSet`"), "{err}");
+}
+
+#[test]
+fn reports_every_problem_with_systems_and_metadata() {
+    let plan = std::fs::read_to_string(PLAN).unwrap();
+    let text = errors(AlnPlanSet::from_str(&format!("{plan}\n{plan}"), None));
+    assert!(text.contains("@ALN_UPDATE_SYSTEM needs a name when a file declares several"), "{text}");
+    assert!(text.contains("add one like `@ALN_UPDATE_SYSTEM name='web' { ... }`"), "{text}");
+
+    let src = two_systems().replace("name='api'", "name='web'");
+    let text = errors(AlnPlanSet::from_str(&src, None));
+    assert!(text.contains("update system `web` is declared twice"), "{text}");
+
+    let src = format!("@META {{ }}\n{}", two_systems().replace("'1.0.1.6'", "'9.0.0.0'"));
+    let text = errors(AlnPlanSet::from_str(&src, None));
+    assert!(text.contains("@META may only appear once"), "{text}");
+    assert!(text.contains("9.0.0.0"), "{text}");
+
+    let src = two_systems().replace("owner:", "ownr:");
+    let (_, warnings) = AlnPlanSet::load_str(&src, None, Strictness::Lenient).unwrap();
+    assert!(warnings.to_string().contains("ownr"), "{warnings}");
+    assert!(AlnPlanSet::load_str(&src, None, Strictness::Strict).is_err());
+}
+
+#[test]
+fn overlays_merge_system_by_system() {
+    let dir = TempDir::new("overlays_merge_system_by_system");
+    let base = dir.write("base.aln", &two_systems());
+    let overlay = dir.write(
+        "overlay.aln",
+        "@META { description: 'web and api rollout' }\n\
+         @ALN_UPDATE_SYSTEM name='api' {\n  @RENDER_IN_FRAME { mode: 'headless' }\n}\n",
+    );
+    let set = AlnPlanSet::from_files(&[&base, &overlay]).unwrap();
+    assert_eq!(set.get("api").unwrap().render.mode, "headless");
+    assert_eq!(set.get("web").unwrap().render.mode, "chat_window_drop_with_history");
+    let meta = set.meta.unwrap();
+    assert_eq!((meta.owner.as_str(), meta.description.as_str()), ("platform-team", "web and api rollout"));
+}
+
+#[test]
+fn plan_set_diffs_match_systems_by_name() {
+    let old = AlnPlanSet::from_str(&two_systems(), None).unwrap();
+    let new = two_systems()
+        .replace("platform-team", "release-team")
+        .replace("version='1.0.1.6'", "version='1.0.1.7'");
+    let new = AlnPlanSet::from_str(&new, None).unwrap();
+    assert_eq!(
+        diff_plan_sets(&old, &new).to_string(),
+        "META.owner: changed from 'platform-team' to 'release-team'
+ALN_UPDATE_SYSTEM(name=api).version: changed from '1.0.1.6' to '1.0.1.7'
+"
+    );
+}
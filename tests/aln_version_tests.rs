@@ -0,0 +1,98 @@
+use aln_system_update_orchestrator::aln::version::{
+    PlanVersion, VersionError, NEWEST_SUPPORTED, OLDEST_SUPPORTED, UNDECLARED,
+};
+use aln_system_update_orchestrator::aln::migrate::Registry;
+use aln_system_update_orchestrator::aln::{AlnUpdatePlan, LoadAlnError, Strictness};
+
+const SECTIONS: &str = "@SEPARATE components {}\n  @INTEROP {}\n  @RENDER_IN_FRAME {}\n  @EXEC_REGO_POLICY {}";
+
+fn load(root: &str) -> Result<AlnUpdatePlan, LoadAlnError> {
+    let src = format!("{}\n  {}\n}}\n", root, SECTIONS);
+    AlnUpdatePlan::from_str(&src, Some("plan.aln"))
+}
+
+#[test]
+fn versions_parse_compare_and_print() {
+    let v: PlanVersion = "1.0.1.7".parse().unwrap();
+    assert_eq!(v, PlanVersion::new(1, 0, 1, 7));
+    assert_eq!(v.to_string(), "1.0.1.7");
+    assert!(PlanVersion::new(1, 0, 1, 10) > v);
+    assert!(PlanVersion::new(1, 0, 0, 99) < v);
+
+    for bad in ["1.0.1", "1.0.1.7.0", "1.0.x.7", "1..1.7", "+1.0.1.7", ""] {
+        assert_eq!(bad.parse::<PlanVersion>(), Err(VersionError::Invalid(bad.to_string())), "{bad}");
+    }
+    assert!(OLDEST_SUPPORTED.check_supported().is_ok());
+    assert!(NEWEST_SUPPORTED.check_supported().is_ok());
+}
+
+#[test]
+fn supported_range_follows_the_compatibility_rule() {
+    assert_eq!(OLDEST_SUPPORTED, PlanVersion::new(NEWEST_SUPPORTED.major, NEWEST_SUPPORTED.minor, 0, 0));
+    // Plans older than a built-in step cannot be read as they are.
+    for step in Registry::builtin().migrations() {
+        assert!(step.to <= OLDEST_SUPPORTED, "{} needs migrating but is accepted", step.to);
+    }
+}
+
+#[test]
+fn version_is_read_from_a_key_or_an_argument() {
+    for root in [
+        "@ALN_UPDATE_SYSTEM {\n  version: '1.0.0.3',",
+        "@ALN_UPDATE_SYSTEM version='1.0.0.3' {",
+        "@ALN_UPDATE_SYSTEM '1.0.0.3' {",
+        "@ALN_UPDATE_SYSTEM '1.0.0.3' {\n  version: '1.0.0.3',",
+        "@ALN_UPDATE_SYSTEM '1.0.0.3' canary {",
+    ] {
+        let plan = load(root).unwrap_or_else(|e| panic!("{root}: {e}"));
+        assert_eq!(plan.version, PlanVersion::new(1, 0, 0, 3), "{root}");
+    }
+}
+
+#[test]
+fn plans_newer_than_supported_are_rejected() {
+    let err = load("@ALN_UPDATE_SYSTEM {\n  version: '1.0.2.0',\n  @NEW_FEATURE {},").unwrap_err();
+    assert!(matches!(err, LoadAlnError::Schema(_)), "{err:?}");
+    let text = err.to_string();
+    assert!(
+        text.contains("error: plan version 1.0.2.0 is newer than this orchestrator supports (up to 1.0.1.7)"),
+        "{text}"
+    );
+    assert!(text.contains("--> plan.aln:2:12"), "{text}");
+
+    let text = load("@ALN_UPDATE_SYSTEM '0.9.0.0' {").unwrap_err().to_string();
+    assert!(text.contains("error: plan version 0.9.0.0 is older than the oldest supported version 1.0.0.0"), "{text}");
+}
+
+#[test]
+fn plans_without_a_version_are_read_as_1_0_1_7() {
+    let src = format!("@ALN_UPDATE_SYSTEM {{\n  {}\n}}\n", SECTIONS);
+    let (plan, warnings) = AlnUpdatePlan::load_str(&src, Some("plan.aln"), Strictness::Strict).unwrap();
+    assert_eq!(plan.version, UNDECLARED);
+    assert_eq!(plan.version, "1.0.1.7");
+    let text = warnings.to_string();
+    assert!(
+        text.contains("warning: @ALN_UPDATE_SYSTEM does not declare a version; reading it as 1.0.1.7"),
+        "{text}"
+    );
+    assert!(text.contains("add `version: '1.0.1.7'`"), "{text}");
+    assert!(text.contains("--> plan.aln:1:2"), "{text}");
+
+    // The warning is kept when the plan fails to load for another reason.
+    let text = load("@ALN_UPDATE_SYSTEM {\n  @UNKNOWN {},").unwrap().version.to_string();
+    assert_eq!(text, "1.0.1.7");
+    let text = AlnUpdatePlan::load_str("@ALN_UPDATE_SYSTEM { @SEPARATE components {} }", None, Strictness::Lenient)
+        .unwrap_err()
+        .to_string();
+    assert!(text.contains("does not declare a version"), "{text}");
+}
+
+#[test]
+fn malformed_and_conflicting_versions_are_errors() {
+    let text = load("@ALN_UPDATE_SYSTEM {\n  version: 1.7,").unwrap_err().to_string();
+    assert!(text.contains("error: invalid plan version `1.7`"), "{text}");
+
+    let text = load("@ALN_UPDATE_SYSTEM version='1.0.1.7' {\n  version: '1.0.0.0',").unwrap_err().to_string();
+    assert!(text.contains("error: @ALN_UPDATE_SYSTEM declares conflicting versions"), "{text}");
+    assert!(text.contains("--> plan.aln:2:12"), "{text}");
+}
@@ -0,0 +1,117 @@
+use aln_system_update_orchestrator::aln::ast::{Block, BlockEntry, Comment, Value};
+use aln_system_update_orchestrator::aln::parser::{parse_file, parse_str};
+use aln_system_update_orchestrator::aln::printer::print;
+use aln_system_update_orchestrator::aln::visit::{self, BlockPath, Visit, VisitMut};
+
+const SRC: &str = "# head
+@A x=1 {
+  k: [1, { n: 2 }],
+  // inner
+  @B { m: 'v' }
+}
+// tail
+";
+
+/// Every node in visiting order, with the path it was visited at.
+#[derive(Default)]
+struct Trace(Vec<String>);
+
+impl<'ast> Visit<'ast> for Trace {
+    fn visit_block(&mut self, block: &'ast Block, path: &mut BlockPath) {
+        self.0.push(format!("[{}] @{}", path, block.name));
+        visit::walk_block(self, block, path);
+    }
+
+    fn visit_entry(&mut self, entry: &'ast BlockEntry, path: &mut BlockPath) {
+        if let BlockEntry::KeyValue { key, .. } = entry {
+            self.0.push(format!("[{}] {}", path, key));
+        }
+        visit::walk_entry(self, entry, path);
+    }
+
+    fn visit_value(&mut self, value: &'ast Value, path: &mut BlockPath) {
+        self.0.push(format!("[{}] {}", path, value));
+        visit::walk_value(self, value, path);
+    }
+
+    fn visit_comment(&mut self, comment: &'ast Comment, path: &mut BlockPath) {
+        self.0.push(format!("[{}] {}", path, comment.text));
+    }
+}
+
+#[test]
+fn visits_every_node_with_its_enclosing_blocks() {
+    let file = parse_str(SRC, None).unwrap();
+    let mut trace = Trace::default();
+    trace.visit_file(&file);
+    assert_eq!(
+        trace.0,
+        [
+            "[] @A",
+            "[] # head",
+            "[A] 1",
+            "[A] k",
+            "[A] [1, { n: 2 }]",
+            "[A] 1",
+            "[A] { n: 2 }",
+            "[A] 2",
+            "[A] @B",
+            "[A] // inner",
+            "[A.B] m",
+            "[A.B] 'v'",
+            "[] // tail",
+        ]
+    );
+}
+
+#[test]
+fn paths_identify_plan_sections() {
+    struct Policies<'ast>(Vec<(String, &'ast str)>);
+
+    impl<'ast> Visit<'ast> for Policies<'ast> {
+        fn visit_entry(&mut self, entry: &'ast BlockEntry, path: &mut BlockPath) {
+            match entry {
+                BlockEntry::KeyValue { key, value: Value::Str(s), .. }
+                    if path.is(&["ALN_UPDATE_SYSTEM", "EXEC_REGO_POLICY"]) =>
+                {
+                    self.0.push((path.key(key), s));
+                }
+                _ => visit::walk_entry(self, entry, path),
+            }
+        }
+    }
+
+    let file = parse_file("aln/system_update_integration_v1.7.aln").unwrap();
+    let mut policies = Policies(Vec::new());
+    policies.visit_file(&file);
+    assert_eq!(
+        policies.0,
+        [("ALN_UPDATE_SYSTEM.EXEC_REGO_POLICY.policy".to_string(), "system_update_policy_v1.7.rego")]
+    );
+}
+
+#[test]
+fn mutable_visitors_rewrite_in_place() {
+    /// Renames `@B` to `@C` and doubles integers everywhere inside it.
+    struct Rewrite;
+
+    impl VisitMut for Rewrite {
+        fn visit_block_mut(&mut self, block: &mut Block, path: &mut BlockPath) {
+            if block.name == "B" {
+                block.name = "C".to_string();
+            }
+            visit::walk_block_mut(self, block, path);
+        }
+
+        fn visit_value_mut(&mut self, value: &mut Value, path: &mut BlockPath) {
+            match value {
+                Value::Integer(n) if path.innermost() == Some("C") => *n *= 2,
+                _ => visit::walk_value_mut(self, value, path),
+            }
+        }
+    }
+
+    let mut file = parse_str("@A { n: 1, @B { n: 1, list: [1, { x: 2 }] } }", None).unwrap();
+    Rewrite.visit_file_mut(&mut file);
+    assert_eq!(print(&file), "@A {\n  n: 1,\n  @C {\n    n: 2,\n    list: [2, { x: 4 }]\n  }\n}\n");
+}
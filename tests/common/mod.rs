@@ -0,0 +1,36 @@
+//! Helpers shared by the integration tests.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A fresh directory under the system temp directory, removed on drop. Each
+/// one is unique to the process and the call, so tests running in parallel,
+/// or the same test run twice at once, never write the same file.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// `test` only makes the directory easier to find while debugging.
+    pub fn new(test: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("{}-{}-{}", test, std::process::id(), n));
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    /// Write `src` to `name`, a path relative to the directory, and return
+    /// the full path.
+    pub fn write(&self, name: &str, src: &str) -> String {
+        let path = self.0.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, src).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}